@@ -0,0 +1,41 @@
+/* Generated by cbindgen from src/ffi.rs, don't edit */
+
+#ifndef CURRENT_LOCATION_H
+#define CURRENT_LOCATION_H
+
+#include <stddef.h>
+#include <sys/types.h>
+
+// Negative return values of C API, see [`ErrorCode`]
+typedef enum ClError {
+  CL_ERROR_WINDOW_MANAGER = -1,
+  CL_ERROR_PROCESS_TREE = -2,
+  CL_ERROR_PROCESS_NOT_FOUND = -3,
+  CL_ERROR_CONFIG = -4,
+  CL_ERROR_REGISTRY = -5,
+  CL_ERROR_CORRUPT_ENTRY = -6,
+  CL_ERROR_OTHER = -7,
+  // Null pointer or a string that isn't valid UTF-8 is passed
+  CL_ERROR_INVALID_ARGUMENT = -8,
+} ClError;
+
+// Writes location of process `pid` and its descendants, like `current-location get
+// --active-pid`, to `buf` as a NUL terminated string. Location of active window is written if
+// `pid` is 0 and the library is built with `wm` feature. Fallback location is written if no known
+// process is found.
+//
+// Returns length of location without NUL like `snprintf`, it's truncated if the length is `len`
+// or more, or a negative [`ClError`]
+//
+// # Safety
+// `buf` is null or points to at least `len` writable bytes
+int cl_get_location(pid_t pid, char *buf, size_t len);
+
+// Writes `location` of process `pid` named `name` to Location Registry, like `current-location
+// write`. Returns 0 or a negative [`ClError`]
+//
+// # Safety
+// `name` and `location` are null or point to NUL terminated strings
+int cl_write(const char *name, pid_t pid, const char *location);
+
+#endif  /* CURRENT_LOCATION_H */
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "tokio")]
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, anyhow};
+use serde::Deserialize;
+#[cfg(feature = "tokio")]
+use tokio::signal::unix::{SignalKind, signal};
+#[cfg(feature = "tokio")]
+use tokio::task::AbortHandle;
+
+#[cfg(feature = "registry")]
+use crate::error::ErrorCode;
+use crate::pattern::ProcMatcher;
+use crate::process::{PROCESS_TREE_CAPACITY, ScanOptions};
+#[cfg(feature = "registry")]
+use crate::registry::{Registry, RegistryBackend, RegistryFormat};
+use crate::resolver::Resolver;
+use crate::{LocationData, Selection};
+
+const KNOWN_PROCS: &[&str] = &["zsh", "nvim"];
+const PROJECT_MARKERS: &[&str] = &[".git", "Cargo.toml", "package.json", "flake.nix"];
+const BFS_HEAP_CAPACITY: usize = 1024;
+const WM_TIMEOUT: u64 = 1000;
+
+/// Settings loaded from `$XDG_CONFIG_HOME/current-location/config.toml`, every field is optional
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Names of processes that write their location to Location Registry, globs, regexes and
+    /// command lines are supported, see [`ProcMatcher`]
+    pub known_procs: Vec<String>,
+    /// Priorities of entries of `known_procs`, 0 by default. Process of the highest priority is
+    /// selected, the deepest one among processes of the same priority
+    pub priorities: HashMap<String, i32>,
+    /// Which of processes of the same priority is selected, `deepest` by default
+    pub selection: Selection,
+    /// How location of entries of `known_procs` is resolved, Location Registry by default
+    pub resolvers: HashMap<String, Resolver>,
+    /// Processes which subtrees are skipped, e.g. container runtimes. Same patterns as
+    /// `known_procs`
+    pub excluded_procs: Vec<String>,
+    /// Directory of Location Registry
+    pub registry_path: PathBuf,
+    /// Kind of Location Registry, `fs` by default
+    #[cfg(feature = "registry")]
+    pub registry_backend: RegistryBackend,
+    /// Format of written registry entries, `json` by default
+    #[cfg(feature = "registry")]
+    pub registry_format: RegistryFormat,
+    /// Number of previous locations kept per process in addition to the current one, none by
+    /// default. `fs` backend only, `sqlite` keeps all of them
+    pub history_size: usize,
+    /// Seconds after which registry entries are treated as absent and removed, never by default
+    pub entry_ttl: Option<u64>,
+    /// Location used when no known process is found, home directory by default
+    pub fallback_location: Option<PathBuf>,
+    /// Working directory of selected process is used if it hasn't written its location or it's
+    /// stale, e.g. shell without hooks in ssh session. `fallback_location` otherwise. Enabled by
+    /// default
+    pub cwd_fallback: bool,
+    /// Files or directories marking root of a project, the nearest directory containing any of
+    /// them is `project_root` of location
+    pub project_markers: Vec<String>,
+    /// Read arguments of every process, e.g. for a [`crate::SelectionStrategy`] inspecting them.
+    /// They are read anyway if some pattern is prefixed with `cmdline:`
+    pub read_cmdline: bool,
+    /// Read executables of every process, they are read anyway if some pattern is prefixed with
+    /// `exe:`
+    pub read_exe: bool,
+    /// Read start times of every process, see [`ScanOptions::read_start_time`]. They are read
+    /// anyway with `newest` selection. Without them a single file is read per process, but
+    /// entries of dead processes whose pids are reused may be selected while searching
+    pub read_start_time: bool,
+    /// Known processes of other users are skipped unless it's set, e.g. root shell opened with
+    /// `sudo -i` in a terminal, since they write to registries of their owners. Implies
+    /// [`Self::scan_other_users`]
+    pub other_users: bool,
+    /// Read processes of other users too, e.g. of system daemons. Otherwise they are skipped along
+    /// with their descendants, e.g. own processes started with `sudo -u`
+    pub scan_other_users: bool,
+    /// Read only processes below process of active window rather than every one, it's a lot
+    /// faster on machines with many processes. Commands of `flatpak-spawn` aren't found
+    pub scan_subtree: bool,
+    /// Initial capacity of process tree, it's better to be larger than number of processes
+    pub process_tree_capacity: usize,
+    /// Initial capacity of process tree walker queue
+    pub bfs_heap_capacity: usize,
+    /// How deep below process of active window known processes are searched, process of active
+    /// window is at depth 0. Unlimited by default
+    pub max_depth: Option<u64>,
+    /// Milliseconds to wait for window manager to report active window, e.g. while compositor is
+    /// reloading, 1000 by default
+    pub wm_timeout: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            known_procs: KNOWN_PROCS.iter().map(|name| name.to_string()).collect(),
+            priorities: HashMap::new(),
+            selection: Selection::default(),
+            resolvers: HashMap::new(),
+            excluded_procs: vec![],
+            registry_path: default_registry_path(),
+            #[cfg(feature = "registry")]
+            registry_backend: RegistryBackend::default(),
+            #[cfg(feature = "registry")]
+            registry_format: RegistryFormat::default(),
+            history_size: 0,
+            entry_ttl: None,
+            fallback_location: None,
+            cwd_fallback: true,
+            project_markers: PROJECT_MARKERS
+                .iter()
+                .map(|marker| marker.to_string())
+                .collect(),
+            read_cmdline: false,
+            read_exe: false,
+            read_start_time: false,
+            other_users: false,
+            scan_other_users: false,
+            scan_subtree: false,
+            process_tree_capacity: PROCESS_TREE_CAPACITY,
+            bfs_heap_capacity: BFS_HEAP_CAPACITY,
+            max_depth: None,
+            wm_timeout: WM_TIMEOUT,
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME` with `~/.config` as default
+pub fn xdg_config_home() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".config")))
+}
+
+/// `$XDG_RUNTIME_DIR/current-location` which is per-user and cleaned at logout,
+/// `/tmp/current-location-<euid>` if it's not set
+fn default_registry_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(runtime_dir) => PathBuf::from(runtime_dir).join("current-location"),
+        None => PathBuf::from(format!("/tmp/current-location-{}", nix::unistd::geteuid())),
+    }
+}
+
+impl Config {
+    /// `$XDG_CONFIG_HOME/current-location/config.toml` with `~/.config` as `XDG_CONFIG_HOME` default
+    pub fn default_path() -> Option<PathBuf> {
+        Some(
+            xdg_config_home()?
+                .join("current-location")
+                .join("config.toml"),
+        )
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = fs::read_to_string(path).context("read config file")?;
+        toml::from_str(&content).context("parse config file")
+    }
+
+    /// Loads config from [`Config::default_path`], defaults are used if there is no config file
+    pub fn load_default() -> anyhow::Result<Self> {
+        let Some(path) = Self::default_path() else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).context("parse config file"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(anyhow!(err).context("read config file")),
+        }
+    }
+
+    /// Loads config from `path` or from [`Config::default_path`] if it's not given
+    pub fn load_from(path: Option<&Path>) -> anyhow::Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => Self::load_default(),
+        }
+    }
+
+    #[cfg(feature = "registry")]
+    pub fn registry(&self) -> anyhow::Result<Registry> {
+        Registry::open(
+            self.registry_backend,
+            self.registry_format,
+            &self.registry_path,
+        )
+        .map(|registry| registry.with_history(self.history_size))
+        .context("open location registry")
+        .context(ErrorCode::Registry)
+    }
+
+    /// Command lines and executables are read only if they are asked for or some pattern needs them
+    pub fn scan_options(&self) -> ScanOptions {
+        let mut patterns = self.known_procs.iter().chain(&self.excluded_procs);
+        let read_cmdline = self.read_cmdline
+            || patterns
+                .clone()
+                .any(|entry| ProcMatcher::needs_cmdline(entry));
+        let read_exe = self.read_exe || patterns.any(|entry| ProcMatcher::needs_exe(entry));
+        ScanOptions {
+            capacity: self.process_tree_capacity,
+            read_cmdline,
+            read_exe,
+            read_start_time: self.read_start_time || self.selection == Selection::Newest,
+            subtree: self.scan_subtree,
+            uid: (!self.scan_other_users && !self.other_users)
+                .then(|| nix::unistd::geteuid().as_raw()),
+        }
+    }
+
+    pub fn fallback(&self) -> LocationData {
+        match &self.fallback_location {
+            Some(location) => LocationData::fallback_to(location.clone()),
+            None => LocationData::fallback(),
+        }
+    }
+}
+
+/// Keeps config up to date by reloading it on SIGHUP, for long-running processes
+///
+/// Config stays the same if reloading fails. Signal is handled by a background task which is
+/// stopped on drop
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct ConfigReloader {
+    config: Arc<RwLock<Arc<Config>>>,
+    listener: AbortHandle,
+}
+
+#[cfg(feature = "tokio")]
+impl ConfigReloader {
+    /// `overrides` are applied after every load, e.g. command line flags
+    pub fn start(
+        path: Option<PathBuf>,
+        overrides: impl Fn(&mut Config) + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        let load = move || {
+            let mut config = Config::load_from(path.as_deref())?;
+            overrides(&mut config);
+            anyhow::Ok(config)
+        };
+
+        let config = Arc::new(RwLock::new(Arc::new(load()?)));
+        let mut hangups = signal(SignalKind::hangup()).context("listen to SIGHUP")?;
+
+        let listener = tokio::spawn({
+            let config = config.clone();
+            async move {
+                while hangups.recv().await.is_some() {
+                    match load() {
+                        Ok(new_config) => {
+                            *config.write().expect("lock is not poisoned") = Arc::new(new_config);
+                        }
+                        Err(err) => tracing::error!("failed to reload config: {err:#}"),
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            config,
+            listener: listener.abort_handle(),
+        })
+    }
+
+    /// Snapshot of current config, it's not affected by later reloads
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().expect("lock is not poisoned").clone()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Drop for ConfigReloader {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::resolver::Resolver;
+
+    #[test]
+    fn partial() {
+        let config: Config = toml::from_str(r#"known_procs = ["fish", "hx"]"#).unwrap();
+
+        assert_eq!(config.known_procs, ["fish", "hx"]);
+        assert_eq!(config.registry_path, Config::default().registry_path);
+    }
+
+    #[test]
+    fn other_users() {
+        let mut config = Config::default();
+        assert!(config.scan_options().uid.is_some());
+
+        // known processes of other users can't be found without reading them
+        config.other_users = true;
+        assert_eq!(config.scan_options().uid, None);
+    }
+
+    #[test]
+    fn priorities() {
+        let config: Config = toml::from_str(
+            r#"
+            known_procs = ["zsh", "nvim"]
+            priorities = { nvim = 2, zsh = 1 }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.priorities["nvim"], 2);
+        assert_eq!(config.priorities["zsh"], 1);
+    }
+
+    #[test]
+    fn resolvers() {
+        let config: Config = toml::from_str(
+            r#"
+            known_procs = ["zsh", "hx", "lf"]
+            resolvers = { hx = "cwd", lf = { command = "lf-location $1" } }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.resolvers["hx"], Resolver::Cwd);
+        assert_eq!(
+            config.resolvers["lf"],
+            Resolver::Command("lf-location $1".to_owned())
+        );
+        assert!(!config.resolvers.contains_key("zsh"));
+    }
+
+    #[test]
+    fn unknown_field() {
+        let config = toml::from_str::<Config>(r#"known_process = ["fish"]"#);
+        assert!(config.is_err());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn reload() {
+        use std::time::Duration;
+
+        use nix::sys::signal::{Signal, raise};
+
+        use crate::config::ConfigReloader;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, r#"known_procs = ["zsh"]"#).unwrap();
+        let reloader = ConfigReloader::start(Some(path.clone()), |config| {
+            config.known_procs.push("fish".to_owned());
+        })
+        .unwrap();
+        assert_eq!(reloader.config().known_procs, ["zsh", "fish"]);
+
+        std::fs::write(&path, r#"known_procs = ["nvim"]"#).unwrap();
+        raise(Signal::SIGHUP).unwrap();
+        // signal is handled by a background task
+        for _ in 0..100 {
+            if reloader.config().known_procs[0] == "nvim" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(reloader.config().known_procs, ["nvim", "fish"]);
+    }
+}
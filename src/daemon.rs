@@ -0,0 +1,315 @@
+use std::mem;
+#[cfg(feature = "proc-connector")]
+use std::os::fd::AsFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(feature = "proc-connector")]
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::sync::Notify;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::time::MissedTickBehavior;
+
+use crate::config::{Config, ConfigReloader};
+use crate::error::ErrorCode;
+#[cfg(feature = "proc-connector")]
+use crate::process::connector::{ProcConnector, ProcessEvent};
+use crate::process::{Pid, ProcessCache};
+use crate::registry::Registry;
+use crate::registry::socket::{CLIENT_TIMEOUT, bind, ensure_same_user, send_request};
+use crate::registry::watch::RegistryWatcher;
+use crate::wm::ActiveWindowProvider;
+use crate::wm::hyprland::ActiveWindowTracker;
+use crate::{LocationData, read_location, request_active_pid, search_in};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum Request {
+    /// Location of active window or of the given process
+    Get { active_pid: Option<Pid> },
+}
+
+/// Socket of [`run`] next to registry directory, `<registry_path>.daemon.sock`
+pub fn socket_path(registry_path: &Path) -> PathBuf {
+    let mut path = registry_path.as_os_str().to_owned();
+    path.push(".daemon.sock");
+    path.into()
+}
+
+/// Asks daemon listening on `socket` for location, `None` if it isn't running
+pub fn query(socket: &Path, active_pid: Option<Pid>) -> anyhow::Result<Option<LocationData>> {
+    send_request(socket, "daemon", &Request::Get { active_pid })
+}
+
+#[cfg(feature = "proc-connector")]
+type ProcessEvents = Vec<ProcessEvent>;
+#[cfg(not(feature = "proc-connector"))]
+type ProcessEvents = ();
+
+/// Events of kernel proc connector read on another thread, the thread is stopped and joined on
+/// drop
+struct ProcessEventsListener {
+    receiver: UnboundedReceiver<ProcessEvents>,
+    /// Dropping it wakes the thread up, see [`ProcConnector::next_events_until`]
+    #[cfg(feature = "proc-connector")]
+    stop: Option<std::io::PipeWriter>,
+    #[cfg(feature = "proc-connector")]
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "proc-connector")]
+impl Drop for ProcessEventsListener {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            tracing::error!("proc events thread panicked");
+        }
+    }
+}
+
+/// `None` if proc connector is unavailable, e.g. without `CAP_NET_ADMIN`
+#[cfg(feature = "proc-connector")]
+fn subscribe_process_events() -> Option<ProcessEventsListener> {
+    let connector = ProcConnector::new()
+        .inspect_err(|err| tracing::info!("rescan /proc on changes, no proc events: {err:#}"))
+        .ok()?;
+    let (stop_reader, stop) = std::io::pipe()
+        .inspect_err(|err| tracing::info!("rescan /proc on changes, no stop pipe: {err}"))
+        .ok()?;
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    let thread = std::thread::spawn(move || {
+        loop {
+            match connector.next_events_until(stop_reader.as_fd()) {
+                Ok(Some(events)) => {
+                    if sender.send(events).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    tracing::error!("stop listening to proc events: {err:#}");
+                    break;
+                }
+            }
+        }
+    });
+    Some(ProcessEventsListener {
+        receiver,
+        stop: Some(stop),
+        thread: Some(thread),
+    })
+}
+
+#[cfg(not(feature = "proc-connector"))]
+fn subscribe_process_events() -> Option<ProcessEventsListener> {
+    None
+}
+
+/// Never completes without proc events, they are dropped once the connector fails
+async fn next_process_events(listener: &mut Option<ProcessEventsListener>) -> ProcessEvents {
+    if let Some(events) = listener.as_mut() {
+        if let Some(events) = events.receiver.recv().await {
+            return events;
+        }
+        *listener = None;
+    }
+    std::future::pending().await
+}
+
+/// State kept between queries
+struct Daemon<'a, P> {
+    provider: &'a P,
+    reloader: &'a ConfigReloader,
+    /// Config `registry` and `processes` are set up with
+    config: Arc<Config>,
+    registry: Registry,
+    /// Refreshed on changes, so queries don't wait for `/proc` scan
+    processes: ProcessCache,
+}
+
+impl<P: ActiveWindowProvider> Daemon<'_, P> {
+    async fn refresh(&mut self) -> anyhow::Result<()> {
+        // cache is left empty if refresh fails, so the next one starts from scratch
+        let mut processes = mem::take(&mut self.processes);
+        self.processes =
+            tokio::task::spawn_blocking(move || processes.refresh().map(|()| processes))
+                .await
+                .context("join failed")?
+                .context(ErrorCode::ProcessTree)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "proc-connector")]
+    fn apply(&mut self, events: ProcessEvents) -> anyhow::Result<()> {
+        self.processes.apply(&events)
+    }
+
+    #[cfg(not(feature = "proc-connector"))]
+    fn apply(&mut self, (): ProcessEvents) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Picks up config reloaded on SIGHUP, process tree is scanned again only if scan options
+    /// have changed
+    fn reload(&mut self) -> anyhow::Result<()> {
+        let config = self.reloader.config();
+        if Arc::ptr_eq(&config, &self.config) {
+            return Ok(());
+        }
+
+        self.registry = config.registry()?;
+        self.processes.set_options(config.scan_options());
+        self.config = config;
+        tracing::info!("apply reloaded config");
+        Ok(())
+    }
+
+    async fn get(&mut self, active_pid: Option<Pid>) -> anyhow::Result<LocationData> {
+        if let Err(err) = self.reload() {
+            tracing::error!("failed to apply reloaded config: {err:#}");
+        }
+        let config = self.config.clone();
+
+        let active_pid = match active_pid {
+            Some(active_pid) => Some(active_pid),
+            None => request_active_pid(self.provider, &config).await?,
+        };
+        let Some(active_pid) = active_pid else {
+            return Ok(read_location(None, &self.registry, &config)?);
+        };
+
+        // window is opened after the last refresh
+        if !self.processes.processes().contains_key(&active_pid) {
+            self.refresh().await?;
+        }
+        let result = search_in(self.processes.processes(), active_pid, &config)?;
+        Ok(read_location(
+            result.map(|result| result.resolved),
+            &self.registry,
+            &config,
+        )?)
+    }
+
+    async fn handle_connection(
+        &mut self,
+        mut stream: tokio::net::UnixStream,
+    ) -> anyhow::Result<()> {
+        ensure_same_user(&stream).context("check client")?;
+
+        // slow client must not hold up the daemon
+        let mut line = String::new();
+        let read = tokio::time::timeout(
+            CLIENT_TIMEOUT,
+            tokio::io::BufReader::new(&mut stream).read_line(&mut line),
+        )
+        .await
+        .context("client didn't send request in time")?
+        .context("receive request")?;
+        // nothing is sent when another daemon checks whether this one is running
+        if read == 0 {
+            return Ok(());
+        }
+        let Request::Get { active_pid } =
+            serde_json::from_str(&line).context("deserialize request")?;
+
+        let reply = self.get(active_pid).await.map_err(|err| format!("{err:#}"));
+        let mut line = serde_json::to_string(&reply).context("serialize reply")?;
+        line.push('\n');
+        tokio::time::timeout(CLIENT_TIMEOUT, stream.write_all(line.as_bytes()))
+            .await
+            .context("client didn't receive reply in time")?
+            .context("send reply")
+    }
+}
+
+/// Serves locations on [`socket_path`] until it fails, process tree is kept up to date with events
+/// of kernel proc connector with `proc-connector` feature. Otherwise or if they are unavailable
+/// it's refreshed on focus changes reported by `tracker`, on registry updates and every `refresh`
+///
+/// Config of `reloader` is applied on the next query after it's reloaded, except `registry_path`
+/// which the socket is bound next to
+pub async fn run(
+    provider: &impl ActiveWindowProvider,
+    tracker: Option<&ActiveWindowTracker>,
+    reloader: &ConfigReloader,
+    refresh: Duration,
+) -> anyhow::Result<()> {
+    let config = reloader.config();
+    let registry_changed = Arc::new(Notify::new());
+    let watcher = RegistryWatcher::new(config.registry_path.clone())?;
+    std::thread::spawn({
+        let registry_changed = registry_changed.clone();
+        move || {
+            loop {
+                if let Err(err) = watcher.next_events() {
+                    tracing::error!("stop watching location registry: {err:#}");
+                    break;
+                }
+                registry_changed.notify_one();
+            }
+        }
+    });
+
+    let listener = bind(&socket_path(&config.registry_path), "daemon")?;
+    listener
+        .set_nonblocking(true)
+        .context("set socket non-blocking")?;
+    let listener = UnixListener::from_std(listener).context("listen on daemon socket")?;
+
+    let mut daemon = Daemon {
+        provider,
+        reloader,
+        registry: config.registry()?,
+        processes: ProcessCache::new(config.scan_options()),
+        config,
+    };
+    daemon.refresh().await?;
+    let mut process_events = subscribe_process_events();
+
+    let mut refresh = tokio::time::interval(refresh);
+    refresh.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // the first tick completes right away
+    refresh.tick().await;
+    loop {
+        let focus_changed = async {
+            match tracker {
+                Some(tracker) => tracker.focus_changed().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            connection = listener.accept() => {
+                let (stream, _) = connection.context("accept connection")?;
+                // failed connection is dropped, the daemon keeps serving others
+                if let Err(err) = daemon.handle_connection(stream).await {
+                    tracing::warn!("failed to handle connection: {err:#}");
+                }
+                continue;
+            }
+            events = next_process_events(&mut process_events) => {
+                if let Err(err) = daemon.apply(events) {
+                    tracing::error!("failed to update process tree: {err:#}");
+                }
+                continue;
+            }
+            () = focus_changed => {}
+            () = registry_changed.notified() => {}
+            _ = refresh.tick() => {}
+        }
+
+        // the tree is already current
+        if process_events.is_some() {
+            continue;
+        }
+        if let Err(err) = daemon.refresh().await {
+            tracing::error!("failed to refresh process tree: {err:#}");
+        }
+        refresh.reset();
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::time::Duration;
+
+use nix::unistd::{AccessFlags, access};
+
+use crate::config::Config;
+use crate::process::{self, Pid, ProcessTree, ScanOptions};
+use crate::registry::{LocationRegistry, Registry, RegistryEntry};
+use crate::resolver::{Resolved, Resolver};
+use crate::walk::Node;
+use crate::{KnownProc, read_entry, search_in};
+
+#[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Debug)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Result of a single check of [`diagnose`]
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    /// What is checked, e.g. `registry` or pattern of known process
+    pub subject: String,
+    /// What is found and what to do about it
+    pub message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, subject: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            subject: subject.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Ok => "ok",
+            Severity::Warning => "warn",
+            Severity::Error => "error",
+        };
+        write!(f, "{severity:<5} {}: {}", self.subject, self.message)
+    }
+}
+
+/// Checks every link between window manager and printed location, e.g. to find out why fallback
+/// location is printed. `active_pid` is what window manager reports
+pub fn diagnose(active_pid: anyhow::Result<Option<Pid>>, config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let active_pid = match active_pid {
+        Ok(Some(pid)) => {
+            findings.push(Finding::new(
+                Severity::Ok,
+                "window manager",
+                format!("active window belongs to process {pid}"),
+            ));
+            Some(pid)
+        }
+        Ok(None) => {
+            findings.push(Finding::new(
+                Severity::Warning,
+                "window manager",
+                "no window is focused",
+            ));
+            None
+        }
+        Err(err) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                "window manager",
+                format!("{err:#}"),
+            ));
+            None
+        }
+    };
+
+    // start times identify entries written from containers
+    let options = ScanOptions {
+        read_start_time: true,
+        ..config.scan_options()
+    };
+    let processes = match process::build_process_tree_with(&options) {
+        Ok(processes) => {
+            findings.push(Finding::new(
+                Severity::Ok,
+                "processes",
+                format!("{} processes are found", processes.len()),
+            ));
+            Some(processes)
+        }
+        Err(err) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                "processes",
+                format!("{err:#}, is /proc mounted?"),
+            ));
+            None
+        }
+    };
+
+    findings.push(check_registry_dir(config));
+    let namespaced = processes
+        .as_ref()
+        .map(process::namespaced_pids)
+        .unwrap_or_default();
+    let Some((registry, entries)) = check_entries(config, &namespaced, &mut findings) else {
+        return findings;
+    };
+    let Some(processes) = processes else {
+        return findings;
+    };
+    check_writers(&processes, &entries, config, &mut findings);
+    if let Some(active_pid) = active_pid {
+        findings.push(check_active(active_pid, &processes, &registry, config));
+    }
+
+    findings
+}
+
+fn check_registry_dir(config: &Config) -> Finding {
+    let path = &config.registry_path;
+    let finding = |severity, message: String| {
+        Finding::new(
+            severity,
+            "registry",
+            format!("{}: {message}", path.display()),
+        )
+    };
+
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return finding(
+                Severity::Warning,
+                "doesn't exist, nothing has written its location yet".to_owned(),
+            );
+        }
+        Err(err) => return finding(Severity::Error, format!("can't be accessed: {err}")),
+    };
+
+    if !metadata.is_dir() {
+        finding(Severity::Error, "is not a directory".to_owned())
+    } else if metadata.uid() != nix::unistd::geteuid().as_raw() {
+        finding(
+            Severity::Error,
+            "is owned by another user, remove it or change `registry_path`".to_owned(),
+        )
+    } else if let Err(errno) = access(path, AccessFlags::W_OK) {
+        finding(Severity::Error, format!("is not writable: {errno}"))
+    } else {
+        finding(Severity::Ok, "is writable".to_owned())
+    }
+}
+
+/// Returns entries of live processes, `namespaced` are ones in containers, see
+/// [`process::namespaced_pids`]
+fn check_entries(
+    config: &Config,
+    namespaced: &HashSet<(Pid, u64)>,
+    findings: &mut Vec<Finding>,
+) -> Option<(Registry, HashSet<RegistryEntry>)> {
+    let error = |err: anyhow::Error| Finding::new(Severity::Error, "registry", format!("{err:#}"));
+    let registry = match config.registry() {
+        Ok(registry) => registry,
+        Err(err) => {
+            findings.push(error(err));
+            return None;
+        }
+    };
+    let iter = match registry.iter() {
+        Ok(iter) => iter,
+        Err(err) => {
+            findings.push(error(err.into()));
+            return None;
+        }
+    };
+
+    let ttl = config.entry_ttl.map(Duration::from_secs);
+    let mut entries = HashSet::new();
+    let mut broken = 0;
+    let mut stale = 0;
+    for entry in iter {
+        match entry {
+            Ok((entry, data)) => {
+                let alive = data.is_written_by(entry.pid)
+                    || data
+                        .start_time
+                        .is_some_and(|start_time| namespaced.contains(&(entry.pid, start_time)));
+                if !alive || ttl.is_some_and(|ttl| data.is_expired(ttl)) {
+                    stale += 1;
+                } else {
+                    entries.insert(entry);
+                }
+            }
+            Err(_) => broken += 1,
+        }
+    }
+
+    findings.push(Finding::new(
+        Severity::Ok,
+        "registry",
+        format!("{} entries of running processes", entries.len()),
+    ));
+    if broken > 0 {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "registry",
+            format!(
+                "{broken} entries can't be read, they may be written by a newer version. \
+                `current-location clear` removes them"
+            ),
+        ));
+    }
+    if stale > 0 {
+        findings.push(Finding::new(
+            Severity::Warning,
+            "registry",
+            format!(
+                "{stale} entries of exited processes or expired ones, `current-location prune` \
+                removes them"
+            ),
+        ));
+    }
+
+    Some((registry, entries))
+}
+
+/// Known processes which are running but have never written their location likely lack hooks
+fn check_writers(
+    processes: &ProcessTree,
+    entries: &HashSet<RegistryEntry>,
+    config: &Config,
+    findings: &mut Vec<Finding>,
+) {
+    let known_procs = match KnownProc::from_config(config) {
+        Ok(known_procs) => known_procs,
+        Err(err) => {
+            findings.push(Finding::new(Severity::Error, "config", format!("{err:#}")));
+            return;
+        }
+    };
+
+    // known processes are in the same order as their patterns
+    for (pattern, known) in config.known_procs.iter().zip(&known_procs) {
+        if known.resolver != Resolver::Registry {
+            continue;
+        }
+        let running: Vec<_> = processes
+            .values()
+            .map(|process| process.data())
+            .filter(|proc| known.matcher.is_match(proc))
+            .collect();
+        if running.is_empty() {
+            continue;
+        }
+
+        let written = running
+            .iter()
+            .filter(|proc| {
+                // processes in containers write their pids inside of them
+                [Some(proc.pid), proc.ns_pid]
+                    .into_iter()
+                    .flatten()
+                    .any(|pid| {
+                        entries.contains(&RegistryEntry {
+                            pid,
+                            name: proc.name.to_string(),
+                        })
+                    })
+            })
+            .count();
+        let finding = if written == 0 {
+            Finding::new(
+                Severity::Warning,
+                pattern,
+                format!(
+                    "none of {} running processes has written its location, are hooks \
+                    installed? See `current-location shell-init` and `current-location nvim-init`",
+                    running.len()
+                ),
+            )
+        } else {
+            Finding::new(
+                Severity::Ok,
+                pattern,
+                format!(
+                    "{written} of {} running processes have written their location",
+                    running.len()
+                ),
+            )
+        };
+        findings.push(finding);
+    }
+}
+
+/// Explains what `get` prints for active window
+fn check_active(
+    active_pid: Pid,
+    processes: &ProcessTree,
+    registry: &Registry,
+    config: &Config,
+) -> Finding {
+    let finding = |severity, message: String| Finding::new(severity, "active window", message);
+
+    let resolved = match search_in(processes, active_pid, config) {
+        Ok(result) => result.map(|result| result.resolved),
+        Err(err) => return finding(Severity::Error, format!("{:#}", anyhow::Error::from(err))),
+    };
+    let entry = match resolved {
+        None => {
+            let name = processes
+                .get(&active_pid)
+                .map(|process| &*process.data().name)
+                .unwrap_or_default();
+            return finding(
+                Severity::Warning,
+                format!(
+                    "neither process {active_pid} ({name}) nor its descendants are known, \
+                    fallback location is printed. Add the program to `known_procs`"
+                ),
+            );
+        }
+        Some(Resolved::Location(data)) => {
+            return finding(
+                Severity::Ok,
+                format!("location is {}", data.location().display()),
+            );
+        }
+        Some(Resolved::Registry(entry)) => entry,
+    };
+
+    let process = format!("{} ({})", entry.name, entry.pid);
+    let printed = if config.cwd_fallback {
+        "its working directory"
+    } else {
+        "fallback location"
+    };
+    let ttl = config.entry_ttl.map(Duration::from_secs);
+    match read_entry(&entry, registry) {
+        Err(err) => finding(Severity::Error, format!("{:#}", anyhow::Error::from(err))),
+        Ok(None) => finding(
+            Severity::Warning,
+            format!(
+                "{process} is found but hasn't written its location, {printed} is printed. Is \
+                its hook installed?"
+            ),
+        ),
+        Ok(Some((_, data)))
+            if !data.is_written_by(entry.pid) || ttl.is_some_and(|ttl| data.is_expired(ttl)) =>
+        {
+            finding(
+                Severity::Warning,
+                format!("location of {process} is expired, {printed} is printed"),
+            )
+        }
+        Ok(Some((_, data))) => finding(
+            Severity::Ok,
+            format!("{process} is at {}", data.location().display()),
+        ),
+    }
+}
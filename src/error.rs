@@ -0,0 +1,209 @@
+use std::fmt;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::process::Pid;
+use crate::registry::RegistryEntry;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Failure of the library API. No focused window isn't one, fallback location is returned then
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Window manager isn't detected, is unreachable or failed to report active window
+    #[error("get active window")]
+    WmUnavailable(#[source] anyhow::Error),
+    /// Window manager didn't reply within `wm_timeout` of config
+    #[error("window manager didn't reply in {0:?}")]
+    WmTimeout(Duration),
+    /// `/proc` can't be read
+    #[error("build processes tree")]
+    ProcessTree(#[source] anyhow::Error),
+    /// Process isn't running, e.g. it exited in between
+    #[error("process {0} not found")]
+    ProcessNotFound(Pid),
+    /// Config is invalid, e.g. a pattern can't be compiled
+    #[error("invalid config")]
+    Config(#[source] anyhow::Error),
+    /// Location of a known process can't be resolved, e.g. its resolver command failed
+    #[error("resolve location of {name}")]
+    Resolve {
+        name: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// Location Registry can't be opened, read or written
+    #[error("access location registry")]
+    RegistryIo(#[source] anyhow::Error),
+    /// Registry entry can't be decoded, e.g. it's written by a newer version
+    #[error("registry entry {} of {} is corrupt", .entry.name, .entry.pid)]
+    CorruptEntry {
+        entry: RegistryEntry,
+        #[source]
+        source: anyhow::Error,
+    },
+}
+
+impl Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::WmUnavailable(_) | Self::WmTimeout(_) => ErrorCode::WindowManager,
+            Self::ProcessTree(_) => ErrorCode::ProcessTree,
+            Self::ProcessNotFound(_) => ErrorCode::ProcessNotFound,
+            Self::Config(_) => ErrorCode::Config,
+            Self::Resolve { .. } => ErrorCode::Other,
+            Self::RegistryIo(_) => ErrorCode::Registry,
+            Self::CorruptEntry { .. } => ErrorCode::CorruptEntry,
+        }
+    }
+
+    /// Registry error of `entry`
+    #[cfg(feature = "procfs")]
+    pub(crate) fn registry(entry: &RegistryEntry, err: RegistryError) -> Self {
+        match err {
+            RegistryError::Io(err) => Self::RegistryIo(err),
+            RegistryError::Corrupt(err) => Self::CorruptEntry {
+                entry: entry.clone(),
+                source: err,
+            },
+        }
+    }
+}
+
+/// Corrupt entries are reported as [`Error::RegistryIo`] when it's unknown which entry it is
+impl From<RegistryError> for Error {
+    fn from(err: RegistryError) -> Self {
+        match err {
+            RegistryError::Io(err) | RegistryError::Corrupt(err) => Self::RegistryIo(err),
+        }
+    }
+}
+
+/// Failure of [`LocationRegistry`](crate::registry::LocationRegistry)
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum RegistryError {
+    /// Storage can't be opened, read or written
+    #[error(transparent)]
+    Io(anyhow::Error),
+    /// Stored entry can't be decoded, e.g. it's written by a newer version
+    #[error(transparent)]
+    Corrupt(anyhow::Error),
+}
+
+impl RegistryError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Io(_) => ErrorCode::Registry,
+            Self::Corrupt(_) => ErrorCode::CorruptEntry,
+        }
+    }
+
+    /// Same error wrapped with `context`, e.g. of the entry it's about
+    #[must_use]
+    pub fn context<C>(self, context: C) -> Self
+    where
+        C: fmt::Display + Send + Sync + 'static,
+    {
+        match self {
+            Self::Io(err) => Self::Io(err.context(context)),
+            Self::Corrupt(err) => Self::Corrupt(err.context(context)),
+        }
+    }
+}
+
+/// Errors of registry implementations, decoding ones are told apart by [`ErrorCode::CorruptEntry`]
+/// attached to them
+impl From<anyhow::Error> for RegistryError {
+    fn from(err: anyhow::Error) -> Self {
+        if ErrorCode::of(&err) == ErrorCode::CorruptEntry {
+            Self::Corrupt(err)
+        } else {
+            Self::Io(err)
+        }
+    }
+}
+
+/// Kind of failure, attached to errors as context so callers can tell them apart without matching
+/// messages
+#[derive(Serialize, Eq, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Window manager is unreachable or failed to report active window
+    WindowManager,
+    /// `/proc` can't be read
+    ProcessTree,
+    /// Active process is not in process tree, e.g. it exited in between
+    ProcessNotFound,
+    /// Config can't be read or is invalid
+    Config,
+    /// Location Registry can't be opened or read
+    Registry,
+    /// Registry entry can't be decoded
+    CorruptEntry,
+    /// Anything else
+    Other,
+}
+
+impl ErrorCode {
+    /// Code of [`Error`] in `err` or code attached to `err`, the outermost one if there are
+    /// several
+    pub fn of(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.downcast_ref::<Error>() {
+            return err.code();
+        }
+        if let Some(err) = err.downcast_ref::<RegistryError>() {
+            return err.code();
+        }
+        err.downcast_ref::<Self>().copied().unwrap_or(Self::Other)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            Self::WindowManager => "get active window",
+            Self::ProcessTree => "build processes tree",
+            Self::ProcessNotFound => "process not found",
+            Self::Config => "invalid config",
+            Self::Registry => "access location registry",
+            Self::CorruptEntry => "corrupt registry entry",
+            Self::Other => "unknown error",
+        };
+        f.write_str(message)
+    }
+}
+
+#[cfg(all(test, feature = "procfs"))]
+mod tests {
+    use anyhow::anyhow;
+
+    use crate::error::{Error, ErrorCode, RegistryError};
+    use crate::registry::RegistryEntry;
+
+    #[test]
+    fn registry_error() {
+        let entry = RegistryEntry {
+            pid: 1,
+            name: "zsh".to_owned(),
+        };
+
+        let corrupt = anyhow!("expected value").context(ErrorCode::CorruptEntry);
+        let io = anyhow!("permission denied").context("read registry entry");
+
+        assert_eq!(
+            Error::registry(&entry, corrupt.into()).code(),
+            ErrorCode::CorruptEntry
+        );
+        assert_eq!(
+            Error::registry(&entry, io.into()).code(),
+            ErrorCode::Registry
+        );
+        assert_eq!(
+            ErrorCode::of(&RegistryError::Io(anyhow!("disk full")).into()),
+            ErrorCode::Registry
+        );
+    }
+}
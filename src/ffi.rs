@@ -0,0 +1,204 @@
+//! C API, see `include/current_location.h` generated by cbindgen. Config is read from its default
+//! path on every call
+
+use std::ffi::{CStr, OsStr, c_char, c_int};
+use std::os::unix::ffi::OsStrExt;
+use std::panic::{self, UnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+
+use anyhow::Context;
+
+use crate::LocationData;
+use crate::config::Config;
+use crate::error::ErrorCode;
+use crate::process::Pid;
+
+/// Negative return values of C API, see [`ErrorCode`]
+#[repr(C)]
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ClError {
+    WindowManager = -1,
+    ProcessTree = -2,
+    ProcessNotFound = -3,
+    Config = -4,
+    Registry = -5,
+    CorruptEntry = -6,
+    Other = -7,
+    /// Null pointer or a string that isn't valid UTF-8 is passed
+    InvalidArgument = -8,
+}
+
+impl From<ErrorCode> for ClError {
+    fn from(code: ErrorCode) -> Self {
+        match code {
+            ErrorCode::WindowManager => Self::WindowManager,
+            ErrorCode::ProcessTree => Self::ProcessTree,
+            ErrorCode::ProcessNotFound => Self::ProcessNotFound,
+            ErrorCode::Config => Self::Config,
+            ErrorCode::Registry => Self::Registry,
+            ErrorCode::CorruptEntry => Self::CorruptEntry,
+            ErrorCode::Other => Self::Other,
+        }
+    }
+}
+
+/// Errors are logged and returned as [`ClError`], panics don't cross the boundary
+fn guard(f: impl FnOnce() -> Result<c_int, ClError> + UnwindSafe) -> c_int {
+    match panic::catch_unwind(f) {
+        Ok(Ok(ret)) => ret,
+        Ok(Err(err)) => err as c_int,
+        Err(_) => ClError::Other as c_int,
+    }
+}
+
+fn code(err: anyhow::Error) -> ClError {
+    tracing::debug!("C API call failed: {err:#}");
+    ErrorCode::of(&err).into()
+}
+
+/// # Safety
+/// `ptr` is null or points to a NUL terminated string
+unsafe fn to_str<'a>(ptr: *const c_char) -> Result<&'a str, ClError> {
+    if ptr.is_null() {
+        return Err(ClError::InvalidArgument);
+    }
+    // SAFETY: guaranteed by caller
+    let str = unsafe { CStr::from_ptr(ptr) };
+    str.to_str().map_err(|_| ClError::InvalidArgument)
+}
+
+fn get_location(pid: Pid) -> anyhow::Result<LocationData> {
+    let config = Config::load_default().context(ErrorCode::Config)?;
+    let registry = config.registry().context(ErrorCode::Registry)?;
+    let data = crate::get_detected((pid > 0).then_some(pid), &registry, &config)?;
+    Ok(data)
+}
+
+/// Writes location of process `pid` and its descendants, like `current-location get
+/// --active-pid`, to `buf` as a NUL terminated string. Location of active window is written if
+/// `pid` is 0 and the library is built with `wm` feature. Fallback location is written if no known
+/// process is found.
+///
+/// Returns length of location without NUL like `snprintf`, it's truncated if the length is `len`
+/// or more, or a negative [`ClError`]
+///
+/// # Safety
+/// `buf` is null or points to at least `len` writable bytes
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cl_get_location(pid: Pid, buf: *mut c_char, len: usize) -> c_int {
+    guard(|| {
+        let data = get_location(pid).map_err(code)?;
+        // SAFETY: guaranteed by caller
+        unsafe { copy_location(data.location().as_os_str().as_bytes(), buf, len) }
+    })
+}
+
+/// Copies `location` to `buf` like `snprintf` does
+///
+/// # Safety
+/// `buf` is null or points to at least `len` writable bytes
+unsafe fn copy_location(location: &[u8], buf: *mut c_char, len: usize) -> Result<c_int, ClError> {
+    if !buf.is_null() && len > 0 {
+        let copied = location.len().min(len - 1);
+        // SAFETY: `buf` has `len` bytes, `copied` is less than that
+        unsafe {
+            ptr::copy_nonoverlapping(location.as_ptr().cast(), buf, copied);
+            *buf.add(copied) = 0;
+        }
+    }
+    c_int::try_from(location.len()).map_err(|_| ClError::Other)
+}
+
+/// Writes `location` of process `pid` named `name` to Location Registry, like `current-location
+/// write`. Returns 0 or a negative [`ClError`]
+///
+/// # Safety
+/// `name` and `location` are null or point to NUL terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn cl_write(name: *const c_char, pid: Pid, location: *const c_char) -> c_int {
+    guard(|| {
+        // SAFETY: guaranteed by caller
+        let name = unsafe { to_str(name)? };
+        if location.is_null() {
+            return Err(ClError::InvalidArgument);
+        }
+        // SAFETY: guaranteed by caller, paths are arbitrary bytes
+        let location = unsafe { CStr::from_ptr(location) };
+        let location = PathBuf::from(OsStr::from_bytes(location.to_bytes()));
+
+        let config = Config::load_default()
+            .context(ErrorCode::Config)
+            .map_err(code)?;
+        let registry = config
+            .registry()
+            .context(ErrorCode::Registry)
+            .map_err(code)?;
+        let data = LocationData::new(location).with_project_root(&config.project_markers);
+        crate::write(name.to_owned(), vec![pid], data, &registry)
+            .map_err(|err| code(err.into()))?;
+        Ok(0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, c_char, c_int};
+    use std::ptr;
+
+    use super::{ClError, cl_write, copy_location, guard, to_str};
+
+    #[test]
+    fn copy_truncated() {
+        let copy = |len| {
+            let mut buf = [c_char::MAX; 8];
+            // SAFETY: `len` is at most length of `buf`
+            let ret = unsafe { copy_location(b"/srv/www", buf.as_mut_ptr(), len) };
+            (ret, buf)
+        };
+
+        let (ret, buf) = copy(8);
+        assert_eq!(ret, Ok(8));
+        // SAFETY: NUL is written
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }, c"/srv/ww");
+
+        let (ret, buf) = copy(1);
+        assert_eq!(ret, Ok(8));
+        assert_eq!(buf[..2], [0, c_char::MAX]);
+
+        // nothing is written, not even NUL
+        let (ret, buf) = copy(0);
+        assert_eq!(ret, Ok(8));
+        assert_eq!(buf[0], c_char::MAX);
+
+        // SAFETY: null is allowed
+        let ret = unsafe { copy_location(b"/srv", ptr::null_mut(), 16) };
+        assert_eq!(ret, Ok(4));
+    }
+
+    #[test]
+    fn invalid_argument() {
+        // SAFETY: null is allowed
+        assert_eq!(
+            unsafe { to_str(ptr::null()) },
+            Err(ClError::InvalidArgument)
+        );
+        // SAFETY: NUL terminated
+        let invalid = unsafe { to_str(c"\xff".as_ptr()) };
+        assert_eq!(invalid, Err(ClError::InvalidArgument));
+
+        // SAFETY: null is allowed
+        let ret = unsafe { cl_write(ptr::null(), 1, c"/srv".as_ptr()) };
+        assert_eq!(ret, ClError::InvalidArgument as c_int);
+        // SAFETY: null is allowed
+        let ret = unsafe { cl_write(c"bar".as_ptr(), 1, ptr::null()) };
+        assert_eq!(ret, ClError::InvalidArgument as c_int);
+    }
+
+    #[test]
+    fn panic_is_caught() {
+        assert_eq!(guard(|| panic!("boom")), ClError::Other as c_int);
+        assert_eq!(guard(|| Err(ClError::Config)), ClError::Config as c_int);
+        assert_eq!(guard(|| Ok(3)), 3);
+    }
+}
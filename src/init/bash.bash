@@ -0,0 +1,24 @@
+# current-location integration for bash, add to ~/.bashrc:
+#   eval "$(current-location shell-init bash)"
+# `bash` has to be added to `known_procs` of config
+
+_current_location_write() {
+  # bash has no hook for directory change, so it's checked before every prompt
+  if [[ "$PWD" != "${_current_location_pwd-}" ]]; then
+    _current_location_pwd=$PWD
+    current-location write bash "$PWD" "$$"
+  fi
+}
+
+_current_location_remove() {
+  current-location remove bash "$$"
+}
+
+if [[ ";${PROMPT_COMMAND-};" != *";_current_location_write;"* ]]; then
+  PROMPT_COMMAND="_current_location_write${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+
+# EXIT trap of user is kept, entries of dead shells are ignored anyway
+if [[ -z "$(trap -p EXIT)" ]]; then
+  trap _current_location_remove EXIT
+fi
@@ -0,0 +1,14 @@
+# current-location integration for fish, add to ~/.config/fish/config.fish:
+#   current-location shell-init fish | source
+# `fish` has to be added to `known_procs` of config
+
+function __current_location_write --on-variable PWD
+    current-location write fish "$PWD" $fish_pid
+end
+
+function __current_location_remove --on-event fish_exit
+    current-location remove fish $fish_pid
+end
+
+# PWD isn't changed on startup
+__current_location_write
@@ -0,0 +1,80 @@
+-- current-location integration for Neovim 0.10+, installed by `current-location nvim-init --install`
+-- or put into `plugin` directory of Neovim config
+
+local group = vim.api.nvim_create_augroup('current_location', { clear = true })
+
+-- in-process module built with `lua` feature, used instead of spawning the binary if it's found
+local has_module, module = pcall(require, 'current_location')
+
+-- windows belong to UI which is the parent of embedded server, e.g. TUI or Neovide
+local function pids()
+  return { vim.fn.getpid(), vim.uv.os_getppid() }
+end
+
+local function notify_error(err)
+  vim.schedule(function()
+    vim.notify('current-location: ' .. err, vim.log.levels.ERROR)
+  end)
+end
+
+-- `cursor` is `{ line, column }` of a file, both 1-based
+local function write(location, cursor)
+  if has_module then
+    local opts = {
+      nvim_pipe = vim.v.servername ~= '' and vim.v.servername or nil,
+      line = cursor and cursor[1],
+      column = cursor and cursor[2],
+    }
+    local ok, err = pcall(module.write, 'nvim', pids(), location, opts)
+    if not ok then
+      notify_error(err)
+    end
+    return
+  end
+
+  local cmd = { 'current-location', 'write', 'nvim', location }
+  vim.list_extend(cmd, vim.tbl_map(tostring, pids()))
+  if vim.v.servername ~= '' then
+    vim.list_extend(cmd, { '--nvim-pipe', vim.v.servername })
+  end
+  if cursor then
+    vim.list_extend(cmd, { '--line', tostring(cursor[1]), '--column', tostring(cursor[2]) })
+  end
+
+  vim.system(cmd, { text = true }, function(result)
+    if result.code ~= 0 then
+      notify_error(result.stderr)
+    end
+  end)
+end
+
+vim.api.nvim_create_autocmd({ 'BufEnter', 'BufWritePost', 'DirChanged' }, {
+  group = group,
+  desc = 'Write current file or working directory to Location Registry',
+  callback = function()
+    local file = vim.api.nvim_buf_get_name(0)
+    -- terminals, file explorers and so on have no file of their own
+    if vim.bo.buftype == '' and vim.fn.filereadable(file) == 1 then
+      -- column of `nvim_win_get_cursor` is 0-based
+      local line, column = unpack(vim.api.nvim_win_get_cursor(0))
+      write(file, { line, column + 1 })
+    else
+      write(vim.fn.getcwd())
+    end
+  end,
+})
+
+vim.api.nvim_create_autocmd('VimLeave', {
+  group = group,
+  desc = 'Remove location from Location Registry',
+  callback = function()
+    if has_module then
+      pcall(module.remove, 'nvim', pids())
+      return
+    end
+
+    local cmd = { 'current-location', 'remove', 'nvim' }
+    vim.list_extend(cmd, vim.tbl_map(tostring, pids()))
+    vim.system(cmd):wait()
+  end,
+})
@@ -1,187 +1,1391 @@
-#![feature(substr_range)]
 #![feature(slice_range)]
 
+use std::collections::HashMap;
 use std::env;
-use std::fs::{self, File};
+#[cfg(feature = "wm")]
+use std::mem;
 use std::ops::ControlFlow;
-use std::os::unix::fs::PermissionsExt;
-use std::path::PathBuf;
-use std::{io, sync::LazyLock};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, anyhow};
-use hyprland::{data::Client, shared::HyprDataActiveOptional};
+use anyhow::Context;
+#[cfg(feature = "wm")]
+use async_io::Timer;
+#[cfg(feature = "wm")]
+use futures_lite::future;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "wm")]
+use tracing::Instrument;
 
-use crate::process::{Pid, Process, ProcessInfo};
-use crate::walk::{ContinueFlow, Node, Walker, WalkerNode};
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::pattern::ProcMatcher;
+#[cfg(feature = "wm")]
+use crate::process::ProcessCache;
+use crate::process::{Pid, Process, ProcessInfo, ProcessTree};
+use crate::registry::{LocationRegistry, RegistryEntry};
+use crate::resolver::{Resolved, Resolver};
+use crate::walk::{ContinueFlow, Node, Walker};
+#[cfg(feature = "wm")]
+use crate::wm::{ActiveWindowProvider, WindowManager};
 
+pub mod config;
+#[cfg(feature = "tokio")]
+pub mod daemon;
+#[cfg(all(feature = "procfs", feature = "registry"))]
+pub mod doctor;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "lua")]
+mod lua;
+mod os_path;
+pub mod pattern;
 pub mod process;
+#[cfg(feature = "python")]
+mod python;
+pub mod registry;
+pub mod resolver;
+#[cfg(all(feature = "wm", feature = "registry"))]
+mod search;
+pub mod selection;
+pub mod template;
 pub mod tosubstr;
 pub mod walk;
+#[cfg(feature = "wm")]
+pub mod wm;
 
-const KNOWN_PROCS: &[&str] = &["zsh", "nvim"];
-const BFS_HEAP_CAPACITY: usize = 1024;
-static LOCATIONS_PATH: LazyLock<PathBuf> =
-    LazyLock::new(|| PathBuf::from(format!("/tmp/current-location-{}", nix::unistd::geteuid())));
+#[cfg(all(feature = "wm", feature = "registry"))]
+pub use search::{Search, SearchBuilder};
+pub use selection::{Selection, SelectionStrategy};
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct LocationData {
+    #[serde(with = "os_path")]
     location: PathBuf,
     nvim_pipe: Option<String>,
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     fallback: Option<bool>,
+    /// Working directory of found process which hasn't written its location, never stored
+    #[serde(default)]
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    cwd_fallback: bool,
+    /// Seconds since UNIX epoch, set by `write`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    written_at: Option<u64>,
+    /// Start time of the process that wrote it, it doesn't match if pid is reused
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start_time: Option<u64>,
+    /// Detected by `write`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<LocationKind>,
+    /// Cursor position in file, 1-based
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<u32>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<u32>,
+    /// The nearest directory containing a project marker, see [`Config::project_markers`]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(with = "os_path::option")]
+    project_root: Option<PathBuf>,
+    /// Address of window manager window of the process, e.g. Hyprland window address
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window: Option<String>,
+    /// Terminal window or pane of the process, see [`detect_terminal`]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    terminal: Option<String>,
+    /// Arbitrary data of integrations, e.g. git branch or virtualenv
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    extra: HashMap<String, String>,
+}
+
+/// Environment variables identifying terminal window or pane, innermost ones first
+const TERMINAL_VARS: &[(&str, &str)] = &[
+    ("TMUX_PANE", "tmux"),
+    ("KITTY_WINDOW_ID", "kitty"),
+    ("WEZTERM_PANE", "wezterm"),
+    ("ALACRITTY_WINDOW_ID", "alacritty"),
+    ("WINDOWID", "x11"),
+];
+
+/// Identifier of terminal window or pane current process runs in, e.g. `kitty:3` or `tmux:%1`,
+/// taken from environment variables set by terminals
+pub fn detect_terminal() -> Option<String> {
+    TERMINAL_VARS.iter().find_map(|(var, terminal)| {
+        let id = env::var(var).ok().filter(|id| !id.is_empty())?;
+        Some(format!("{terminal}:{id}"))
+    })
+}
+
+/// Pids and addresses of open Hyprland windows, `None` if Hyprland isn't running
+#[cfg(feature = "hyprland")]
+pub fn open_windows() -> Option<Vec<(Pid, String)>> {
+    env::var_os("HYPRLAND_INSTANCE_SIGNATURE").filter(|signature| !signature.is_empty())?;
+    wm::hyprland::Hyprland::default()
+        .windows_blocking()
+        .inspect_err(|err| tracing::debug!("list windows: {err:#}"))
+        .ok()
+}
+
+/// Address of window process `pid` runs in, i.e. of the nearest of its ancestors owning one of
+/// `windows`, see [`open_windows`]
+#[cfg(feature = "procfs")]
+pub fn detect_window(pid: Pid, windows: &[(Pid, String)]) -> Option<String> {
+    // bounded in case a cycle is read while processes are reparented
+    let mut pid = pid;
+    for _ in 0..MAX_WINDOW_DEPTH {
+        if let Some((_, address)) = windows.iter().find(|(owner, _)| *owner == pid) {
+            return Some(address.clone());
+        }
+        pid = process::ppid(pid).ok().filter(|&ppid| ppid > 1)?;
+    }
+    None
+}
+
+/// Ancestors of a process searched for its window
+#[cfg(feature = "procfs")]
+const MAX_WINDOW_DEPTH: usize = 64;
+
+/// What location points to
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LocationKind {
+    File,
+    Directory,
+}
+
+impl LocationKind {
+    /// `None` if location doesn't exist or is neither a file nor a directory
+    pub fn detect(location: &Path) -> Option<Self> {
+        let metadata = location.metadata().ok()?;
+        if metadata.is_file() {
+            Some(Self::File)
+        } else if metadata.is_dir() {
+            Some(Self::Directory)
+        } else {
+            None
+        }
+    }
 }
 
 impl LocationData {
-    pub fn fallback() -> Self {
+    pub fn new(location: PathBuf) -> Self {
         Self {
-            location: env::home_dir().unwrap_or_else(|| PathBuf::from("/home/root/")),
+            location,
             nvim_pipe: None,
+            fallback: None,
+            cwd_fallback: false,
+            written_at: None,
+            start_time: None,
+            kind: None,
+            line: None,
+            column: None,
+            project_root: None,
+            window: None,
+            terminal: None,
+            extra: HashMap::new(),
+        }
+    }
+
+    pub fn with_nvim_pipe(self, nvim_pipe: Option<String>) -> Self {
+        Self { nvim_pipe, ..self }
+    }
+
+    pub fn with_window(self, window: Option<String>) -> Self {
+        Self { window, ..self }
+    }
+
+    pub fn with_terminal(self, terminal: Option<String>) -> Self {
+        Self { terminal, ..self }
+    }
+
+    pub fn with_extra(self, extra: HashMap<String, String>) -> Self {
+        Self { extra, ..self }
+    }
+
+    /// Cursor position in file, 1-based
+    pub fn with_cursor(self, line: Option<u32>, column: Option<u32>) -> Self {
+        Self {
+            line,
+            column,
+            ..self
+        }
+    }
+
+    pub fn fallback() -> Self {
+        Self::fallback_to(env::home_dir().unwrap_or_else(|| PathBuf::from("/home/root/")))
+    }
+
+    pub fn fallback_to(location: PathBuf) -> Self {
+        Self {
             fallback: true.into(),
+            ..Self::new(location)
+        }
+    }
+
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+
+    pub fn nvim_pipe(&self) -> Option<&str> {
+        self.nvim_pipe.as_deref()
+    }
+
+    pub fn kind(&self) -> Option<LocationKind> {
+        self.kind
+    }
+
+    pub fn line(&self) -> Option<u32> {
+        self.line
+    }
+
+    pub fn column(&self) -> Option<u32> {
+        self.column
+    }
+
+    pub fn project_root(&self) -> Option<&Path> {
+        self.project_root.as_deref()
+    }
+
+    pub fn window(&self) -> Option<&str> {
+        self.window.as_deref()
+    }
+
+    pub fn terminal(&self) -> Option<&str> {
+        self.terminal.as_deref()
+    }
+
+    /// Whether the window it's written from isn't among open `windows`, see [`open_windows`].
+    /// `None` if no window is recorded
+    pub fn is_window_closed(&self, windows: &[(Pid, String)]) -> Option<bool> {
+        let address = |window: &str| window.trim_start_matches("0x").to_owned();
+        let window = address(self.window.as_deref()?);
+        Some(!windows.iter().any(|(_, open)| address(open) == window))
+    }
+
+    pub fn extra(&self) -> &HashMap<String, String> {
+        &self.extra
+    }
+
+    /// Finds project root of location unless it's known already, `markers` are file names
+    pub fn with_project_root(mut self, markers: &[String]) -> Self {
+        if self.project_root.is_none() {
+            self.project_root = self
+                .location
+                .ancestors()
+                .find(|dir| markers.iter().any(|marker| dir.join(marker).exists()))
+                .map(Path::to_owned);
         }
+        self
+    }
+
+    /// Replaces location with a directory, position in file doesn't make sense anymore
+    fn set_directory(&mut self, dir: PathBuf) {
+        self.location = dir;
+        self.kind = Some(LocationKind::Directory);
+        self.line = None;
+        self.column = None;
+    }
+
+    /// Whether it's fallback location used when no location of a known process is found
+    pub fn is_fallback(&self) -> bool {
+        self.fallback == Some(true)
+    }
+
+    /// Whether it's working directory of found process which hasn't written its location or
+    /// whose entry is stale, see [`Config::cwd_fallback`]
+    pub fn is_cwd_fallback(&self) -> bool {
+        self.cwd_fallback
+    }
+
+    /// Whether the location isn't written by found process, either [`Self::is_fallback`] or
+    /// [`Self::is_cwd_fallback`]
+    pub fn is_any_fallback(&self) -> bool {
+        self.is_fallback() || self.is_cwd_fallback()
+    }
+
+    /// Time it's written at by `write`, `None` for data written by older versions
+    pub fn written_at(&self) -> Option<SystemTime> {
+        let written_at = self.written_at?;
+        Some(UNIX_EPOCH + Duration::from_secs(written_at))
+    }
+
+    /// Time passed since it's written, `None` if it has no timestamp
+    pub fn age(&self) -> Option<Duration> {
+        let written_at = self.written_at?;
+        Duration::from_secs(unix_time().saturating_sub(written_at)).into()
+    }
+
+    /// Whether it's written by currently running process with this pid rather than by a dead one
+    /// with the same pid, data without start time is trusted
+    #[cfg(feature = "procfs")]
+    pub fn is_written_by(&self, pid: Pid) -> bool {
+        self.start_time
+            .is_none_or(|start_time| process::start_time(pid).ok() == Some(start_time))
+    }
+
+    /// Same as [`Self::is_written_by`] but start time is taken from `proc`, so /proc isn't read.
+    /// Processes of unknown start time are trusted as well
+    pub fn is_written_by_process(&self, proc: &ProcessInfo) -> bool {
+        match (self.start_time, proc.start_time) {
+            (Some(written), Some(started)) => written == started,
+            _ => true,
+        }
+    }
+
+    /// Same data regardless of when it's written, e.g. the same location written again
+    pub fn is_same_as(&self, other: &Self) -> bool {
+        let unwritten = |data: &Self| Self {
+            written_at: None,
+            ..data.clone()
+        };
+        unwritten(self) == unwritten(other)
+    }
+
+    /// Resolves symlinks and `..` of location, fails if it doesn't exist anymore
+    pub fn canonicalize(mut self) -> anyhow::Result<Self> {
+        self.location = self
+            .location
+            .canonicalize()
+            .with_context(|| format!("canonicalize {}", self.location.display()))?;
+        Ok(self)
+    }
+
+    /// Replaces location which is a regular file, e.g. buffer of nvim, with its parent directory
+    pub fn dirname(mut self) -> Self {
+        if self.location.is_file()
+            && let Some(parent) = self.location.parent()
+        {
+            self.set_directory(parent.to_owned());
+        }
+        self
+    }
+
+    /// Replaces location with root of git repository it's in, `.git` may be a file in worktrees
+    /// and submodules. Location outside of repositories stays the same
+    pub fn git_root(mut self) -> Self {
+        let root = self
+            .location
+            .ancestors()
+            .find(|dir| dir.join(".git").exists());
+        if let Some(root) = root {
+            self.set_directory(root.to_owned());
+        }
+        self
+    }
+
+    /// Makes location relative to `base`, e.g. home directory for compact display. Location
+    /// outside of `base` stays absolute
+    pub fn relative_to(mut self, base: &Path) -> Self {
+        if let Ok(relative) = self.location.strip_prefix(base) {
+            self.location = if relative.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                relative.to_owned()
+            };
+        }
+        self
+    }
+
+    /// Whether it's written more than `ttl` ago, data without timestamp never expires
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        self.written_at
+            .is_some_and(|written_at| unix_time().saturating_sub(written_at) > ttl.as_secs())
     }
 }
 
 #[derive(Clone, Debug)]
-struct LocationSearch<'a> {
-    known_procs: Vec<&'a ProcessInfo>,
+struct KnownProc {
+    matcher: ProcMatcher,
+    priority: i32,
+    resolver: Resolver,
 }
 
-impl<'a> LocationSearch<'a> {
-    fn new() -> Self {
-        Self {
-            known_procs: Vec::with_capacity(KNOWN_PROCS.len() * 4),
-        }
+impl KnownProc {
+    fn from_config(config: &Config) -> anyhow::Result<Vec<Self>> {
+        config
+            .known_procs
+            .iter()
+            .map(|pattern| {
+                Ok(Self {
+                    matcher: ProcMatcher::new(pattern)?,
+                    priority: config.priorities.get(pattern).copied().unwrap_or_default(),
+                    resolver: config.resolvers.get(pattern).cloned().unwrap_or_default(),
+                })
+            })
+            .collect()
     }
+}
+
+/// Known process found in process tree of active window, see [`matches_in`]
+#[derive(Clone, Debug)]
+pub struct Match<'a> {
+    pub process: &'a ProcessInfo,
+    /// Depth below process of active window, which is at depth 0
+    pub depth: u64,
+    /// Position among children of its parent
+    pub sibling_no: u64,
+    /// Priority of the matched pattern of `known_procs`
+    pub priority: i32,
+    pub resolver: Resolver,
+}
+
+impl Match<'_> {
+    /// `None` if resolver finds no location
+    pub fn resolve(&self, config: &Config) -> Result<Option<SearchResult>> {
+        let resolved = self
+            .resolver
+            .resolve(self.process)
+            .map_err(|source| Error::Resolve {
+                name: self.process.name.to_string(),
+                source,
+            })?;
+        Ok(resolved.map(|resolved| SearchResult {
+            process: self.process.clone(),
+            depth: self.depth,
+            sibling_no: self.sibling_no,
+            registry_path: matches!(resolved, Resolved::Registry(_))
+                .then(|| config.registry_path.clone()),
+            resolved,
+        }))
+    }
+}
+
+/// Known processes in BFS order, so the nearest ones to active window go first. Process tree is
+/// walked lazily, so it's fine to stop early
+#[derive(Debug)]
+pub struct Matches<'a> {
+    walker: Walker<'a, ProcessInfo, Process>,
+    known_procs: Vec<KnownProc>,
+    excluded_procs: Vec<ProcMatcher>,
+    max_depth: Option<u64>,
+    /// Known processes of other users are skipped, see [`Config::other_users`]
+    uid: Option<u32>,
+}
 
-    fn handle_node(
-        &mut self,
-        node: WalkerNode<'a, ProcessInfo, Process>,
-    ) -> ControlFlow<Pid, ContinueFlow> {
-        if cfg!(debug_assertions) {
-            println!(
-                "{} - {} process({}): {:?}",
-                node.depth,
-                node.sibling_no,
-                node.inner.data().pid,
-                node.inner.data().name
+impl<'a> Iterator for Matches<'a> {
+    type Item = Match<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Self {
+            walker,
+            known_procs,
+            excluded_procs,
+            max_depth,
+            uid,
+        } = self;
+
+        walker.bfs(|node| {
+            let proc = node.inner.data();
+            tracing::trace!(
+                depth = node.depth,
+                sibling_no = node.sibling_no,
+                pid = proc.pid,
+                name = &*proc.name,
+                "visit process"
             );
-        }
 
-        if KNOWN_PROCS.contains(&node.inner.data().name.as_str()) {
-            self.known_procs.push(node.inner.data());
-        }
+            if max_depth.is_some_and(|max_depth| node.depth > max_depth) {
+                return ControlFlow::Continue(ContinueFlow::Skip);
+            }
+            if excluded_procs
+                .iter()
+                .any(|excluded| excluded.is_match(proc))
+            {
+                tracing::debug!(pid = proc.pid, name = &*proc.name, "skip excluded subtree");
+                return ControlFlow::Continue(ContinueFlow::Skip);
+            }
 
-        ControlFlow::Continue(ContinueFlow::Forward)
+            let known = known_procs
+                .iter()
+                .filter(|known| known.matcher.is_match(proc))
+                .max_by_key(|known| known.priority);
+            let Some(known) = known else {
+                return ControlFlow::Continue(ContinueFlow::Forward);
+            };
+            // processes of unknown owner are trusted
+            if let (Some(uid), Some(owner)) = (*uid, proc.uid)
+                && uid != owner
+            {
+                tracing::debug!(
+                    pid = proc.pid,
+                    name = &*proc.name,
+                    owner,
+                    "skip process of another user"
+                );
+                return ControlFlow::Continue(ContinueFlow::Forward);
+            }
+            tracing::debug!(
+                pid = proc.pid,
+                name = &*proc.name,
+                priority = known.priority,
+                "match known process"
+            );
+            ControlFlow::Break(Match {
+                process: proc,
+                depth: node.depth,
+                sibling_no: node.sibling_no,
+                priority: known.priority,
+                resolver: known.resolver.clone(),
+            })
+        })
     }
+}
+
+/// Known process selected by [`search`] and how its location is resolved
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub process: ProcessInfo,
+    /// Depth below process of active window, which is at depth 0
+    pub depth: u64,
+    /// Position among children of its parent
+    pub sibling_no: u64,
+    pub resolved: Resolved,
+    /// Directory of Location Registry the location is read from, `None` if it's resolved
+    /// otherwise. Whether the process has written it is known once it's read, see
+    /// [`LocationData::is_any_fallback`] of [`read_location`]
+    pub registry_path: Option<PathBuf>,
+}
 
-    fn select(&self) -> Option<&'a ProcessInfo> {
-        self.known_procs.last().copied()
+impl SearchResult {
+    /// Entry of Location Registry to read the location from
+    pub fn entry(&self) -> Option<&RegistryEntry> {
+        match &self.resolved {
+            Resolved::Registry(entry) => Some(entry),
+            Resolved::Location(_) => None,
+        }
     }
 }
 
-fn build_path(pid: Pid, name: &str) -> PathBuf {
-    let filename = format!("{name}-{pid}.txt");
-    LOCATIONS_PATH.join(filename)
+/// Compiled `known_procs` and `excluded_procs` of config
+fn compile_procs(config: &Config) -> Result<(Vec<KnownProc>, Vec<ProcMatcher>)> {
+    let known_procs = KnownProc::from_config(config)
+        .context("compile known processes")
+        .map_err(Error::Config)?;
+    let excluded_procs = config
+        .excluded_procs
+        .iter()
+        .map(|pattern| ProcMatcher::new(pattern))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("compile excluded processes")
+        .map_err(Error::Config)?;
+    Ok((known_procs, excluded_procs))
+}
+
+/// Known processes in process tree of `active_pid`, e.g. to select one of them in own way
+pub fn matches_in<'a>(
+    processes: &'a ProcessTree,
+    active_pid: Pid,
+    config: &Config,
+) -> Result<Matches<'a>> {
+    let (known_procs, excluded_procs) = compile_procs(config)?;
+    let root = processes
+        .get(&active_pid)
+        .ok_or(Error::ProcessNotFound(active_pid))?;
+    Ok(Matches {
+        walker: Walker::with_capacity(root, processes, config.bfs_heap_capacity),
+        known_procs,
+        excluded_procs,
+        max_depth: config.max_depth,
+        uid: (!config.other_users).then(|| nix::unistd::geteuid().as_raw()),
+    })
+}
+
+/// Same as [`search_sync`] but in already built process tree, e.g. a cached one
+pub fn search_in(
+    processes: &ProcessTree,
+    active_pid: Pid,
+    config: &Config,
+) -> Result<Option<SearchResult>> {
+    let strategy = config.selection.strategy(config)?;
+    search_in_with(processes, active_pid, config, strategy.as_ref())
 }
 
-pub async fn search(active_pid: Option<Pid>) -> anyhow::Result<Option<PathBuf>> {
-    let active_pid_fut = if active_pid.is_none() {
-        tokio::spawn(Client::get_active_async()).into()
-    } else {
-        None
+/// Same as [`search_in`] but `strategy` selects the process instead of `selection` of config
+pub fn search_in_with(
+    processes: &ProcessTree,
+    active_pid: Pid,
+    config: &Config,
+    strategy: &dyn SelectionStrategy,
+) -> Result<Option<SearchResult>> {
+    let span = tracing::debug_span!("tree walk").entered();
+    let matches: Vec<_> = matches_in(processes, active_pid, config)?.collect();
+    let Some(selected) = strategy.select(&matches).and_then(|i| matches.get(i)) else {
+        tracing::debug!(active_pid, "no known process found");
+        return Ok(None);
     };
+    tracing::debug!(
+        pid = selected.process.pid,
+        name = &*selected.process.name,
+        resolver = ?selected.resolver,
+        "select process"
+    );
+    drop(span);
 
-    let processes = process::build_process_tree().context("build processes tree")?;
+    let _span = tracing::debug_span!("resolve").entered();
+    selected.resolve(config)
+}
 
-    let active_pid = if let Some(active_pid) = active_pid {
-        active_pid
-    } else {
-        let Some(active_client) = active_pid_fut
-            .expect("fut is present if active_pid is None")
-            .await
-            .context("join failed")?
-            .context("failed to get active client")?
-        else {
+/// Same as [`search_in`] but every matched process is resolved, e.g. to let user pick one of them.
+/// The one [`search_in`] selects goes first, see [`SelectionStrategy::rank`]
+pub fn search_all_in(
+    processes: &ProcessTree,
+    active_pid: Pid,
+    config: &Config,
+) -> Result<Vec<SearchResult>> {
+    let strategy = config.selection.strategy(config)?;
+    let matches = strategy.rank(matches_in(processes, active_pid, config)?.collect());
+
+    let mut results = Vec::new();
+    for matched in matches {
+        results.extend(matched.resolve(config)?);
+    }
+    Ok(results)
+}
+
+/// Asks `provider` for active window, fails if it doesn't reply within `wm_timeout` of config
+#[cfg(feature = "wm")]
+pub async fn request_active_pid(
+    provider: &impl ActiveWindowProvider,
+    config: &Config,
+) -> Result<Option<Pid>> {
+    let timeout = Duration::from_millis(config.wm_timeout);
+    let active_pid = async { provider.active_pid().await.map_err(Error::WmUnavailable) };
+    let expired = async {
+        Timer::after(timeout).await;
+        Err(Error::WmTimeout(timeout))
+    };
+    future::or(active_pid, expired)
+        .instrument(tracing::debug_span!("window manager"))
+        .await
+}
+
+/// Asks `provider` for active window unless `active_pid` is given while /proc is being scanned,
+/// `None` if no window is focused
+///
+/// Only window manager IPC is awaited, /proc is scanned on a thread of [`blocking`] pool, so it
+/// works with any async runtime. With [`Config::scan_subtree`] active window is requested first
+#[cfg(feature = "wm")]
+pub async fn scan(
+    mut active_pid: Option<Pid>,
+    provider: &impl ActiveWindowProvider,
+    config: &Config,
+) -> Result<Option<(Pid, ProcessTree)>> {
+    let options = config.scan_options();
+    if active_pid.is_none() && options.subtree {
+        active_pid = request_active_pid(provider, config).await?;
+        tracing::debug!(?active_pid, "got active window");
+        if active_pid.is_none() {
             return Ok(None);
-        };
+        }
+    }
+    let Some(active_pid) = active_pid else {
+        let processes = blocking::unblock(move || process::build_process_tree_with(&options));
+        let (active_pid, processes) =
+            future::zip(request_active_pid(provider, config), processes).await;
+        let processes = processes.map_err(Error::ProcessTree)?;
+        let active_pid = active_pid?;
+        tracing::debug!(?active_pid, "got active window");
+        return Ok(active_pid.map(|active_pid| (active_pid, processes)));
+    };
+
+    let processes =
+        blocking::unblock(move || process::build_process_tree_from(active_pid, &options))
+            .await
+            .map_err(Error::ProcessTree)?;
+    Ok(Some((active_pid, processes)))
+}
 
-        active_client.pid
+#[cfg(feature = "wm")]
+pub async fn search(
+    active_pid: Option<Pid>,
+    provider: &impl ActiveWindowProvider,
+    config: &Config,
+) -> Result<Option<SearchResult>> {
+    match scan(active_pid, provider, config).await? {
+        Some((active_pid, processes)) => search_in(&processes, active_pid, config),
+        None => Ok(None),
+    }
+}
+
+/// Same as [`search`] but process tree of `cache` is refreshed instead of being built from
+/// scratch, e.g. by an embedder answering many queries. It's refreshed on a thread of
+/// [`blocking`] pool while window manager is asked for active window
+#[cfg(feature = "wm")]
+pub async fn search_cached(
+    active_pid: Option<Pid>,
+    provider: &impl ActiveWindowProvider,
+    cache: &mut ProcessCache,
+    config: &Config,
+) -> Result<Option<SearchResult>> {
+    // cache is left empty if refresh fails, so the next one starts from scratch
+    let mut taken = mem::take(cache);
+    let refreshed = blocking::unblock(move || taken.refresh().map(|()| taken));
+    let (active_pid, refreshed) = match active_pid {
+        Some(active_pid) => (Ok(Some(active_pid)), refreshed.await),
+        None => future::zip(request_active_pid(provider, config), refreshed).await,
     };
+    *cache = refreshed.map_err(Error::ProcessTree)?;
+    match active_pid? {
+        Some(active_pid) => search_in(cache.processes(), active_pid, config),
+        None => Ok(None),
+    }
+}
 
-    let root = processes.get(&active_pid).context("process not found")?;
-    let mut walker = Walker::with_capacity(root, &processes, BFS_HEAP_CAPACITY);
-    let mut location_search = LocationSearch::new();
-    _ = walker.bfs(|node| location_search.handle_node(node));
-    let selected_proc = location_search.select();
+/// Same as [`search`] but for a specific Hyprland window, `address` is in hex as reported by
+/// `hyprctl clients` with or without `0x` prefix
+#[cfg(feature = "hyprland")]
+pub async fn search_by_window_address(
+    address: &str,
+    hyprland: &wm::hyprland::Hyprland,
+    config: &Config,
+) -> Result<Option<SearchResult>> {
+    let window = wm::hyprland::Window::new(hyprland.clone(), address);
+    search(None, &window, config).await
+}
 
-    let Some(selected_proc) = selected_proc else {
-        return Ok(None);
+/// Same as [`scan`] but window manager is asked with blocking IPC on another thread, so no async
+/// runtime is required. The thread is left behind if window manager doesn't reply in time
+#[cfg(feature = "wm")]
+pub fn scan_blocking(
+    active_pid: Option<Pid>,
+    wm: WindowManager,
+    config: &Config,
+) -> Result<Option<(Pid, ProcessTree)>> {
+    future::block_on(scan(active_pid, &wm::BlockingIpc(wm), config))
+}
+
+/// Same as [`search`] but blocking, see [`scan_blocking`]
+#[cfg(feature = "wm")]
+pub fn search_blocking(
+    active_pid: Option<Pid>,
+    wm: WindowManager,
+    config: &Config,
+) -> Result<Option<SearchResult>> {
+    match scan_blocking(active_pid, wm, config)? {
+        Some((active_pid, processes)) => search_in(&processes, active_pid, config),
+        None => Ok(None),
+    }
+}
+
+/// [`search_blocking`] with window manager detected from environment, or [`search_sync`] without
+/// `wm` feature where `active_pid` is required. For bindings built with or without it
+#[cfg(feature = "procfs")]
+pub fn search_detected(active_pid: Option<Pid>, config: &Config) -> Result<Option<SearchResult>> {
+    #[cfg(feature = "wm")]
+    return search_blocking(active_pid, WindowManager::default(), config);
+    #[cfg(not(feature = "wm"))]
+    match active_pid {
+        Some(active_pid) => search_sync(active_pid, config),
+        None => Err(Error::WmUnavailable(anyhow::anyhow!(
+            "`active_pid` is required without `wm` feature"
+        ))),
+    }
+}
+
+/// Same as [`search`] but without querying window manager, so no async runtime is required
+#[cfg(feature = "procfs")]
+pub fn search_sync(active_pid: Pid, config: &Config) -> Result<Option<SearchResult>> {
+    let processes = process::build_process_tree_from(active_pid, &config.scan_options())
+        .map_err(Error::ProcessTree)?;
+    search_in(&processes, active_pid, config)
+}
+
+/// Seconds since UNIX epoch
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+/// Reads location found by [`search`], fallback location is returned if nothing is found.
+/// Working directory of the process is returned if its registry entry is absent or expired, see
+/// [`Config::cwd_fallback`]. Project root is found if it's not written
+#[cfg(feature = "procfs")]
+pub fn read_location(
+    resolved: Option<Resolved>,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<LocationData> {
+    let data = tracing::debug_span!("registry read")
+        .in_scope(|| read_resolved(resolved, registry, config))?;
+    let _span = tracing::debug_span!("project root").entered();
+    Ok(data.with_project_root(&config.project_markers))
+}
+
+#[cfg(feature = "procfs")]
+fn read_resolved(
+    resolved: Option<Resolved>,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<LocationData> {
+    let entry = match resolved {
+        Some(Resolved::Registry(entry)) => entry,
+        Some(Resolved::Location(data)) => return Ok(data),
+        None => return Ok(config.fallback()),
     };
 
-    let path = build_path(selected_proc.pid, &selected_proc.name);
-    Ok(path.into())
+    let Some((found, data)) = read_entry(&entry, registry)? else {
+        tracing::debug!(
+            pid = entry.pid,
+            name = entry.name,
+            "registry entry is absent"
+        );
+        return Ok(unwritten_fallback(&entry, config));
+    };
+    let expired = is_expired(&data, config);
+    // start time doesn't depend on PID namespace, so it's checked against the host pid
+    if expired || !data.is_written_by(entry.pid) {
+        tracing::debug!(
+            pid = found.pid,
+            name = found.name,
+            expired,
+            "registry entry is stale"
+        );
+        // the process is likely dead, it's fine if removing fails. Entry written from container
+        // is left since its key may be reused by another host process
+        if found == entry {
+            _ = registry.remove(&found);
+        }
+        return Ok(unwritten_fallback(&entry, config));
+    }
+
+    Ok(data)
 }
 
-#[allow(dead_code)]
-pub async fn get(active_pid: Option<Pid>) -> anyhow::Result<LocationData> {
-    let Some(path) = search(active_pid).await? else {
-        return Ok(LocationData::fallback());
+/// Whether `data` is older than [`Config::entry_ttl`]
+#[cfg(feature = "procfs")]
+fn is_expired(data: &LocationData, config: &Config) -> bool {
+    config
+        .entry_ttl
+        .is_some_and(|ttl| data.is_expired(Duration::from_secs(ttl)))
+}
+
+/// Reads `entry` or the one written by the same process from inside of a container, i.e. with its
+/// pid in nested PID namespace, see [`ProcessInfo::ns_pid`]
+#[cfg(feature = "procfs")]
+fn read_entry(
+    entry: &RegistryEntry,
+    registry: &impl LocationRegistry,
+) -> Result<Option<(RegistryEntry, LocationData)>> {
+    let read = registry.read(entry);
+    if let Some(data) = read.map_err(|err| Error::registry(entry, err))? {
+        return Ok(Some((entry.clone(), data)));
+    }
+
+    let Ok(Some(ns_pid)) = process::ns_pid(entry.pid) else {
+        return Ok(None);
     };
+    read_ns_entry(entry, ns_pid, registry)
+}
 
-    let file = match File::open(path) {
-        Ok(file) => file,
-        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(LocationData::fallback()),
-        Err(err) => return Err(anyhow!(err).context("open location file")),
+/// Entry written by process of `entry` from inside of a container under `ns_pid`. Another host
+/// process may have the same pid, so the entry is accepted only if start time of the process is
+/// written to it
+#[cfg(feature = "procfs")]
+fn read_ns_entry(
+    entry: &RegistryEntry,
+    ns_pid: Pid,
+    registry: &impl LocationRegistry,
+) -> Result<Option<(RegistryEntry, LocationData)>> {
+    let ns_entry = RegistryEntry {
+        pid: ns_pid,
+        name: entry.name.clone(),
+    };
+    let read = registry.read(&ns_entry);
+    let Some(data) = read.map_err(|err| Error::registry(&ns_entry, err))? else {
+        return Ok(None);
     };
+    let start_time = process::start_time(entry.pid).ok();
+    if data.start_time.is_none() || data.start_time != start_time {
+        tracing::debug!(
+            pid = entry.pid,
+            ns_pid,
+            "entry is written by another process with the same pid"
+        );
+        return Ok(None);
+    }
+
+    tracing::debug!(pid = entry.pid, ns_pid, "use entry written from container");
+    Ok(Some((ns_entry, data)))
+}
 
+/// Location of process which hasn't written it, see [`Config::cwd_fallback`]
+#[cfg(feature = "procfs")]
+fn unwritten_fallback(entry: &RegistryEntry, config: &Config) -> LocationData {
+    if !config.cwd_fallback {
+        return config.fallback();
+    }
+    match process::cwd(entry.pid) {
+        Ok(cwd) => {
+            tracing::debug!(pid = entry.pid, name = entry.name, "use working directory");
+            LocationData {
+                cwd_fallback: true,
+                ..LocationData::new(cwd)
+            }
+        }
+        Err(err) => {
+            tracing::debug!(pid = entry.pid, "{err:#}");
+            config.fallback()
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[cfg(feature = "wm")]
+pub async fn get(
+    active_pid: Option<Pid>,
+    provider: &impl ActiveWindowProvider,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<LocationData> {
+    let result = search(active_pid, provider, config).await?;
     // Blocking executor but it's fine here
-    let data: LocationData =
-        serde_json::from_reader(file).context("deserialize + write to file")?;
-    Ok(data)
+    read_location(result.map(|result| result.resolved), registry, config)
+}
+
+/// Same as [`get`] but with process tree of `cache`, see [`search_cached`]
+#[cfg(feature = "wm")]
+pub async fn get_cached(
+    active_pid: Option<Pid>,
+    provider: &impl ActiveWindowProvider,
+    cache: &mut ProcessCache,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<LocationData> {
+    let result = search_cached(active_pid, provider, cache, config).await?;
+    read_location(result.map(|result| result.resolved), registry, config)
+}
+
+/// Same as [`get`] but blocking, see [`scan_blocking`]
+#[cfg(feature = "wm")]
+pub fn get_blocking(
+    active_pid: Option<Pid>,
+    wm: WindowManager,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<LocationData> {
+    let result = search_blocking(active_pid, wm, config)?;
+    read_location(result.map(|result| result.resolved), registry, config)
+}
+
+/// Same as [`get`] but without querying window manager, so no async runtime is required
+#[cfg(feature = "procfs")]
+pub fn get_sync(
+    active_pid: Pid,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<LocationData> {
+    let result = search_sync(active_pid, config)?;
+    read_location(result.map(|result| result.resolved), registry, config)
+}
+
+/// Same as [`get`] but with [`search_detected`]
+#[cfg(feature = "procfs")]
+pub fn get_detected(
+    active_pid: Option<Pid>,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<LocationData> {
+    let result = search_detected(active_pid, config)?;
+    read_location(result.map(|result| result.resolved), registry, config)
 }
 
+/// Writes `data` for every process of `pids` with its start time, nothing is written if one of
+/// them isn't running
+#[cfg(feature = "procfs")]
 pub fn write(
     name: String,
     pids: Vec<Pid>,
-    location: PathBuf,
-    nvim_pipe: Option<String>,
-) -> anyhow::Result<()> {
+    data: LocationData,
+    registry: &impl LocationRegistry,
+) -> Result<()> {
     let data = LocationData {
-        location,
-        nvim_pipe,
         fallback: None,
+        cwd_fallback: false,
+        written_at: unix_time().into(),
+        kind: data.kind.or_else(|| LocationKind::detect(&data.location)),
+        ..data
     };
-
-    fs::create_dir_all(LOCATIONS_PATH.as_path()).context("create location dir")?;
-    fs::set_permissions(LOCATIONS_PATH.as_path(), fs::Permissions::from_mode(0o700))
-        .context("set permissions for location registry")?;
-
-    for pid in pids {
-        let path = build_path(pid, &name);
-        let file = File::options()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(path)
-            .context("open location file")?;
-        file.metadata()
-            .context("access file's metadata")?
-            .permissions()
-            .set_mode(0o600);
+    // all pids are checked first, so nothing is written if one of them is dead
+    let start_times = pids
+        .iter()
+        .map(|&pid| {
+            process::start_time(pid).map_err(|err| {
+                if process::is_not_found(&err) {
+                    Error::ProcessNotFound(pid)
+                } else {
+                    Error::ProcessTree(err)
+                }
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (pid, start_time) in pids.into_iter().zip(start_times) {
+        let data = LocationData {
+            start_time: start_time.into(),
+            ..data.clone()
+        };
 
         // Blocking executor but it's fine here
-        serde_json::to_writer(file, &data).context("serialize + parse to file")?;
+        registry.write(&name, &[pid], &data)?;
+    }
+
+    Ok(())
+}
+
+/// Locations written by the process, the most recent first. Empty if the entry is written by a
+/// dead process with the same pid
+#[cfg(feature = "procfs")]
+pub fn history(
+    entry: &RegistryEntry,
+    registry: &impl LocationRegistry,
+) -> Result<Vec<LocationData>> {
+    let history = registry
+        .history(entry)
+        .map_err(|err| Error::registry(entry, err))?;
+    if history
+        .first()
+        .is_some_and(|data| !data.is_written_by(entry.pid))
+    {
+        return Ok(vec![]);
     }
+    Ok(history)
+}
+
+/// Entries of running processes which aren't expired, as [`read_location`] would return them.
+/// Broken entries are logged and skipped, as well as ones written from containers
+#[cfg(feature = "procfs")]
+pub fn entries(
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> Result<Vec<(RegistryEntry, LocationData)>> {
+    let entries = registry
+        .iter()?
+        .filter_map(|entry| {
+            entry
+                .inspect_err(|err| tracing::warn!("skip registry entry: {err:#}"))
+                .ok()
+        })
+        .filter(|(entry, data)| data.is_written_by(entry.pid) && !is_expired(data, config))
+        .map(|(entry, data)| (entry, data.with_project_root(&config.project_markers)))
+        .collect();
+    Ok(entries)
+}
 
+/// Removes entries written by `write` with the same `name`
+pub fn remove(name: &str, pids: &[Pid], registry: &impl LocationRegistry) -> Result<()> {
+    for &pid in pids {
+        let entry = RegistryEntry {
+            pid,
+            name: name.to_owned(),
+        };
+        registry.remove(&entry)?;
+    }
     Ok(())
 }
 
-pub fn clear() -> anyhow::Result<()> {
-    match fs::remove_dir_all(LOCATIONS_PATH.as_path()) {
-        Ok(()) => Ok(()),
-        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
-        Err(e) => Err(e.into()),
+/// Removes entries of dead processes and expired ones, returns number of removed entries.
+/// Entries written from containers are kept while their processes are running
+#[cfg(feature = "procfs")]
+pub fn prune(registry: &impl LocationRegistry, config: &Config) -> Result<usize> {
+    let ttl = config.entry_ttl.map(Duration::from_secs);
+    let mut namespaced = None;
+    let stale: Vec<_> = registry
+        .iter()?
+        .filter_map(|entry| {
+            // broken entry is left for `clear` since it may be written by a newer version
+            let (entry, data) = entry.ok()?;
+            let alive = match process::start_time(entry.pid) {
+                Ok(start_time) => data.start_time.is_none_or(|written| written == start_time),
+                Err(_) => false,
+            };
+            // pid of a dead host process may belong to a running process in a container
+            let alive = alive
+                || data.start_time.is_some_and(|start_time| {
+                    namespaced
+                        .get_or_insert_with(|| {
+                            // start times identify processes in containers
+                            let options = process::ScanOptions {
+                                read_start_time: true,
+                                ..process::ScanOptions::default()
+                            };
+                            process::build_process_tree_with(&options)
+                                .map(|processes| process::namespaced_pids(&processes))
+                                .unwrap_or_default()
+                        })
+                        .contains(&(entry.pid, start_time))
+                });
+            let expired = ttl.is_some_and(|ttl| data.is_expired(ttl));
+            (!alive || expired).then_some(entry)
+        })
+        .collect();
+
+    for entry in &stale {
+        registry
+            .remove(entry)
+            .context("remove entry")
+            .map_err(Error::RegistryIo)?;
+    }
+    Ok(stale.len())
+}
+
+/// Upgrades entries written by previous versions, returns number of upgraded entries
+pub fn migrate(registry: &impl LocationRegistry) -> Result<usize> {
+    Ok(registry.migrate()?)
+}
+
+pub fn clear(registry: &impl LocationRegistry) -> Result<()> {
+    Ok(registry.clear()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::process::ProcessTree;
+    use crate::process::fixture::TreeBuilder;
+    use crate::walk::Node;
+    use crate::{Selection, matches_in, search_in};
+
+    /// kitty (1) -> zsh (2) -> [htop (3), zsh (4) -> nvim (5)]
+    fn processes() -> ProcessTree {
+        TreeBuilder::new("kitty")
+            .child("zsh")
+            .child("htop")
+            .up()
+            .child("zsh")
+            .child("nvim")
+            .build()
+    }
+
+    #[test]
+    fn search_result() {
+        let mut config = Config {
+            known_procs: vec!["zsh".to_owned()],
+            ..Config::default()
+        };
+
+        let result = search_in(&processes(), 1, &config).unwrap().unwrap();
+        assert_eq!(result.process.pid, 4);
+        assert_eq!((result.depth, result.sibling_no), (2, 1));
+        assert_eq!(result.entry().unwrap().name, "zsh");
+        assert_eq!(result.registry_path, Some(config.registry_path.clone()));
+
+        config.selection = Selection::Shallowest;
+        let result = search_in(&processes(), 1, &config).unwrap().unwrap();
+        assert_eq!(result.process.pid, 2);
+        assert_eq!(result.depth, 1);
+
+        config.known_procs = vec!["fish".to_owned()];
+        assert!(search_in(&processes(), 1, &config).unwrap().is_none());
+    }
+
+    #[test]
+    fn matches() {
+        let config = Config {
+            known_procs: vec!["zsh".to_owned(), "nvim".to_owned()],
+            max_depth: Some(2),
+            ..Config::default()
+        };
+        let processes = processes();
+
+        let pids: Vec<_> = matches_in(&processes, 1, &config)
+            .unwrap()
+            .map(|matched| matched.process.pid)
+            .collect();
+        assert_eq!(pids, [2, 4]);
+
+        let first = matches_in(&processes, 1, &config).unwrap().next().unwrap();
+        assert_eq!((first.process.pid, first.depth), (2, 1));
+        assert!(matches_in(&processes, 6, &config).is_err());
+    }
+
+    #[test]
+    fn other_users() {
+        let mut config = Config {
+            known_procs: vec!["zsh".to_owned()],
+            ..Config::default()
+        };
+        let mut processes = processes();
+        let uid = nix::unistd::geteuid().as_raw();
+        // e.g. root shell of `sudo -i`
+        processes.get_mut(&4).unwrap().data_mut().uid = Some(uid + 1);
+        processes.get_mut(&2).unwrap().data_mut().uid = Some(uid);
+
+        let result = search_in(&processes, 1, &config).unwrap().unwrap();
+        assert_eq!(result.process.pid, 2);
+
+        config.other_users = true;
+        let result = search_in(&processes, 1, &config).unwrap().unwrap();
+        assert_eq!(result.process.pid, 4);
+
+        // subtree mode skips them too
+        #[cfg(feature = "procfs")]
+        {
+            use crate::process::{Pid, build_subtree};
+
+            config.other_users = false;
+            config.scan_subtree = true;
+            let mut options = config.scan_options();
+            let mut child = std::process::Command::new("sleep")
+                .arg("10")
+                .spawn()
+                .unwrap();
+            let pid = std::process::id() as Pid;
+            let scanned = build_subtree(pid, &options);
+            options.uid = options.uid.map(|uid| uid.wrapping_add(1));
+            let skipped = build_subtree(pid, &options);
+            child.kill().unwrap();
+            child.wait().unwrap();
+
+            assert!(scanned.unwrap().contains_key(&(child.id() as Pid)));
+            assert!(skipped.is_err());
+        }
+    }
+
+    #[cfg(feature = "procfs")]
+    #[test]
+    fn ns_entry() {
+        use crate::LocationData;
+        use crate::process::{self, Pid};
+        use crate::read_ns_entry;
+        use crate::registry::memory::MemoryRegistry;
+        use crate::registry::{LocationRegistry, RegistryEntry};
+
+        let pid: Pid = std::process::id().try_into().unwrap();
+        let entry = RegistryEntry {
+            pid,
+            name: "cargo".to_owned(),
+        };
+        // init has the same pid in host namespace as this process has in a container
+        let ns_pid = 1;
+        let written = |start_time| LocationData {
+            start_time,
+            ..LocationData::new("/tmp".into())
+        };
+        let registry = MemoryRegistry::new();
+
+        let other = written(process::start_time(ns_pid).ok());
+        registry.write("cargo", &[ns_pid], &other).unwrap();
+        assert!(read_ns_entry(&entry, ns_pid, &registry).unwrap().is_none());
+        registry.write("cargo", &[ns_pid], &written(None)).unwrap();
+        assert!(read_ns_entry(&entry, ns_pid, &registry).unwrap().is_none());
+
+        let own = written(process::start_time(pid).ok());
+        registry.write("cargo", &[ns_pid], &own).unwrap();
+        let (found, _) = read_ns_entry(&entry, ns_pid, &registry).unwrap().unwrap();
+        assert_eq!(found.pid, ns_pid);
+    }
+
+    #[cfg(feature = "procfs")]
+    #[test]
+    fn cwd_fallback() {
+        use crate::read_location;
+        use crate::registry::RegistryEntry;
+        use crate::registry::memory::MemoryRegistry;
+        use crate::resolver::Resolved;
+
+        let entry = RegistryEntry {
+            pid: std::process::id().try_into().unwrap(),
+            name: "cargo".to_owned(),
+        };
+        let resolved = || Some(Resolved::Registry(entry.clone()));
+        let mut config = Config::default();
+
+        let data = read_location(resolved(), &MemoryRegistry::new(), &config).unwrap();
+        assert_eq!(data.location(), std::env::current_dir().unwrap());
+        assert!(data.is_cwd_fallback() && !data.is_fallback());
+        assert!(data.is_any_fallback());
+
+        config.cwd_fallback = false;
+        let data = read_location(resolved(), &MemoryRegistry::new(), &config).unwrap();
+        assert!(data.is_fallback() && !data.is_cwd_fallback());
+        assert!(data.is_any_fallback());
+    }
+
+    #[cfg(feature = "procfs")]
+    #[test]
+    fn entries() {
+        use crate::registry::LocationRegistry;
+        use crate::registry::memory::MemoryRegistry;
+        use crate::{LocationData, process, unix_time};
+
+        let pid = std::process::id().try_into().unwrap();
+        let written = |start_time, written_at| LocationData {
+            start_time,
+            written_at: Some(written_at),
+            ..LocationData::new("/tmp".into())
+        };
+        let config = Config {
+            entry_ttl: Some(60),
+            ..Config::default()
+        };
+        let registry = MemoryRegistry::new();
+        let own = || process::start_time(pid).ok();
+        registry
+            .write("cargo", &[pid], &written(own(), unix_time()))
+            .unwrap();
+        registry
+            .write("expired", &[pid], &written(own(), 0))
+            .unwrap();
+        // written by a process with the same pid in container
+        let other = own().map(|start_time| start_time + 1);
+        registry
+            .write("other", &[pid], &written(other, unix_time()))
+            .unwrap();
+
+        let entries = crate::entries(&registry, &config).unwrap();
+        let names: Vec<_> = entries
+            .iter()
+            .map(|(entry, _)| entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["cargo"]);
+    }
+
+    #[cfg(feature = "procfs")]
+    #[test]
+    fn window() {
+        use crate::{LocationData, detect_window, process};
+
+        let pid = std::process::id().try_into().unwrap();
+        let parent = process::ppid(pid).unwrap();
+        // e.g. shell runs in terminal owning the window
+        let windows = [(parent, "0x5f3a".to_owned()), (1, "0x7b00".to_owned())];
+        let window = detect_window(pid, &windows);
+        assert_eq!(window.as_deref(), Some("0x5f3a"));
+        assert_eq!(detect_window(pid, &windows[1..]), None);
+
+        let data = LocationData::new("/tmp".into());
+        assert_eq!(data.is_window_closed(&windows), None);
+        let data = data.with_window(Some("5f3a".to_owned()));
+        assert_eq!(data.is_window_closed(&windows), Some(false));
+        assert_eq!(data.is_window_closed(&windows[1..]), Some(true));
+    }
+
+    #[cfg(feature = "procfs")]
+    #[test]
+    fn write_dead() {
+        use crate::LocationData;
+        use crate::error::Error;
+        use crate::process::Pid;
+        use crate::registry::LocationRegistry;
+        use crate::registry::memory::MemoryRegistry;
+
+        let pid = std::process::id().try_into().unwrap();
+        let registry = MemoryRegistry::new();
+        let data = LocationData::new("/tmp".into());
+        let err = crate::write("cargo".to_owned(), vec![pid, Pid::MAX], data, &registry);
+        assert!(matches!(err, Err(Error::ProcessNotFound(Pid::MAX))));
+        assert_eq!(registry.iter().unwrap().count(), 0);
     }
 }
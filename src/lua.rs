@@ -0,0 +1,181 @@
+//! Lua module `current_location`, e.g. `require("current_location").get()` in Neovim. Locations
+//! are tables of the same shape as JSON output
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use mlua::{Lua, LuaSerdeExt, Result as LuaResult, Table, Value};
+use serde::Deserialize;
+
+use crate::LocationData;
+use crate::config::Config;
+use crate::process::Pid;
+use crate::registry::LocationRegistry;
+
+/// Options of `get` and `search`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct QueryOpts {
+    /// Active window is queried if it's not set
+    active_pid: Option<Pid>,
+    config: Option<PathBuf>,
+}
+
+/// Options of `write` and `remove`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct WriteOpts {
+    config: Option<PathBuf>,
+    nvim_pipe: Option<String>,
+    line: Option<u32>,
+    column: Option<u32>,
+    window: Option<String>,
+    terminal: Option<String>,
+}
+
+fn error(err: impl Into<anyhow::Error>) -> mlua::Error {
+    mlua::Error::runtime(format!("{:#}", err.into()))
+}
+
+fn opts<T: Default + for<'de> Deserialize<'de>>(lua: &Lua, opts: Option<Value>) -> LuaResult<T> {
+    opts.map_or_else(|| Ok(T::default()), |opts| lua.from_value(opts))
+}
+
+fn load_config(path: Option<PathBuf>) -> LuaResult<Config> {
+    Config::load_from(path.as_deref())
+        .context("load config")
+        .map_err(error)
+}
+
+fn to_table(lua: &Lua, data: &LocationData) -> LuaResult<Value> {
+    let options = mlua::SerializeOptions::new().serialize_none_to_null(false);
+    lua.to_value_with(data, options)
+}
+
+fn registry(config: &Config) -> LuaResult<impl LocationRegistry> {
+    config.registry().map_err(error)
+}
+
+fn get(lua: &Lua, opts: Option<Value>) -> LuaResult<Value> {
+    let QueryOpts { active_pid, config } = self::opts(lua, opts)?;
+    let config = load_config(config)?;
+    let data = crate::get_detected(active_pid, &registry(&config)?, &config).map_err(error)?;
+    to_table(lua, &data)
+}
+
+fn search(lua: &Lua, opts: Option<Value>) -> LuaResult<Option<Table>> {
+    let QueryOpts { active_pid, config } = self::opts(lua, opts)?;
+    let config = load_config(config)?;
+    let Some(result) = crate::search_detected(active_pid, &config).map_err(error)? else {
+        return Ok(None);
+    };
+
+    // same location as `get` returns, stale entries are not trusted
+    let location =
+        crate::read_location(Some(result.resolved), &registry(&config)?, &config).map_err(error)?;
+    let table = lua.create_table()?;
+    table.set("pid", result.process.pid)?;
+    table.set("name", &*result.process.name)?;
+    table.set("depth", result.depth)?;
+    table.set("location", to_table(lua, &location)?)?;
+    Ok(Some(table))
+}
+
+/// Location written by `write` for `pid`, `location` is arbitrary bytes as paths are, so are Lua
+/// strings
+fn written_data(
+    location: &[u8],
+    pid: Option<Pid>,
+    opts: WriteOpts,
+    config: &Config,
+) -> LocationData {
+    let WriteOpts {
+        config: _,
+        nvim_pipe,
+        line,
+        column,
+        window,
+        terminal,
+    } = opts;
+    #[cfg(feature = "hyprland")]
+    let window = window.or_else(|| crate::detect_window(pid?, &crate::open_windows()?));
+    #[cfg(not(feature = "hyprland"))]
+    let _ = pid;
+    LocationData::new(PathBuf::from(OsStr::from_bytes(location)))
+        .with_nvim_pipe(nvim_pipe)
+        .with_cursor(line, column)
+        .with_window(window)
+        .with_terminal(terminal.or_else(crate::detect_terminal))
+        .with_project_root(&config.project_markers)
+}
+
+fn write(
+    lua: &Lua,
+    (name, pids, location, opts): (String, Vec<Pid>, mlua::String, Option<Value>),
+) -> LuaResult<()> {
+    let mut opts: WriteOpts = self::opts(lua, opts)?;
+    let config = load_config(opts.config.take())?;
+    let data = written_data(&location.as_bytes(), pids.first().copied(), opts, &config);
+    crate::write(name, pids, data, &registry(&config)?).map_err(error)
+}
+
+fn remove(lua: &Lua, (name, pids, opts): (String, Vec<Pid>, Option<Value>)) -> LuaResult<()> {
+    let WriteOpts { config, .. } = self::opts(lua, opts)?;
+    let config = load_config(config)?;
+    crate::remove(&name, &pids, &registry(&config)?).map_err(error)
+}
+
+#[mlua::lua_module]
+fn current_location(lua: &Lua) -> LuaResult<Table> {
+    let module = lua.create_table()?;
+    module.set("get", lua.create_function(get)?)?;
+    module.set("search", lua.create_function(search)?)?;
+    module.set("write", lua.create_function(write)?)?;
+    module.set("remove", lua.create_function(remove)?)?;
+    Ok(module)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use crate::config::Config;
+
+    use super::{QueryOpts, WriteOpts, written_data};
+
+    #[test]
+    fn opts() {
+        let opts: QueryOpts = serde_json::from_str(r#"{"active_pid": 42}"#).unwrap();
+        assert_eq!((opts.active_pid, opts.config), (Some(42), None));
+
+        let opts: WriteOpts = serde_json::from_str(r#"{"line": 3}"#).unwrap();
+        assert_eq!((opts.line, opts.column), (Some(3), None));
+    }
+
+    #[test]
+    fn write_data() {
+        let opts = WriteOpts {
+            nvim_pipe: Some("/run/nvim.sock".to_owned()),
+            line: Some(3),
+            column: Some(7),
+            terminal: Some("kitty".to_owned()),
+            ..WriteOpts::default()
+        };
+        let config = Config {
+            project_markers: Vec::new(),
+            ..Config::default()
+        };
+
+        // not valid UTF-8
+        let data = written_data(b"/srv/\xff", None, opts, &config);
+        assert_eq!(data.location(), Path::new(OsStr::from_bytes(b"/srv/\xff")));
+        assert_eq!(data.nvim_pipe(), Some("/run/nvim.sock"));
+        assert_eq!((data.line(), data.column()), (Some(3), Some(7)));
+        assert_eq!(data.terminal(), Some("kitty"));
+        assert_eq!(data.project_root(), None);
+    }
+}
@@ -1,29 +1,185 @@
-use std::fs::File;
-use std::io;
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{env, fs};
 
-use anyhow::{Context, anyhow};
-use clap::{Parser, Subcommand};
+use anyhow::Context;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::generate;
+use clap_complete_nushell::Nushell;
+use serde::Serialize;
+use tracing::{Subscriber, span};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::filter::{LevelFilter, filter_fn};
+use tracing_subscriber::layer::{self, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
 
-use current_location::{LocationData, process::Pid};
+#[cfg(feature = "tokio")]
+use current_location::config::ConfigReloader;
+use current_location::config::{self, Config};
+#[cfg(feature = "tokio")]
+use current_location::daemon;
+use current_location::doctor::{self, Finding, Severity};
+use current_location::error::ErrorCode;
+#[cfg(feature = "tokio")]
+use current_location::process::ProcessCache;
+use current_location::process::{self, Pid, ProcessTree};
+#[cfg(feature = "tokio")]
+use current_location::registry::watch::RegistryWatcher;
+use current_location::registry::{LocationRegistry, RegistryEntry, RegistryFormat, socket};
+use current_location::template::Template;
+#[cfg(feature = "wm")]
+use current_location::wm::WindowManager;
+#[cfg(feature = "tokio")]
+use current_location::wm::{hyprland, hyprland::Hyprland};
+use current_location::{LocationData, Selection};
+#[cfg(feature = "tokio")]
+use tokio::sync::Notify;
 
+const NVIM_INIT_SCRIPT: &str = include_str!("init/nvim.lua");
+
+/// `get` printed fallback location
+const FALLBACK_EXIT_CODE: u8 = 2;
+
+/// A tool that help to determine Current Working File of currently active window
 #[derive(Parser)]
 #[command(version)]
 struct Opts {
     /// Provides active pid which skips requesting it from window manager.
     ///
-    /// Use it if your window manager is not supported
+    /// Use it if your window manager is not supported. Required when built without `wm` feature
     #[arg(short, long, env = "CURRENT_LOCATION_ACTIVE_PID")]
     active_pid: Option<Pid>,
+    /// Window manager to request active window from
+    #[cfg(feature = "wm")]
+    #[arg(long, env = "CURRENT_LOCATION_WM", value_enum, default_value_t)]
+    wm: WindowManager,
+    /// Signature of Hyprland instance to query, implies Hyprland. Useful when multiple instances
+    /// are running
+    #[cfg(feature = "tokio")]
+    #[arg(long, env = "CURRENT_LOCATION_HYPRLAND_INSTANCE")]
+    hyprland_instance: Option<String>,
+    /// Path to config file, `$XDG_CONFIG_HOME/current-location/config.toml` by default
+    #[arg(long, env = "CURRENT_LOCATION_CONFIG")]
+    config: Option<PathBuf>,
+    /// Additional process name to look for, appended to `known_procs` of config. Can be repeated,
+    /// comma-separated in environment variable
+    #[arg(
+        long = "known-proc",
+        value_name = "NAME",
+        env = "CURRENT_LOCATION_KNOWN_PROCS",
+        value_delimiter = ','
+    )]
+    known_procs: Vec<String>,
+    /// Directory of Location Registry, overrides `registry_path` of config
+    #[arg(long, env = "CURRENT_LOCATION_REGISTRY")]
+    registry_path: Option<PathBuf>,
+    /// Format of written registry entries, overrides `registry_format` of config. Entries of any
+    /// format are read
+    #[arg(long, env = "CURRENT_LOCATION_REGISTRY_FORMAT", value_enum)]
+    registry_format: Option<RegistryFormat>,
+    /// Which of matched processes is selected, overrides `selection` of config
+    #[arg(long, env = "CURRENT_LOCATION_STRATEGY", value_enum)]
+    strategy: Option<Strategy>,
+    /// How errors are reported, `json` ones are printed to stdout for scripts to tell errors apart
+    #[arg(long, value_enum, default_value_t)]
+    errors: ErrorFormat,
+    /// Verbosity of logs printed to stderr: `error`, `warn` (default), `info`, `debug`, `trace` or
+    /// filter directives like `current_location::process=trace`. `RUST_LOG` is used if not set
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<String>,
     #[clap(subcommand)]
-    subcommand: Subcommands,
+    subcommand: Command,
 }
 
-/// A tool that help to determine Current Working File of currently active window
+/// Setup subcommands are split from the rest to run before config is loaded, so they work even
+/// if it's broken
+#[derive(Subcommand, Clone)]
+enum Command {
+    #[command(flatten)]
+    Configured(Subcommands),
+    #[command(flatten)]
+    Setup(SetupSubcommands),
+}
+
+/// Subcommands which need config
 #[derive(Subcommand, Clone)]
 enum Subcommands {
     /// Get location of currently active window
-    Get,
+    ///
+    /// Exits with code 2 if fallback location is printed because no location of a known process is
+    /// found, with 1 on errors
+    Get {
+        #[clap(flatten)]
+        output: Output,
+        /// Print every found location instead of only the preferred one, the preferred one goes
+        /// first. JSON output is an array of them with `pid` and `name` of their processes, other
+        /// formats print a line per location. Exits with code 2 if none is found
+        #[clap(long)]
+        all: bool,
+        /// Print durations of window manager request, /proc scan, process tree walk, resolving,
+        /// registry read, project root lookup, transforms and serialization to stderr. Window
+        /// manager is requested while /proc is scanned, so total is less than their sum
+        #[clap(long, conflicts_with = "all")]
+        timings: bool,
+        #[cfg(feature = "tokio")]
+        #[clap(flatten)]
+        selector: WindowSelector,
+        /// Ask running `daemon` instead of searching, falls back to searching if it isn't running
+        #[cfg(feature = "tokio")]
+        #[clap(
+            long,
+            conflicts_with_all = ["monitor", "window_class", "window_title", "timings", "all"]
+        )]
+        daemon: bool,
+    },
+    /// Get location of a specific process and its descendants without asking window manager, e.g.
+    /// from tmux hooks and scripts that know which process they care about
+    ///
+    /// Exits with code 2 if fallback location is printed, with 1 on errors
+    Query {
+        pid: Pid,
+        /// Print every found location, same as `get --all`
+        #[clap(long)]
+        all: bool,
+        #[clap(flatten)]
+        output: Output,
+    },
+    /// Print location of currently active window every time it changes, a line per change
+    ///
+    /// Changes are caught on focus change with Hyprland, on registry update with `fs` registry and
+    /// by polling otherwise. Errors are reported to stderr without stopping
+    #[cfg(feature = "tokio")]
+    Watch {
+        #[clap(flatten)]
+        output: Output,
+        /// Milliseconds between checks done regardless of events
+        #[clap(long, value_name = "MS", default_value_t = 1000)]
+        interval: u64,
+    },
+    /// Keep window manager connection and process tree in memory and answer `get --daemon`
+    /// queries over Unix socket, for callers running `get` often
+    #[cfg(feature = "tokio")]
+    Daemon {
+        /// Milliseconds between rebuilds of process tree done regardless of events
+        #[clap(long, value_name = "MS", default_value_t = 1000)]
+        refresh: u64,
+    },
+    #[command(flatten)]
+    Registry(RegistrySubcommands),
+    /// Check window manager connection, process scanning, Location Registry and hooks of known
+    /// processes, e.g. to find out why fallback location is printed
+    Doctor,
+}
+
+/// Subcommands reading or writing Location Registry, see [`run_registry`]
+#[derive(Subcommand, Clone)]
+enum RegistrySubcommands {
     /// Write location of a specific program to Location Registry
     Write {
         name: String,
@@ -32,52 +188,1189 @@ enum Subcommands {
         pids: Vec<Pid>,
         #[clap(long)]
         nvim_pipe: Option<String>,
+        /// Line of cursor in file, 1-based
+        #[clap(long)]
+        line: Option<u32>,
+        /// Column of cursor in file, 1-based
+        #[clap(long, requires = "line")]
+        column: Option<u32>,
+        /// Address of window manager window of the program, e.g. Hyprland window address. Window
+        /// of the first pid or of its nearest ancestor is detected on Hyprland by default
+        #[clap(long)]
+        window: Option<String>,
+        /// Terminal window or pane of the program, detected from environment variables set by
+        /// terminals and tmux by default
+        #[clap(long)]
+        terminal: Option<String>,
+        /// Additional data, e.g. `--meta branch=main`. Can be repeated
+        #[clap(long, value_name = "KEY=VALUE", value_parser = parse_meta)]
+        meta: Vec<(String, String)>,
+    },
+    /// List entries of Location Registry
+    List {
+        #[clap(long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
+    /// List previous locations of a specific program, the most recent first
+    ///
+    /// Only the current one is kept unless `history_size` is set in config, `sqlite` registry
+    /// keeps all of them
+    History {
+        name: String,
+        pid: Pid,
+        #[clap(long, value_enum, default_value_t)]
+        format: ListFormat,
+    },
+    /// Remove location of a specific program from Location Registry, e.g. on its exit
+    Remove {
+        name: String,
+        #[clap(required = true)]
+        pids: Vec<Pid>,
     },
+    /// Remove entries of dead processes and expired ones from Location Registry
+    Prune,
+    /// Upgrade entries written by previous versions of current-location and convert them to
+    /// `--registry-format` in place
+    Migrate,
     /// Clear Location Registry
     Clear,
+    /// Hold Location Registry in memory and serve it over Unix socket to `socket` registry backend
+    RegistryDaemon,
+}
+
+/// Subcommands printing or installing integrations, see [`run_setup`]
+#[derive(Subcommand, Clone)]
+enum SetupSubcommands {
+    /// Print shell code writing location of the shell on every directory change, e.g.
+    /// `eval "$(current-location shell-init zsh)"` in `~/.zshrc`
+    ShellInit { shell: Shell },
+    /// Print Lua code of Neovim writing location of the current buffer, e.g. to put it into
+    /// `plugin` directory of Neovim config
+    NvimInit {
+        /// Write it to `plugin/current-location.lua` of Neovim config instead, `NVIM_APPNAME` is
+        /// respected
+        #[clap(long)]
+        install: bool,
+        /// Overwrite the installed file if it differs, e.g. if it's edited or written by another
+        /// version
+        #[clap(long, requires = "install")]
+        force: bool,
+    },
+    /// Print completions of this command for a shell, e.g.
+    /// `current-location completions fish > ~/.config/fish/completions/current-location.fish`
+    Completions { shell: CompletionShell },
+    /// Print man page of this command in roff format, e.g.
+    /// `current-location man | gzip > current-location.1.gz`
+    Man {
+        /// Write pages of this command and every subcommand to the directory instead, e.g. for
+        /// packaging
+        #[clap(long, value_name = "DIR")]
+        out_dir: Option<PathBuf>,
+    },
+}
+
+/// How location data is printed by `get`
+#[derive(clap::Args, Clone)]
+struct Output {
+    #[clap(long, value_enum, default_value_t)]
+    format: OutputFormat,
+    /// Print fields of location data in custom format, e.g. `{location}[ --server {nvim_pipe}]`.
+    ///
+    /// Unset fields are empty, section in square brackets is printed only if all fields inside of
+    /// it are set. Fields: `location`, `nvim_pipe`, `fallback`, `written_at`, `start_time`, `kind`,
+    /// `line`, `column`, `project_root`, `window`, `terminal`, `extra.<key>`
+    #[clap(long, conflicts_with = "format")]
+    template: Option<Template>,
+    /// Resolve symlinks and `..` of location
+    #[clap(long)]
+    canonicalize: bool,
+    /// Print parent directory if location is a regular file
+    #[clap(long)]
+    dirname: bool,
+    /// Print root of git repository location is in
+    #[clap(long)]
+    git_root: bool,
+    /// Print location relative to this directory if it's inside of it, e.g. `$HOME`
+    #[clap(long, value_name = "BASE")]
+    relative_to: Option<PathBuf>,
+    /// Terminate output with NUL instead of newline, e.g. for `xargs -0`
+    #[clap(short = '0', long)]
+    print0: bool,
+}
+
+#[derive(clap::ValueEnum, Default, Copy, Clone)]
+enum OutputFormat {
+    /// The whole location data
+    #[default]
+    Json,
+    /// Only the path
+    Plain,
+    /// The path quoted for shell, e.g. for `eval "cd $(current-location get --format shell)"`
+    Shell,
+}
+
+#[derive(clap::ValueEnum, Copy, Clone)]
+enum Shell {
+    Zsh,
+    /// Requires `bash` in `known_procs` of config
+    Bash,
+    /// Requires `fish` in `known_procs` of config
+    Fish,
+}
+
+impl Shell {
+    fn init_script(self) -> &'static str {
+        match self {
+            Self::Zsh => include_str!("init/zsh.zsh"),
+            Self::Bash => include_str!("init/bash.bash"),
+            Self::Fish => include_str!("init/fish.fish"),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Copy, Clone)]
+enum CompletionShell {
+    Bash,
+    Elvish,
+    Fish,
+    Nushell,
+    Powershell,
+    Zsh,
+}
+
+/// [`Selection`] of command line
+#[derive(clap::ValueEnum, Copy, Clone, Debug)]
+enum Strategy {
+    /// The deepest one of the highest priority
+    Deepest,
+    /// The nearest one of the highest priority to window
+    #[value(alias = "nearest-to-root")]
+    Shallowest,
+    /// The last found one regardless of priority
+    LastMatch,
+    /// The most recently started one of the highest priority
+    Newest,
+    /// The one of the highest priority that has written its location last
+    MostRecent,
+}
+
+impl From<Strategy> for Selection {
+    fn from(strategy: Strategy) -> Self {
+        match strategy {
+            Strategy::Deepest => Self::Deepest,
+            Strategy::Shallowest => Self::Shallowest,
+            Strategy::LastMatch => Self::LastMatch,
+            Strategy::Newest => Self::Newest,
+            Strategy::MostRecent => Self::MostRecent,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Default, Copy, Clone)]
+enum ErrorFormat {
+    /// Human readable, to stderr
+    #[default]
+    Text,
+    /// JSON object with error code and context chain, to stdout
+    Json,
+}
+
+/// Error in JSON format
+#[derive(Serialize)]
+struct JsonError {
+    code: ErrorCode,
+    /// The whole context chain in a single line
+    message: String,
+    /// Context chain from the outermost one to the root cause
+    chain: Vec<String>,
+}
+
+#[derive(clap::ValueEnum, Default, Copy, Clone)]
+enum ListFormat {
+    #[default]
+    Table,
+    /// JSON array of entries
+    Json,
+}
+
+/// Entry of `list` output in JSON format
+#[derive(Serialize)]
+struct ListEntry {
+    #[serde(flatten)]
+    entry: RegistryEntry,
+    #[serde(flatten)]
+    data: LocationData,
+    /// Seconds since entry is written
+    age: Option<u64>,
+    /// Whether window of the entry is closed, absent if it's unknown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    window_closed: Option<bool>,
+}
+
+/// Entry of `history` output in JSON format
+#[derive(Serialize)]
+struct HistoryEntry {
+    #[serde(flatten)]
+    data: LocationData,
+    /// Seconds since location is written
+    age: Option<u64>,
 }
 
-async fn print_location(active_pid: Option<Pid>) -> anyhow::Result<()> {
-    let stdout = io::stdout();
-    let mut stdout_lock = stdout.lock();
-    let Some(path) = current_location::search(active_pid).await? else {
-        return serde_json::to_writer(stdout_lock, &LocationData::fallback())
-            .context("write fallback location data to stdout");
+/// Selects window other than the focused one, Hyprland only
+#[cfg(feature = "tokio")]
+#[derive(clap::Args, Clone)]
+#[command(next_help_heading = "Window selection (Hyprland only)")]
+struct WindowSelector {
+    /// Take active window of this monitor
+    #[clap(long, conflicts_with_all = ["window_class", "window_title"])]
+    monitor: Option<String>,
+    /// Take window with exactly this class, most recently focused one if there are several
+    #[clap(long)]
+    window_class: Option<String>,
+    /// Take window which title contains this string, most recently focused one if there are
+    /// several
+    #[clap(long)]
+    window_title: Option<String>,
+}
+
+#[cfg(feature = "tokio")]
+async fn scan_processes(
+    active_pid: Option<Pid>,
+    wm: WindowManager,
+    hyprland_instance: Option<String>,
+    selector: WindowSelector,
+    config: &Config,
+) -> anyhow::Result<Option<(Pid, ProcessTree)>> {
+    let hyprland = match &hyprland_instance {
+        Some(signature) => Hyprland::with_instance(signature.clone())?,
+        None => Hyprland::default(),
+    };
+
+    let WindowSelector {
+        monitor,
+        window_class,
+        window_title,
+    } = selector;
+
+    // Hyprland IPC would fail with a confusing error under another window manager
+    let selects = monitor.is_some() || window_class.is_some() || window_title.is_some();
+    if selects && hyprland_instance.is_none() {
+        let wm = match wm {
+            WindowManager::Auto => WindowManager::detect().context(ErrorCode::WindowManager)?,
+            wm => wm,
+        };
+        anyhow::ensure!(
+            wm == WindowManager::Hyprland,
+            "`--monitor`, `--window-class` and `--window-title` work only with Hyprland, not {wm:?}"
+        );
+    }
+
+    let scanned = if let Some(name) = monitor {
+        let monitor = hyprland::Monitor { hyprland, name };
+        current_location::scan(active_pid, &monitor, config).await
+    } else if window_class.is_some() || window_title.is_some() {
+        let window = hyprland::WindowMatch {
+            hyprland,
+            class: window_class,
+            title: window_title,
+        };
+        current_location::scan(active_pid, &window, config).await
+    } else if hyprland_instance.is_some() {
+        current_location::scan(active_pid, &hyprland, config).await
+    } else {
+        current_location::scan(active_pid, &wm, config).await
+    };
+    Ok(scanned?)
+}
+
+/// Scans process tree of `--active-pid`, it's required without window manager support
+#[cfg(not(feature = "wm"))]
+fn scan_active_pid(
+    active_pid: Option<Pid>,
+    config: &Config,
+) -> anyhow::Result<Option<(Pid, ProcessTree)>> {
+    let active_pid = active_pid
+        .context("`--active-pid` is required, window manager support is disabled at build time")?;
+    let processes = scan_sync(active_pid, config)?;
+    Ok(Some((active_pid, processes)))
+}
+
+fn scan_sync(active_pid: Pid, config: &Config) -> anyhow::Result<ProcessTree> {
+    process::build_process_tree_from(active_pid, &config.scan_options())
+        .context(ErrorCode::ProcessTree)
+}
+
+/// Prints location found in process tree of `active_pid`, every found one if `all` is set.
+/// Fallback location is printed if there is no active window
+fn print_scanned(
+    scanned: Option<(Pid, ProcessTree)>,
+    all: bool,
+    output: Output,
+    config: &Config,
+) -> anyhow::Result<ExitCode> {
+    if all {
+        return print_all(scanned, output, config);
+    }
+
+    let resolved = match scanned {
+        Some((active_pid, processes)) => {
+            current_location::search_in(&processes, active_pid, config)?
+                .map(|result| result.resolved)
+        }
+        None => None,
     };
+    let data = current_location::read_location(resolved, &config.registry()?, config)?;
+    print_data(data, output)
+}
 
-    // should use `splice`
-    // https://doc.rust-lang.org/std/io/fn.copy.html#platform-specific-behavior
-    let mut file = match File::open(path) {
-        Ok(file) => file,
-        Err(err) if err.kind() == io::ErrorKind::NotFound => {
-            return serde_json::to_writer(stdout_lock, &LocationData::fallback())
-                .context("write fallback location data to stdout");
+/// Prints every found location, exits with code 2 if there are none
+fn print_all(
+    scanned: Option<(Pid, ProcessTree)>,
+    output: Output,
+    config: &Config,
+) -> anyhow::Result<ExitCode> {
+    let results = match scanned {
+        Some((active_pid, processes)) => {
+            current_location::search_all_in(&processes, active_pid, config)?
         }
-        Err(err) => return Err(anyhow!(err).context("open location file")),
+        None => Vec::new(),
     };
 
-    io::copy(&mut file, &mut stdout_lock).context("copy location file to stdout")?;
+    let registry = config.registry()?;
+    let mut entries = Vec::with_capacity(results.len());
+    for result in results {
+        let entry = RegistryEntry {
+            pid: result.process.pid,
+            name: result.process.name.to_string(),
+        };
+        let data = current_location::read_location(Some(result.resolved), &registry, config)?;
+        // process hasn't written its location yet or it's expired
+        if data.is_fallback() {
+            continue;
+        }
+        entries.push(ListEntry {
+            age: data.age().map(|age| age.as_secs()),
+            // found under the active window, so it's open
+            window_closed: None,
+            entry,
+            data: transform(data, &output)?,
+        });
+    }
+
+    let json = output.template.is_none() && matches!(output.format, OutputFormat::Json);
+    let mut stdout = io::stdout().lock();
+    if json {
+        serde_json::to_writer(&mut stdout, &entries).context("write locations to stdout")?;
+    } else {
+        for entry in &entries {
+            let mut line = render(&entry.data, &output)?;
+            line.push(if output.print0 { b'\0' } else { b'\n' });
+            stdout
+                .write_all(&line)
+                .context("write locations to stdout")?;
+        }
+    }
+    stdout.flush().context("write locations to stdout")?;
+
+    if entries.is_empty() {
+        Ok(ExitCode::from(FALLBACK_EXIT_CODE))
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn query_location(
+    pid: Pid,
+    all: bool,
+    output: Output,
+    config: &Config,
+) -> anyhow::Result<ExitCode> {
+    scan_sync(pid, config)
+        .and_then(|processes| print_scanned(Some((pid, processes)), all, output, config))
+        .with_context(|| format!("get location of {pid}"))
+}
+
+/// Durations of `get` stages, i.e. of spans of the library, printed to stderr with `--timings`
+#[derive(Clone, Default)]
+struct Timings(Arc<Mutex<Vec<Stage>>>);
+
+struct Stage {
+    name: &'static str,
+    start: Instant,
+    duration: Duration,
+}
+
+/// Start of a span, stored in its extensions
+struct SpanStart(Instant);
+
+impl Timings {
+    fn print(&self) {
+        let stages = self.0.lock().expect("timings mutex is poisoned");
+        for stage in stages.iter() {
+            eprintln!("{:<15} {:?}", stage.name, stage.duration);
+        }
+        // window manager is requested while /proc is scanned, so stages overlap
+        let start = stages.iter().map(|stage| stage.start).min();
+        let end = stages
+            .iter()
+            .map(|stage| stage.start + stage.duration)
+            .max();
+        if let (Some(start), Some(end)) = (start, end) {
+            eprintln!("{:<15} {:?}", "total", end - start);
+        }
+    }
+}
+
+impl<S> Layer<S> for Timings
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _: &span::Attributes<'_>, id: &span::Id, ctx: layer::Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: layer::Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(&SpanStart(start)) = span.extensions().get::<SpanStart>() else {
+            return;
+        };
+        let stage = Stage {
+            name: span.name(),
+            start,
+            duration: start.elapsed(),
+        };
+        self.0
+            .lock()
+            .expect("timings mutex is poisoned")
+            .push(stage);
+    }
+}
+
+fn print_data(data: LocationData, output: Output) -> anyhow::Result<ExitCode> {
+    let fallback = data.is_fallback();
+    let data = tracing::debug_span!("transforms").in_scope(|| transform(data, &output))?;
+    let mut line = tracing::debug_span!("serialization").in_scope(|| render(&data, &output))?;
+    let json = output.template.is_none() && matches!(output.format, OutputFormat::Json);
+    if output.print0 {
+        line.push(b'\0');
+    } else if !json {
+        // JSON has never been terminated by newline
+        line.push(b'\n');
+    }
+    io::stdout()
+        .lock()
+        .write_all(&line)
+        .context("write location to stdout")?;
+
+    if fallback {
+        Ok(ExitCode::from(FALLBACK_EXIT_CODE))
+    } else {
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+fn parse_meta(meta: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = meta.split_once('=').context("expected `KEY=VALUE`")?;
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Applies transforms of `output` in a fixed order
+fn transform(mut data: LocationData, output: &Output) -> anyhow::Result<LocationData> {
+    if output.canonicalize {
+        data = data.canonicalize()?;
+    }
+    if output.dirname {
+        data = data.dirname();
+    }
+    if output.git_root {
+        data = data.git_root();
+    }
+    if let Some(base) = &output.relative_to {
+        data = data.relative_to(base);
+    }
+    Ok(data)
+}
+
+/// Renders `data` in format of `output` without terminator
+fn render(data: &LocationData, output: &Output) -> anyhow::Result<Vec<u8>> {
+    let location = data.location().as_os_str().as_bytes();
+    let rendered = match (&output.template, output.format) {
+        (Some(template), _) => template.render(data).into_vec(),
+        (None, OutputFormat::Json) => {
+            serde_json::to_vec(data).context("serialize location data")?
+        }
+        (None, OutputFormat::Plain) => location.to_vec(),
+        (None, OutputFormat::Shell) => shell_quote(location),
+    };
+    Ok(rendered)
+}
+
+/// Wraps `arg` in single quotes, the only character special inside of them is `'` itself
+fn shell_quote(arg: &[u8]) -> Vec<u8> {
+    let mut quoted = Vec::with_capacity(arg.len() + 2);
+    quoted.push(b'\'');
+    for &byte in arg {
+        if byte == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// Tracks focus changes if window manager is Hyprland, the only one reporting them, other ones
+/// are polled
+#[cfg(feature = "tokio")]
+async fn start_tracker(
+    wm: WindowManager,
+    hyprland_instance: Option<String>,
+) -> anyhow::Result<Option<hyprland::ActiveWindowTracker>> {
+    let is_hyprland = hyprland_instance.is_some()
+        || wm == WindowManager::Hyprland
+        || (wm == WindowManager::Auto
+            && WindowManager::detect().is_ok_and(|wm| wm == WindowManager::Hyprland));
+    if !is_hyprland {
+        return Ok(None);
+    }
+
+    let hyprland = match hyprland_instance {
+        Some(signature) => Hyprland::with_instance(signature)?,
+        None => Hyprland::default(),
+    };
+    let tracker = hyprland::ActiveWindowTracker::start(hyprland).await?;
+    Ok(Some(tracker))
+}
+
+/// Config of long-running subcommands, it's reloaded on SIGHUP
+#[cfg(feature = "tokio")]
+fn start_reloader(path: Option<PathBuf>, overrides: Overrides) -> anyhow::Result<ConfigReloader> {
+    ConfigReloader::start(path, move |config| overrides.apply(config)).context(ErrorCode::Config)
+}
+
+#[cfg(feature = "tokio")]
+async fn run_daemon(
+    wm: WindowManager,
+    hyprland_instance: Option<String>,
+    refresh: Duration,
+    reloader: &ConfigReloader,
+) -> anyhow::Result<()> {
+    match start_tracker(wm, hyprland_instance).await? {
+        Some(tracker) => daemon::run(&tracker, Some(&tracker), reloader, refresh).await,
+        None => daemon::run(&wm, None, reloader, refresh).await,
+    }
+}
+
+/// Wakes [`watch_location`] on registry updates, the thread lives until the process exits
+#[cfg(feature = "tokio")]
+fn spawn_registry_watcher(path: PathBuf, changed: Arc<Notify>) -> anyhow::Result<()> {
+    let watcher = RegistryWatcher::new(path)?;
+    std::thread::spawn(move || {
+        loop {
+            if let Err(err) = watcher.next_events() {
+                tracing::error!("stop watching location registry: {err:#}");
+                break;
+            }
+            changed.notify_one();
+        }
+    });
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+async fn watch_location(
+    active_pid: Option<Pid>,
+    wm: WindowManager,
+    hyprland_instance: Option<String>,
+    output: Output,
+    interval: Duration,
+    reloader: &ConfigReloader,
+) -> anyhow::Result<()> {
+    let mut config = reloader.config();
+    let mut registry = config.registry()?;
+    let registry_changed = Arc::new(Notify::new());
+    // registry directory is watched from start to end, it isn't changed by reloads
+    spawn_registry_watcher(config.registry_path.clone(), registry_changed.clone())?;
+
+    // fixed process doesn't depend on focus
+    let tracker = match active_pid {
+        Some(_) => None,
+        None => start_tracker(wm, hyprland_instance).await?,
+    };
+
+    let mut processes = ProcessCache::new(config.scan_options());
+    let mut last: Option<LocationData> = None;
+    let mut stdout = io::stdout().lock();
+    loop {
+        let reloaded = reloader.config();
+        if !Arc::ptr_eq(&reloaded, &config) {
+            match reloaded.registry() {
+                Ok(reloaded_registry) => {
+                    registry = reloaded_registry;
+                    processes.set_options(reloaded.scan_options());
+                    config = reloaded;
+                }
+                Err(err) => tracing::error!("failed to apply reloaded config: {err:#}"),
+            }
+        }
+
+        let data = match &tracker {
+            Some(tracker) => {
+                current_location::get_cached(
+                    active_pid,
+                    tracker,
+                    &mut processes,
+                    &registry,
+                    &config,
+                )
+                .await
+            }
+            None => {
+                current_location::get_cached(active_pid, &wm, &mut processes, &registry, &config)
+                    .await
+            }
+        }
+        .map_err(Into::into)
+        .and_then(|data| transform(data, &output));
+
+        match data {
+            Ok(data) if last.as_ref().is_some_and(|last| last.is_same_as(&data)) => {}
+            Ok(data) => {
+                let mut line = render(&data, &output)?;
+                line.push(if output.print0 { b'\0' } else { b'\n' });
+                stdout
+                    .write_all(&line)
+                    .and_then(|()| stdout.flush())
+                    .context("write location to stdout")?;
+                last = Some(data);
+            }
+            Err(err) => tracing::error!("get location: {err:#}"),
+        }
+
+        let focus_changed = async {
+            match &tracker {
+                Some(tracker) => tracker.focus_changed().await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            () = registry_changed.notified() => {}
+            () = focus_changed => {}
+            () = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+fn print_list(format: ListFormat, config: &Config) -> anyhow::Result<()> {
+    let mut entries: Vec<_> = config
+        .registry()?
+        .iter()?
+        // single broken entry shouldn't hide the others
+        .filter_map(|entry| {
+            entry
+                .inspect_err(|err| tracing::error!("read registry entry: {err:#}"))
+                .ok()
+        })
+        .collect();
+    entries.sort_by_key(|(entry, _)| entry.pid);
+
+    let mut stdout = io::stdout().lock();
+    match format {
+        ListFormat::Json => {
+            #[cfg(feature = "hyprland")]
+            let windows = current_location::open_windows();
+            #[cfg(not(feature = "hyprland"))]
+            let windows: Option<Vec<_>> = None;
+            let entries: Vec<_> = entries
+                .into_iter()
+                .map(|(entry, data)| ListEntry {
+                    age: data.age().map(|age| age.as_secs()),
+                    window_closed: windows
+                        .as_ref()
+                        .and_then(|windows| data.is_window_closed(windows)),
+                    entry,
+                    data,
+                })
+                .collect();
+            serde_json::to_writer(stdout, &entries).context("write entries to stdout")?;
+        }
+        ListFormat::Table => {
+            writeln!(
+                stdout,
+                "{:<8} {:<16} {:<6} {:<40} NVIM PIPE",
+                "PID", "NAME", "AGE", "LOCATION"
+            )
+            .context("write entries to stdout")?;
+            for (entry, data) in entries {
+                let age = data.age().map(format_age).unwrap_or_else(|| "-".to_owned());
+                writeln!(
+                    stdout,
+                    "{:<8} {:<16} {:<6} {:<40} {}",
+                    entry.pid,
+                    entry.name,
+                    age,
+                    data.location().display(),
+                    data.nvim_pipe().unwrap_or("-"),
+                )
+                .context("write entries to stdout")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_history(entry: RegistryEntry, format: ListFormat, config: &Config) -> anyhow::Result<()> {
+    let history = current_location::history(&entry, &config.registry()?)?;
+
+    let mut stdout = io::stdout().lock();
+    match format {
+        ListFormat::Json => {
+            let history: Vec<_> = history
+                .into_iter()
+                .map(|data| HistoryEntry {
+                    age: data.age().map(|age| age.as_secs()),
+                    data,
+                })
+                .collect();
+            serde_json::to_writer(stdout, &history).context("write history to stdout")?;
+        }
+        ListFormat::Table => {
+            writeln!(stdout, "{:<6} LOCATION", "AGE").context("write history to stdout")?;
+            for data in history {
+                let age = data.age().map(format_age).unwrap_or_else(|| "-".to_owned());
+                writeln!(stdout, "{:<6} {}", age, data.location().display())
+                    .context("write history to stdout")?;
+            }
+        }
+    }
 
     Ok(())
 }
 
+/// Fails if any of checks has failed
+fn print_findings(findings: Vec<Finding>) -> ExitCode {
+    for finding in &findings {
+        println!("{finding}");
+    }
+    if findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error)
+    {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Formats duration in the largest whole unit, e.g. `5m`
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
 // Using `current_thread` for faster startup time
+#[cfg(feature = "tokio")]
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
     let opts = Opts::parse();
+    let errors = opts.errors;
+    report(errors, run_main(opts).await)
+}
+
+#[cfg(feature = "tokio")]
+async fn run_main(mut opts: Opts) -> anyhow::Result<ExitCode> {
+    let timings = matches!(
+        opts.subcommand,
+        Command::Configured(Subcommands::Get { timings: true, .. })
+    )
+    .then(Timings::default);
+    init_logging(opts.log_level.as_deref(), timings.clone())?;
+    let overrides = Overrides::take(&mut opts);
+    let subcommand = match opts.subcommand {
+        // they work with broken config as well
+        Command::Setup(subcommand) => return run_setup(subcommand).map(|()| ExitCode::SUCCESS),
+        Command::Configured(subcommand) => subcommand,
+    };
+    let config = load_config(opts.config.as_deref(), &overrides)?;
+
+    match subcommand {
+        Subcommands::Get {
+            output,
+            all,
+            selector,
+            daemon,
+            ..
+        } => {
+            let socket = daemon::socket_path(&config.registry_path);
+            if daemon
+                && let Some(data) =
+                    daemon::query(&socket, opts.active_pid).context("get location data")?
+            {
+                return print_data(data, output);
+            }
 
-    match opts.subcommand {
-        Subcommands::Get => print_location(opts.active_pid)
+            let scanned = scan_processes(
+                opts.active_pid,
+                opts.wm,
+                opts.hyprland_instance,
+                selector,
+                &config,
+            )
+            .await;
+            let exit_code =
+                scanned.and_then(|scanned| print_scanned(scanned, all, output, &config));
+            // stages finished before a failure are printed as well
+            if let Some(timings) = timings {
+                timings.print();
+            }
+            exit_code.context("get location data")
+        }
+        Subcommands::Query { pid, all, output } => query_location(pid, all, output, &config),
+        Subcommands::Daemon { refresh } => {
+            let reloader = start_reloader(opts.config, overrides)?;
+            run_daemon(
+                opts.wm,
+                opts.hyprland_instance,
+                Duration::from_millis(refresh),
+                &reloader,
+            )
+            .await
+            .context("run daemon")
+            .map(|()| ExitCode::SUCCESS)
+        }
+        Subcommands::Watch { output, interval } => {
+            let reloader = start_reloader(opts.config, overrides)?;
+            watch_location(
+                opts.active_pid,
+                opts.wm,
+                opts.hyprland_instance,
+                output,
+                Duration::from_millis(interval),
+                &reloader,
+            )
             .await
-            .context("get location data")?,
-        Subcommands::Write {
+            .context("watch location")
+            .map(|()| ExitCode::SUCCESS)
+        }
+        Subcommands::Doctor => {
+            let active_pid = match (opts.active_pid, opts.hyprland_instance) {
+                (Some(active_pid), _) => Ok(Some(active_pid)),
+                (None, Some(signature)) => match Hyprland::with_instance(signature) {
+                    Ok(hyprland) => current_location::request_active_pid(&hyprland, &config)
+                        .await
+                        .map_err(Into::into),
+                    Err(err) => Err(err),
+                },
+                (None, None) => current_location::request_active_pid(&opts.wm, &config)
+                    .await
+                    .map_err(Into::into),
+            };
+            Ok(print_findings(doctor::diagnose(active_pid, &config)))
+        }
+        Subcommands::Registry(subcommand) => {
+            run_registry(subcommand, &config).map(|()| ExitCode::SUCCESS)
+        }
+    }
+}
+
+#[cfg(not(feature = "tokio"))]
+fn main() -> anyhow::Result<ExitCode> {
+    let opts = Opts::parse();
+    let errors = opts.errors;
+    report(errors, run_main(opts))
+}
+
+#[cfg(not(feature = "tokio"))]
+fn run_main(mut opts: Opts) -> anyhow::Result<ExitCode> {
+    let timings = matches!(
+        opts.subcommand,
+        Command::Configured(Subcommands::Get { timings: true, .. })
+    )
+    .then(Timings::default);
+    init_logging(opts.log_level.as_deref(), timings.clone())?;
+    let overrides = Overrides::take(&mut opts);
+    let subcommand = match opts.subcommand {
+        // they work with broken config as well
+        Command::Setup(subcommand) => return run_setup(subcommand).map(|()| ExitCode::SUCCESS),
+        Command::Configured(subcommand) => subcommand,
+    };
+    let config = load_config(opts.config.as_deref(), &overrides)?;
+
+    match subcommand {
+        Subcommands::Get { output, all, .. } => {
+            #[cfg(feature = "wm")]
+            let scanned = current_location::scan_blocking(opts.active_pid, opts.wm, &config)
+                .map_err(Into::into);
+            #[cfg(not(feature = "wm"))]
+            let scanned = scan_active_pid(opts.active_pid, &config);
+            let exit_code =
+                scanned.and_then(|scanned| print_scanned(scanned, all, output, &config));
+            // stages finished before a failure are printed as well
+            if let Some(timings) = timings {
+                timings.print();
+            }
+            exit_code.context("get location data")
+        }
+        Subcommands::Query { pid, all, output } => query_location(pid, all, output, &config),
+        Subcommands::Doctor => {
+            #[cfg(feature = "wm")]
+            let active_pid = match opts.active_pid {
+                Some(active_pid) => Ok(Some(active_pid)),
+                None => opts.wm.active_pid_blocking(),
+            };
+            #[cfg(not(feature = "wm"))]
+            let active_pid = opts.active_pid.map(Some).context(
+                "`--active-pid` is required, window manager support is disabled at build time",
+            );
+            Ok(print_findings(doctor::diagnose(active_pid, &config)))
+        }
+        Subcommands::Registry(subcommand) => {
+            run_registry(subcommand, &config).map(|()| ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// Command line settings applied over config file, on every reload of it as well
+#[derive(Clone, Debug)]
+struct Overrides {
+    known_procs: Vec<String>,
+    registry_path: Option<PathBuf>,
+    registry_format: Option<RegistryFormat>,
+    strategy: Option<Strategy>,
+}
+
+impl Overrides {
+    fn take(opts: &mut Opts) -> Self {
+        Self {
+            known_procs: mem::take(&mut opts.known_procs),
+            registry_path: opts.registry_path.take(),
+            registry_format: opts.registry_format,
+            strategy: opts.strategy,
+        }
+    }
+
+    fn apply(&self, config: &mut Config) {
+        config.known_procs.extend(self.known_procs.iter().cloned());
+        if let Some(registry_path) = &self.registry_path {
+            config.registry_path = registry_path.clone();
+        }
+        if let Some(registry_format) = self.registry_format {
+            config.registry_format = registry_format;
+        }
+        if let Some(strategy) = self.strategy {
+            config.selection = strategy.into();
+        }
+    }
+}
+
+/// Loads config file and applies `overrides` over it
+fn load_config(path: Option<&Path>, overrides: &Overrides) -> anyhow::Result<Config> {
+    let mut config = Config::load_from(path).context(ErrorCode::Config)?;
+    overrides.apply(&mut config);
+    Ok(config)
+}
+
+/// Logs go to stderr so they don't mix with printed locations, stages are timed by spans
+/// regardless of log level if `timings` are given
+fn init_logging(level: Option<&str>, timings: Option<Timings>) -> anyhow::Result<()> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level).context("parse log level")?,
+        None => EnvFilter::builder()
+            .with_default_directive(LevelFilter::WARN.into())
+            .from_env_lossy(),
+    };
+    let logs = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_filter(filter);
+    let timings = timings.map(|timings| timings.with_filter(filter_fn(|meta| meta.is_span())));
+    tracing_subscriber::registry()
+        .with(logs)
+        .with(timings)
+        .init();
+    Ok(())
+}
+
+/// Prints error as JSON to stdout if asked to, otherwise it's printed by `main` as text
+fn report<T>(format: ErrorFormat, result: anyhow::Result<T>) -> anyhow::Result<T> {
+    let (ErrorFormat::Json, Err(err)) = (format, &result) else {
+        return result;
+    };
+
+    let error = JsonError {
+        code: ErrorCode::of(err),
+        message: format!("{err:#}"),
+        chain: err.chain().map(ToString::to_string).collect(),
+    };
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &error).context("write error to stdout")?;
+    writeln!(stdout).context("write error to stdout")?;
+    stdout.flush().context("write error to stdout")?;
+    std::process::exit(1)
+}
+
+fn print_completions(shell: CompletionShell) {
+    let mut command = Opts::command();
+    let name = command.get_name().to_owned();
+    let stdout = &mut io::stdout();
+    match shell {
+        CompletionShell::Bash => generate(clap_complete::Shell::Bash, &mut command, name, stdout),
+        CompletionShell::Elvish => {
+            generate(clap_complete::Shell::Elvish, &mut command, name, stdout)
+        }
+        CompletionShell::Fish => generate(clap_complete::Shell::Fish, &mut command, name, stdout),
+        CompletionShell::Nushell => generate(Nushell, &mut command, name, stdout),
+        CompletionShell::Powershell => {
+            generate(clap_complete::Shell::PowerShell, &mut command, name, stdout)
+        }
+        CompletionShell::Zsh => generate(clap_complete::Shell::Zsh, &mut command, name, stdout),
+    }
+}
+
+/// Writes [`NVIM_INIT_SCRIPT`] into `plugin` directory of Neovim config, returns its path. A
+/// different file there is overwritten only if `force` is set
+fn install_nvim_init(force: bool) -> anyhow::Result<PathBuf> {
+    let appname = env::var_os("NVIM_APPNAME")
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "nvim".into());
+    let plugin_dir = config::xdg_config_home()
+        .context("home directory is unknown")?
+        .join(appname)
+        .join("plugin");
+    fs::create_dir_all(&plugin_dir).context("create plugin directory")?;
+
+    let path = plugin_dir.join("current-location.lua");
+    match fs::read(&path) {
+        Ok(installed) if installed == NVIM_INIT_SCRIPT.as_bytes() => return Ok(path),
+        Ok(_) if !force => anyhow::bail!(
+            "{} exists and differs, pass `--force` to overwrite it",
+            path.display()
+        ),
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            return Err(err).context("read installed plugin");
+        }
+        _ => {}
+    }
+    fs::write(&path, NVIM_INIT_SCRIPT).context("write plugin")?;
+    Ok(path)
+}
+
+/// Runs subcommands that only need config, not window manager or process tree
+fn run_registry(subcommand: RegistrySubcommands, config: &Config) -> anyhow::Result<()> {
+    match subcommand {
+        RegistrySubcommands::Write {
             name,
             pids,
             location,
             nvim_pipe,
-        } => current_location::write(name, pids, location, nvim_pipe).context("write location")?,
-        Subcommands::Clear => current_location::clear().context("clear location")?,
+            line,
+            column,
+            window,
+            terminal,
+            meta,
+        } => {
+            #[cfg(feature = "hyprland")]
+            let window = window.or_else(|| {
+                let windows = current_location::open_windows()?;
+                current_location::detect_window(*pids.first()?, &windows)
+            });
+            let data = LocationData::new(location)
+                .with_nvim_pipe(nvim_pipe)
+                .with_cursor(line, column)
+                .with_window(window)
+                .with_terminal(terminal.or_else(current_location::detect_terminal))
+                .with_extra(meta.into_iter().collect())
+                .with_project_root(&config.project_markers);
+            current_location::write(name, pids, data, &config.registry()?)
+                .context("write location")?
+        }
+        RegistrySubcommands::List { format } => {
+            print_list(format, config).context("list locations")?
+        }
+        RegistrySubcommands::History { name, pid, format } => {
+            print_history(RegistryEntry { pid, name }, format, config).context("list history")?
+        }
+        RegistrySubcommands::Remove { name, pids } => {
+            current_location::remove(&name, &pids, &config.registry()?)
+                .context("remove location")?
+        }
+        RegistrySubcommands::Prune => {
+            let removed =
+                current_location::prune(&config.registry()?, config).context("prune locations")?;
+            println!("removed {removed} entries");
+        }
+        RegistrySubcommands::Migrate => {
+            let migrated =
+                current_location::migrate(&config.registry()?).context("migrate locations")?;
+            println!("migrated {migrated} entries");
+        }
+        RegistrySubcommands::Clear => {
+            current_location::clear(&config.registry()?).context("clear location")?
+        }
+        RegistrySubcommands::RegistryDaemon => {
+            socket::run_daemon(&config.registry_path).context("run registry daemon")?
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs subcommands that need neither config nor window manager
+fn run_setup(subcommand: SetupSubcommands) -> anyhow::Result<()> {
+    match subcommand {
+        SetupSubcommands::ShellInit { shell } => io::stdout()
+            .write_all(shell.init_script().as_bytes())
+            .context("write shell code to stdout")?,
+        SetupSubcommands::NvimInit { install: false, .. } => io::stdout()
+            .write_all(NVIM_INIT_SCRIPT.as_bytes())
+            .context("write Lua code to stdout")?,
+        SetupSubcommands::NvimInit {
+            install: true,
+            force,
+        } => {
+            let path = install_nvim_init(force).context("install Neovim integration")?;
+            println!("installed to {}", path.display());
+        }
+        SetupSubcommands::Completions { shell } => print_completions(shell),
+        SetupSubcommands::Man { out_dir: None } => clap_mangen::Man::new(Opts::command())
+            .render(&mut io::stdout())
+            .context("write man page to stdout")?,
+        SetupSubcommands::Man {
+            out_dir: Some(out_dir),
+        } => {
+            fs::create_dir_all(&out_dir).context("create output directory")?;
+            clap_mangen::generate_to(Opts::command(), &out_dir).context("write man pages")?
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use crate::{Opts, shell_quote};
+
+    #[test]
+    fn cli() {
+        Opts::command().debug_assert();
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(shell_quote(b"/home/user"), b"'/home/user'");
+        assert_eq!(shell_quote(b"it's $HOME"), br#"'it'\''s $HOME'"#);
+    }
+}
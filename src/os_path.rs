@@ -0,0 +1,125 @@
+//! Serde helpers for paths which are arbitrary bytes on Linux, unlike `PathBuf`'s own
+//! implementation that fails on non-UTF-8 ones
+//!
+//! Human readable formats get a string if path is valid UTF-8 and an array of bytes otherwise.
+//! Binary formats always get bytes, postcard encodes them the same way as strings
+
+use std::ffi::OsString;
+use std::fmt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    match path.to_str() {
+        Some(path) if serializer.is_human_readable() => serializer.serialize_str(path),
+        _ => serializer.serialize_bytes(path.as_os_str().as_bytes()),
+    }
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(PathVisitor)
+    } else {
+        deserializer.deserialize_byte_buf(PathVisitor)
+    }
+}
+
+struct PathVisitor;
+
+impl<'de> Visitor<'de> for PathVisitor {
+    type Value = PathBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("path as a string or bytes")
+    }
+
+    fn visit_str<E: de::Error>(self, path: &str) -> Result<PathBuf, E> {
+        Ok(path.into())
+    }
+
+    fn visit_bytes<E: de::Error>(self, path: &[u8]) -> Result<PathBuf, E> {
+        Ok(OsString::from_vec(path.to_vec()).into())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, path: Vec<u8>) -> Result<PathBuf, E> {
+        Ok(OsString::from_vec(path).into())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<PathBuf, A::Error> {
+        let mut path = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(byte) = seq.next_element()? {
+            path.push(byte);
+        }
+        Ok(OsString::from_vec(path).into())
+    }
+}
+
+/// Same for optional paths
+pub mod option {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    struct Borrowed<'a>(#[serde(with = "super")] &'a Path);
+
+    #[derive(Deserialize)]
+    struct Owned(#[serde(with = "super")] PathBuf);
+
+    pub fn serialize<S: Serializer>(
+        path: &Option<PathBuf>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        path.as_deref().map(Borrowed).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<PathBuf>, D::Error> {
+        let path = Option::<Owned>::deserialize(deserializer)?;
+        Ok(path.map(|Owned(path)| path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Wrapper(#[serde(with = "crate::os_path")] PathBuf);
+
+    #[test]
+    fn non_utf8() {
+        let path = Wrapper(PathBuf::from(OsStr::from_bytes(b"/tmp/\xff")));
+        let json = serde_json::to_string(&path).unwrap();
+        assert_eq!(json, "[47,116,109,112,47,255]");
+        assert_eq!(serde_json::from_str::<Wrapper>(&json).unwrap(), path);
+
+        // same encoding as `PathBuf`'s own one for valid paths
+        let wrapper = Wrapper(PathBuf::from("/srv"));
+        assert_eq!(serde_json::to_string(&wrapper).unwrap(), r#""/srv""#);
+    }
+
+    #[test]
+    #[cfg(feature = "registry")]
+    fn non_utf8_binary() {
+        let path = Wrapper(PathBuf::from(OsStr::from_bytes(b"/tmp/\xff")));
+        let bytes = postcard::to_stdvec(&path).unwrap();
+        assert_eq!(postcard::from_bytes::<Wrapper>(&bytes).unwrap(), path);
+
+        // same encoding as `PathBuf`'s own one for valid paths
+        let path = PathBuf::from("/srv");
+        let wrapper = Wrapper(path.clone());
+        assert_eq!(
+            postcard::to_stdvec(&wrapper).unwrap(),
+            postcard::to_stdvec(&path).unwrap()
+        );
+    }
+}
@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+
+use anyhow::Context;
+use regex_lite::Regex;
+
+use crate::process::ProcessInfo;
+
+const CMDLINE_PREFIX: &str = "cmdline:";
+const EXE_PREFIX: &str = "exe:";
+
+/// What pattern is matched against
+#[derive(Copy, Clone, Debug)]
+enum Field {
+    Name,
+    Cmdline,
+    Exe,
+}
+
+/// Known process entry, pattern prefixed with `cmdline:` is matched against command line (arguments
+/// joined by spaces) instead of name, e.g. `cmdline:nvim --embed*`, and prefixed with `exe:` against
+/// path of executable, e.g. `exe:/usr/bin/*vim`
+#[derive(Clone, Debug)]
+pub struct ProcMatcher {
+    field: Field,
+    pattern: ProcPattern,
+}
+
+impl ProcMatcher {
+    pub fn new(entry: &str) -> anyhow::Result<Self> {
+        let (field, pattern) = if let Some(pattern) = entry.strip_prefix(CMDLINE_PREFIX) {
+            (Field::Cmdline, pattern)
+        } else if let Some(pattern) = entry.strip_prefix(EXE_PREFIX) {
+            (Field::Exe, pattern)
+        } else {
+            (Field::Name, entry)
+        };
+        Ok(Self {
+            field,
+            pattern: ProcPattern::new(pattern)?,
+        })
+    }
+
+    /// Whether this entry needs command lines of processes
+    pub fn needs_cmdline(entry: &str) -> bool {
+        entry.starts_with(CMDLINE_PREFIX)
+    }
+
+    /// Whether this entry needs executables of processes
+    pub fn needs_exe(entry: &str) -> bool {
+        entry.starts_with(EXE_PREFIX)
+    }
+
+    pub fn is_match(&self, proc: &ProcessInfo) -> bool {
+        match self.field {
+            Field::Name => self.pattern.is_match(&proc.name),
+            Field::Cmdline => proc
+                .args
+                .as_deref()
+                .is_some_and(|args| self.pattern.is_match_args(args)),
+            // paths which aren't valid UTF-8 never match
+            Field::Exe => proc
+                .exe
+                .as_deref()
+                .and_then(|exe| exe.to_str())
+                .is_some_and(|exe| self.pattern.is_match(exe)),
+        }
+    }
+}
+
+/// Known process entry, matched against the whole process name
+///
+/// - starting with `^` is a regex, e.g. `^python3?$`
+/// - containing `*`, `?` or `[` is a glob, e.g. `nvim*`
+/// - anything else is matched exactly
+#[derive(Clone, Debug)]
+pub enum ProcPattern {
+    Exact(String),
+    Regex(Regex),
+}
+
+impl ProcPattern {
+    pub fn new(pattern: &str) -> anyhow::Result<Self> {
+        if pattern.starts_with('^') {
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("invalid process name regex `{pattern}`"))?;
+            return Ok(Self::Regex(regex));
+        }
+
+        if pattern.contains(['*', '?', '[']) {
+            let regex = Regex::new(&glob_to_regex(pattern))
+                .with_context(|| format!("invalid process name glob `{pattern}`"))?;
+            return Ok(Self::Regex(regex));
+        }
+
+        Ok(Self::Exact(pattern.to_owned()))
+    }
+
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            Self::Exact(exact) => exact == name,
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// Same as [`Self::is_match`] against `args` joined by spaces. They are joined only for
+    /// regexes, into a buffer reused by every call on the thread
+    pub fn is_match_args(&self, args: &[String]) -> bool {
+        thread_local! {
+            static CMDLINE: RefCell<String> = const { RefCell::new(String::new()) };
+        }
+
+        match self {
+            Self::Exact(exact) => is_joined(exact, args),
+            Self::Regex(regex) => CMDLINE.with_borrow_mut(|cmdline| {
+                cmdline.clear();
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        cmdline.push(' ');
+                    }
+                    cmdline.push_str(arg);
+                }
+                regex.is_match(cmdline)
+            }),
+        }
+    }
+}
+
+/// Whether `joined` is `args` joined by spaces
+fn is_joined(joined: &str, args: &[String]) -> bool {
+    let mut rest = joined;
+    for (i, arg) in args.iter().enumerate() {
+        let separated = if i > 0 {
+            rest.strip_prefix(' ')
+        } else {
+            Some(rest)
+        };
+        let Some(next) = separated.and_then(|rest| rest.strip_prefix(arg.as_str())) else {
+            return false;
+        };
+        rest = next;
+    }
+    rest.is_empty()
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::with_capacity(glob.len() * 2 + 2);
+    regex.push('^');
+
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '[' => {
+                regex.push('[');
+                let mut class = chars.by_ref().take_while(|&c| c != ']').peekable();
+                if class.next_if(|&c| c == '!').is_some() {
+                    regex.push('^');
+                }
+                for c in class {
+                    // `-` keeps its meaning of range
+                    if c != '-' {
+                        regex.push_str(&regex_lite::escape(&c.to_string()));
+                    } else {
+                        regex.push(c);
+                    }
+                }
+                regex.push(']');
+            }
+            c => regex.push_str(&regex_lite::escape(&c.to_string())),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pattern::{ProcMatcher, ProcPattern};
+    use crate::process::ProcessInfo;
+
+    #[test]
+    fn exact() {
+        let pattern = ProcPattern::new("nvim").unwrap();
+
+        assert!(pattern.is_match("nvim"));
+        assert!(!pattern.is_match("nvim-qt"));
+    }
+
+    #[test]
+    fn glob() {
+        let pattern = ProcPattern::new("nvim*").unwrap();
+        assert!(pattern.is_match("nvim"));
+        assert!(pattern.is_match("nvim-0.10"));
+        assert!(!pattern.is_match("xnvim"));
+
+        let pattern = ProcPattern::new("python3.[0-9]").unwrap();
+        assert!(pattern.is_match("python3.9"));
+        assert!(!pattern.is_match("python3x9"));
+
+        let pattern = ProcPattern::new("[!z]sh").unwrap();
+        assert!(pattern.is_match("bsh"));
+        assert!(!pattern.is_match("zsh"));
+    }
+
+    #[test]
+    fn regex() {
+        let pattern = ProcPattern::new("^python3?$").unwrap();
+
+        assert!(pattern.is_match("python"));
+        assert!(pattern.is_match("python3"));
+        assert!(!pattern.is_match("python3.12"));
+    }
+
+    #[test]
+    fn invalid_regex() {
+        assert!(ProcPattern::new("^(nvim").is_err());
+    }
+
+    #[test]
+    fn cmdline() {
+        let matcher = ProcMatcher::new("cmdline:nvim --embed*").unwrap();
+        let mut proc = ProcessInfo::new(1, "nvim".to_owned());
+        assert!(!matcher.is_match(&proc));
+
+        let args = ["nvim", "--embed", "--listen", "/tmp/nvim.sock"];
+        proc.args = Some(args.map(str::to_owned).to_vec());
+        assert!(matcher.is_match(&proc));
+        assert!(proc.has_arg("--listen"));
+
+        proc.args = Some(vec!["nvim-qt".to_owned()]);
+        assert!(!matcher.is_match(&proc));
+        assert!(!proc.has_arg("--listen"));
+    }
+
+    #[test]
+    fn exact_cmdline() {
+        let matcher = ProcMatcher::new("cmdline:nvim --embed").unwrap();
+        let mut proc = ProcessInfo::new(1, "nvim".to_owned());
+
+        for (args, matches) in [
+            (&["nvim", "--embed"][..], true),
+            (&["nvim --embed"], true),
+            (&["nvim", "--embed", "-u"], false),
+            (&["nvim", "--em"], false),
+            (&["nvim"], false),
+        ] {
+            proc.args = Some(args.iter().map(|&arg| arg.to_owned()).collect());
+            assert_eq!(matcher.is_match(&proc), matches, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn arg_with_value() {
+        let mut proc = ProcessInfo::new(1, "nvim".to_owned());
+        let args = ["nvim", "--listen=/tmp/nvim.sock", "--listener"];
+        proc.args = Some(args.map(str::to_owned).to_vec());
+
+        assert!(proc.has_arg("--listen"));
+        assert!(proc.has_arg("--listener"));
+        assert!(!proc.has_arg("--list"));
+        assert!(!proc.has_arg("nvim"));
+    }
+
+    #[test]
+    fn exe() {
+        let matcher = ProcMatcher::new("exe:/usr/bin/*vim").unwrap();
+        let mut proc = ProcessInfo::new(1, "vi".to_owned());
+        assert!(!matcher.is_match(&proc));
+
+        proc.exe = Some("/usr/bin/nvim".into());
+        assert!(matcher.is_match(&proc));
+        proc.exe = Some("/opt/nvim/bin/nvim".into());
+        assert!(!matcher.is_match(&proc));
+    }
+}
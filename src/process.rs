@@ -1,46 +1,189 @@
-use std::collections::{HashMap, hash_map};
-use std::io::BufRead;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+#[cfg(feature = "procfs")]
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::Context;
 use rustc_hash::FxBuildHasher;
+use smallvec::SmallVec;
 
-use crate::tosubstr::ToSubStr;
+#[cfg(feature = "proc-connector")]
+use crate::process::connector::ProcessEvent;
 use crate::walk::Node;
 
+#[cfg(feature = "proc-connector")]
+pub mod connector;
+#[cfg(any(test, feature = "test-support"))]
+pub mod fixture;
+
 pub type Pid = i32;
 pub type ProcessTree = HashMap<Pid, Process, FxBuildHasher>;
+/// Most processes have no children or a single one, they are kept inline
+type Children = SmallVec<[Pid; 4]>;
 
-const PROCESS_TREE_CAPACITY: usize = 2048;
+pub(crate) const PROCESS_TREE_CAPACITY: usize = 2048;
 
 #[derive(Default, Clone, Debug)]
 pub struct ProcessInfo {
     pub pid: Pid,
-    pub name: String,
+    /// Truncated to 15 bytes by kernel. Names are shared by processes of the same scan, see
+    /// [`NamePool`]
+    pub name: Arc<str>,
+    /// Arguments from `/proc/<pid>/cmdline`, the first one is program itself. Read only if
+    /// [`ScanOptions::read_cmdline`] is set
+    pub args: Option<Vec<String>>,
+    /// Clock ticks after boot, see [`start_time`]. `None` for processes not read from /proc and if
+    /// [`ScanOptions::read_start_time`] isn't set
+    pub start_time: Option<u64>,
+    /// Pid inside of the innermost PID namespace if it's a nested one, e.g. pid of a shell in a
+    /// container which its hooks write
+    pub ns_pid: Option<Pid>,
+    /// Effective user id, i.e. owner of `/proc/<pid>` as in [`ScanOptions::uid`]. `None` for
+    /// processes not read from /proc
+    pub uid: Option<u32>,
+    /// Target of `/proc/<pid>/exe`, e.g. to tell apart symlinked or renamed binaries. Read only if
+    /// [`ScanOptions::read_exe`] is set, `None` for processes of other users
+    pub exe: Option<PathBuf>,
 }
 
 impl ProcessInfo {
-    pub fn new(pid: Pid, name: String) -> Self {
-        Self { pid, name }
+    pub fn new(pid: Pid, name: impl Into<Arc<str>>) -> Self {
+        Self {
+            pid,
+            name: name.into(),
+            args: None,
+            start_time: None,
+            ns_pid: None,
+            uid: None,
+            exe: None,
+        }
+    }
+
+    /// Arguments joined by spaces
+    pub fn cmdline(&self) -> Option<String> {
+        self.args.as_ref().map(|args| args.join(" "))
+    }
+
+    /// Whether `arg` is among arguments, e.g. `--listen` of Neovim serving UI, either alone or
+    /// with a value as in `--listen=/tmp/nvim.sock`. `false` if arguments are not read
+    pub fn has_arg(&self, arg: &str) -> bool {
+        self.args.as_ref().is_some_and(|args| {
+            args.iter().skip(1).any(|a| {
+                a.strip_prefix(arg)
+                    .is_some_and(|value| value.is_empty() || value.starts_with('='))
+            })
+        })
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct ScanOptions {
+    /// Initial capacity of process tree, it's better to be larger than number of processes
+    pub capacity: usize,
+    /// Read `/proc/<pid>/cmdline` of every process, noticeably slower. Command lines of
+    /// `flatpak-spawn` processes and their commands are read anyway
+    pub read_cmdline: bool,
+    /// Read `/proc/<pid>/exe` of every process
+    pub read_exe: bool,
+    /// Read `/proc/<pid>/stat` of every process for its start time, see
+    /// [`ProcessInfo::start_time`]. Otherwise only `status` is read, so twice fewer files are
+    /// opened, but [`selection::Newest`](crate::selection::Newest) falls back to the deepest
+    /// process, entries of processes with reused pids are trusted while searching and
+    /// [`namespaced_pids`] is empty. Start times of `flatpak-spawn` processes are read anyway
+    pub read_start_time: bool,
+    /// Read only processes below the root one, see [`build_process_tree_from`]
+    pub subtree: bool,
+    /// Skip processes not owned by this user, their files aren't read. Owner is the one of
+    /// `/proc/<pid>`, i.e. effective user id, so own processes under skipped ones are lost too
+    pub uid: Option<u32>,
+}
+
+impl ScanOptions {
+    /// Whether `proc` isn't read, processes of unknown owner are read
+    #[cfg(feature = "procfs")]
+    fn skips(&self, proc: &procfs::process::Process) -> bool {
+        self.uid
+            .is_some_and(|uid| proc.uid().is_ok_and(|owner| owner != uid))
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            capacity: PROCESS_TREE_CAPACITY,
+            read_cmdline: false,
+            read_exe: false,
+            read_start_time: false,
+            subtree: false,
+            uid: None,
+        }
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct Process {
     info: ProcessInfo,
-    children: Vec<Pid>,
+    children: Children,
 }
 
 impl Process {
     pub fn new(info: ProcessInfo) -> Self {
         Self {
             info,
-            children: vec![],
+            children: Children::new(),
+        }
+    }
+
+    pub fn new_with_children(info: ProcessInfo, children: impl IntoIterator<Item = Pid>) -> Self {
+        Self {
+            info,
+            children: children.into_iter().collect(),
+        }
+    }
+}
+
+/// Interns names of processes, so processes of the same program share a single allocation, e.g.
+/// dozens of shells
+#[derive(Default, Clone, Debug)]
+pub struct NamePool {
+    names: HashSet<Arc<str>, FxBuildHasher>,
+}
+
+impl NamePool {
+    pub fn intern(&mut self, name: &str) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return interned.clone();
         }
+        let interned: Arc<str> = name.into();
+        self.names.insert(interned.clone());
+        interned
     }
 
-    pub fn new_with_children(info: ProcessInfo, children: Vec<Pid>) -> Self {
-        Self { info, children }
+    /// Same as [`Self::intern`] but `name` is kept if it's new, so interned names of different
+    /// pools are merged without allocating
+    pub fn share(&mut self, name: &Arc<str>) -> Arc<str> {
+        if let Some(interned) = self.names.get(name) {
+            return interned.clone();
+        }
+        self.names.insert(name.clone());
+        name.clone()
     }
+
+    /// Forgets names no process has anymore
+    pub fn shrink(&mut self) {
+        self.names.retain(|name| Arc::strong_count(name) > 1);
+    }
+}
+
+/// Buffers reused across processes while /proc is scanned
+#[cfg(feature = "procfs")]
+#[derive(Default, Clone, Debug)]
+struct Scratch {
+    /// Contents of `status` file
+    buf: Vec<u8>,
+    names: NamePool,
 }
 
 impl Node<ProcessInfo> for Process {
@@ -59,66 +202,978 @@ impl Node<ProcessInfo> for Process {
     }
 }
 
+#[cfg(feature = "procfs")]
 #[derive(Debug, Clone)]
 struct Status {
     /// Command run by this process.
-    pub name: String,
+    pub name: Arc<str>,
+    pub ppid: Pid,
+    /// `None` on older kernels lacking `Kthread` field
+    pub kthread: Option<bool>,
+    /// See [`ProcessInfo::ns_pid`]
+    pub ns_pid: Option<Pid>,
+    /// See [`ProcessInfo::uid`]
+    pub uid: Option<u32>,
 }
 
-impl procfs::FromBufRead for Status {
-    fn from_buf_read<R: BufRead>(mut reader: R) -> procfs::ProcResult<Self> {
-        let mut line = "".to_string();
-        while reader.read_line(&mut line)? != 0 {
-            let Some(name) = line.strip_prefix("Name:") else {
-                line.clear();
+#[cfg(feature = "procfs")]
+impl Status {
+    /// Buffers of `scratch` are reused across processes, so only new names are allocated
+    fn read(proc: &procfs::process::Process, scratch: &mut Scratch) -> anyhow::Result<Self> {
+        let Scratch { buf, names } = scratch;
+        buf.clear();
+        let mut file = proc.open_relative("status").context("open status file")?;
+        file.read_to_end(buf).context("read status file")?;
+        Self::parse(buf, names).context("parse status file")
+    }
+
+    fn parse(status: &[u8], names: &mut NamePool) -> Option<Self> {
+        let mut name = None;
+        let mut ppid = None;
+        let mut ns_pid = None;
+        let mut uid = None;
+        let mut kthread = None;
+        for line in status.split(|&byte| byte == b'\n') {
+            if let Some(value) = line.strip_prefix(b"Name:") {
+                name = Some(names.intern(String::from_utf8_lossy(value).trim()));
+                continue;
+            }
+            let Some(colon) = line.iter().position(|&byte| byte == b':') else {
                 continue;
             };
-
-            let range = line.substr_range(name.trim()).expect("name is within line");
-            line.to_substr(range);
-            let status = Status { name: line };
-            return Ok(status);
+            let (key, value) = line.split_at(colon);
+            let Ok(value) = str::from_utf8(&value[1..]) else {
+                continue;
+            };
+            match key {
+                b"PPid" => ppid = value.trim().parse().ok(),
+                // real, effective, saved and filesystem ones, the effective one owns `/proc/<pid>`
+                b"Uid" => {
+                    uid = value
+                        .split_whitespace()
+                        .nth(1)
+                        .and_then(|uid| uid.parse().ok())
+                }
+                // it's absent on kernels older than 4.1
+                b"NSpid" => {
+                    // the first one is in the host namespace
+                    let pids = value.split_whitespace().skip(1);
+                    ns_pid = pids.last().and_then(|pid| pid.parse().ok());
+                }
+                // `Kthread` is the last field read
+                b"Kthread" => {
+                    kthread = Some(value.trim() == "1");
+                    break;
+                }
+                _ => {}
+            }
         }
 
-        let err = procfs::ProcError::NotFound(None);
-        Err(err)
+        Some(Status {
+            name: name?,
+            ppid: ppid?,
+            kthread,
+            ns_pid,
+            uid,
+        })
+    }
+
+    /// Same as [`is_kernel_thread`], children of `kthreadd` are kernel threads on older kernels
+    fn is_kernel_thread(&self, pid: Pid) -> bool {
+        self.kthread.unwrap_or(pid == 2 || self.ppid == 2)
     }
 }
 
+/// Pid of process inside of its PID namespace, see [`ProcessInfo::ns_pid`]
+#[cfg(feature = "procfs")]
+pub fn ns_pid(pid: Pid) -> anyhow::Result<Option<Pid>> {
+    let proc = procfs::process::Process::new(pid).context("open process")?;
+    Ok(Status::read(&proc, &mut Scratch::default())?.ns_pid)
+}
+
+/// Pids of processes inside of nested PID namespaces along with their start times, they identify
+/// registry entries written from containers. Processes of unknown start time are left out, see
+/// [`ScanOptions::read_start_time`]
+pub fn namespaced_pids(processes: &ProcessTree) -> HashSet<(Pid, u64)> {
+    processes
+        .values()
+        .filter_map(|process| Some((process.info.ns_pid?, process.info.start_time?)))
+        .collect()
+}
+
+/// Working directory of process, e.g. of a shell that hasn't written its location
+pub fn cwd(pid: Pid) -> anyhow::Result<PathBuf> {
+    fs::read_link(format!("/proc/{pid}/cwd")).context("read process working directory")
+}
+
+/// Start time of process in clock ticks after boot, it distinguishes processes with reused pid
+#[cfg(feature = "procfs")]
+pub fn start_time(pid: Pid) -> anyhow::Result<u64> {
+    let proc = procfs::process::Process::new(pid).context("open process")?;
+    let stat = proc.stat().context("read stat file")?;
+    Ok(stat.starttime)
+}
+
+/// Whether `err` of [`start_time`] or [`ppid`] means that the process isn't running
+#[cfg(feature = "procfs")]
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<procfs::ProcError>(),
+        Some(procfs::ProcError::NotFound(_))
+    )
+}
+
+/// Pid of parent process
+#[cfg(feature = "procfs")]
+pub fn ppid(pid: Pid) -> anyhow::Result<Pid> {
+    let proc = procfs::process::Process::new(pid).context("open process")?;
+    let stat = proc.stat().context("read stat file")?;
+    Ok(stat.ppid)
+}
+
+#[cfg(feature = "procfs")]
 pub fn build_process_tree() -> anyhow::Result<ProcessTree> {
-    let mut processes = ProcessTree::with_capacity_and_hasher(PROCESS_TREE_CAPACITY, FxBuildHasher);
-    for proc in procfs::process::all_processes().context("read /proc")? {
+    build_process_tree_with_capacity(PROCESS_TREE_CAPACITY)
+}
+
+#[cfg(feature = "procfs")]
+pub fn build_process_tree_with_capacity(capacity: usize) -> anyhow::Result<ProcessTree> {
+    build_process_tree_with(&ScanOptions {
+        capacity,
+        ..Default::default()
+    })
+}
+
+/// Reads info of process and pid of its parent, `None` for kernel threads. Both come from
+/// `status`, `stat` is read only for start time
+#[cfg(feature = "procfs")]
+fn read_process(
+    proc: &procfs::process::Process,
+    options: &ScanOptions,
+    scratch: &mut Scratch,
+) -> anyhow::Result<Option<(ProcessInfo, Pid)>> {
+    let status = Status::read(proc, scratch)?;
+    if status.is_kernel_thread(proc.pid()) {
+        return Ok(None);
+    }
+    let ppid = status.ppid;
+    let mut info = read_info(proc, status, options);
+    if options.read_start_time {
+        info.start_time = Some(proc.stat().context("read stat file")?.starttime);
+    }
+    Ok(Some((info, ppid)))
+}
+
+/// Result of [`read_process`] unless it failed, e.g. because the process died after being listed
+#[cfg(feature = "procfs")]
+fn skip_failed<T>(read: anyhow::Result<Option<T>>) -> Option<T> {
+    read.inspect_err(|err| tracing::trace!("skip process: {err:#}"))
+        .ok()
+        .flatten()
+}
+
+/// Kernel threads, i.e. `kthreadd` and its children, never have shells or editors below them
+#[cfg(feature = "procfs")]
+fn is_kernel_thread(stat: &procfs::process::Stat) -> bool {
+    stat.flags()
+        .is_ok_and(|flags| flags.contains(procfs::process::StatFlags::PF_KTHREAD))
+}
+
+#[cfg(feature = "procfs")]
+fn read_info(
+    proc: &procfs::process::Process,
+    status: Status,
+    options: &ScanOptions,
+) -> ProcessInfo {
+    let mut info = ProcessInfo::new(proc.pid(), status.name);
+    info.ns_pid = status.ns_pid;
+    info.uid = status.uid;
+    if options.read_cmdline {
+        // zombies have empty cmdline
+        info.args = proc.cmdline().ok().filter(|args| !args.is_empty());
+    }
+    if options.read_exe {
+        // it isn't readable for processes of other users and zombies
+        info.exe = proc.exe().ok();
+    }
+    info
+}
+
+/// Files of every process are read in parallel with `parallel` feature, the tree is built on the
+/// calling thread
+#[cfg(feature = "procfs")]
+pub fn build_process_tree_with(options: &ScanOptions) -> anyhow::Result<ProcessTree> {
+    let _span = tracing::debug_span!("process scan").entered();
+    scan_all(options)
+}
+
+#[cfg(feature = "procfs")]
+fn scan_all(options: &ScanOptions) -> anyhow::Result<ProcessTree> {
+    let procs: Vec<_> = procfs::process::all_processes()
+        .context("read /proc")?
         // Process could die by the time we come to it, it's normal
-        let Ok(proc) = proc else { continue };
+        .filter_map(|proc| {
+            proc.inspect_err(|err| tracing::trace!("skip process: {err}"))
+                .ok()
+        })
+        .filter(|proc| !options.skips(proc))
+        .collect();
+    #[cfg(feature = "parallel")]
+    let infos: Vec<_> = {
+        use rayon::prelude::*;
+        let mut infos: Vec<_> = procs
+            .par_iter()
+            // buffers per rayon job rather than per process
+            .map_init(Scratch::default, |scratch, proc| {
+                read_process(proc, options, scratch)
+            })
+            .filter_map(skip_failed)
+            .collect();
+        // every job has its own pool, names of different jobs are merged here
+        let mut names = NamePool::default();
+        for (info, _) in &mut infos {
+            info.name = names.share(&info.name);
+        }
+        infos
+    };
+    #[cfg(not(feature = "parallel"))]
+    let infos: Vec<_> = {
+        let mut scratch = Scratch::default();
+        procs
+            .iter()
+            .filter_map(|proc| skip_failed(read_process(proc, options, &mut scratch)))
+            .collect()
+    };
+
+    let mut processes = link_processes(infos, options.capacity);
+    graft_flatpak_spawns(&mut processes);
+    tracing::debug!(processes = processes.len(), "scanned /proc");
+    Ok(processes)
+}
 
-        let stat = proc.stat().context("read stat file")?;
-        let status = proc
-            .read::<_, Status>("status")
-            .context("read status file")?;
-        let info = ProcessInfo::new(proc.pid(), status.name);
+/// Same as [`build_process_tree_with`] but processes are listed by `sysinfo`, so it works on
+/// FreeBSD and macOS as well. Start time, pid in namespace and `flatpak-spawn` commands are
+/// unknown, [`ScanOptions::subtree`] is ignored
+#[cfg(feature = "sysinfo")]
+pub fn build_process_tree_sysinfo(options: &ScanOptions) -> anyhow::Result<ProcessTree> {
+    let _span = tracing::debug_span!("process scan").entered();
+    use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
 
-        match processes.entry(proc.pid()) {
-            hash_map::Entry::Occupied(mut e) => {
-                e.get_mut().info = info;
-            }
-            hash_map::Entry::Vacant(e) => {
-                e.insert(Process::new(info));
-            }
+    let mut refresh = ProcessRefreshKind::nothing()
+        .without_tasks()
+        .with_user(UpdateKind::OnlyIfNotSet);
+    if options.read_cmdline {
+        refresh = refresh.with_cmd(UpdateKind::Always);
+    }
+    if options.read_exe {
+        refresh = refresh.with_exe(UpdateKind::Always);
+    }
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh);
+    anyhow::ensure!(
+        !system.processes().is_empty(),
+        "no processes are listed, is the platform supported by sysinfo?"
+    );
+
+    let mut infos = Vec::with_capacity(system.processes().len());
+    let mut names = NamePool::default();
+    for proc in system.processes().values() {
+        let uid = proc.effective_user_id().or(proc.user_id()).map(|uid| **uid);
+        // kernel threads and threads of processes, they are listed on Linux
+        if proc.thread_kind().is_some()
+            || options
+                .uid
+                .is_some_and(|owner| uid.is_some_and(|uid| uid != owner))
+        {
+            continue;
+        }
+
+        let pid = proc.pid().as_u32() as Pid;
+        let mut info = ProcessInfo::new(pid, names.intern(&proc.name().to_string_lossy()));
+        info.uid = uid;
+        if options.read_cmdline {
+            let args = proc
+                .cmd()
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned());
+            info.args = Some(args.collect());
+        }
+        if options.read_exe {
+            info.exe = proc.exe().map(PathBuf::from);
         }
+        let ppid = proc.parent().map_or(0, |ppid| ppid.as_u32() as Pid);
+        infos.push((info, ppid));
+    }
+    let processes = link_processes(infos, options.capacity);
+
+    tracing::debug!(processes = processes.len(), "listed processes");
+    Ok(processes)
+}
+
+/// Tree of read processes linked to their parents. Parents which aren't read, e.g. skipped
+/// processes of other users or exited ones, aren't added, so their children aren't reachable
+#[cfg(any(feature = "procfs", feature = "sysinfo"))]
+fn link_processes(infos: Vec<(ProcessInfo, Pid)>, capacity: usize) -> ProcessTree {
+    let mut processes = ProcessTree::with_capacity_and_hasher(capacity, FxBuildHasher);
+    let mut parents = Vec::with_capacity(infos.len());
+    for (info, ppid) in infos {
+        parents.push((info.pid, ppid));
+        processes.insert(info.pid, Process::new(info));
+    }
 
+    for (pid, ppid) in parents {
         // skipping root process because it's `children` vec is going to be huge and useless
-        if stat.ppid == 1 {
+        if ppid == 1 {
             continue;
         }
+        if let Some(parent) = processes.get_mut(&ppid) {
+            parent.children.push(pid);
+        }
+    }
+    processes
+}
+
+/// Process tree kept between scans, e.g. by a daemon answering many queries. [`Self::refresh`]
+/// reads only `stat` of known processes to notice exited, reparented and exec'd ones, the rest of
+/// files is read for new processes only
+#[cfg(feature = "procfs")]
+#[derive(Default, Clone, Debug)]
+pub struct ProcessCache {
+    options: ScanOptions,
+    processes: ProcessTree,
+    /// Processes having pid among their children: its parent and `flatpak-spawn` it's grafted to,
+    /// so exited processes are unlinked without visiting every process
+    parents: HashMap<Pid, SmallVec<[Pid; 2]>, FxBuildHasher>,
+    scratch: Scratch,
+}
+
+#[cfg(feature = "procfs")]
+impl ProcessCache {
+    /// Empty until [`Self::refresh`], [`ScanOptions::subtree`] is ignored. Start time is read
+    /// regardless of [`ScanOptions::read_start_time`] since it tells changed processes apart
+    pub fn new(options: ScanOptions) -> Self {
+        let processes = ProcessTree::with_capacity_and_hasher(options.capacity, FxBuildHasher);
+        Self {
+            options: Self::cached_options(options),
+            processes,
+            parents: HashMap::default(),
+            scratch: Scratch::default(),
+        }
+    }
+
+    /// Starts over only if `options` differ from the ones of the cache, e.g. of reloaded config
+    pub fn set_options(&mut self, options: ScanOptions) {
+        if Self::cached_options(options.clone()) != self.options {
+            *self = Self::new(options);
+        }
+    }
+
+    fn cached_options(options: ScanOptions) -> ScanOptions {
+        ScanOptions {
+            read_start_time: true,
+            ..options
+        }
+    }
+
+    pub fn processes(&self) -> &ProcessTree {
+        &self.processes
+    }
+
+    pub fn into_processes(self) -> ProcessTree {
+        self.processes
+    }
+
+    /// Brings the tree up to date with /proc
+    pub fn refresh(&mut self) -> anyhow::Result<()> {
+        let mut parents = Vec::with_capacity(self.processes.len());
+        let mut read = 0;
+        for proc in procfs::process::all_processes().context("read /proc")? {
+            // Process could die by the time we come to it, it's normal
+            let Ok(proc) = proc else {
+                continue;
+            };
+            if self.options.skips(&proc) {
+                continue;
+            }
+            let Ok(stat) = proc.stat() else {
+                continue;
+            };
+            if is_kernel_thread(&stat) {
+                continue;
+            }
+
+            // the same pid may be reused by another process or command may be replaced by exec
+            let unchanged = self.processes.get(&proc.pid).is_some_and(|process| {
+                process.info.start_time == Some(stat.starttime) && *process.info.name == stat.comm
+            });
+            if !unchanged {
+                let status = match Status::read(&proc, &mut self.scratch) {
+                    Ok(status) => status,
+                    Err(err) => {
+                        tracing::trace!("skip process: {err:#}");
+                        continue;
+                    }
+                };
+                let mut info = read_info(&proc, status, &self.options);
+                info.start_time = Some(stat.starttime);
+                self.processes.insert(proc.pid, Process::new(info));
+                read += 1;
+            }
+            parents.push((proc.pid, stat.ppid));
+        }
+
+        let alive: HashSet<_, FxBuildHasher> = parents.iter().map(|&(pid, _)| pid).collect();
+        self.processes.retain(|pid, _| alive.contains(pid));
+        self.scratch.names.shrink();
+        for process in self.processes.values_mut() {
+            process.children.clear();
+        }
+        self.parents.clear();
+        for (pid, ppid) in parents {
+            self.adopt(ppid, pid);
+        }
+        self.graft_flatpak_spawns();
+
+        tracing::debug!(
+            processes = self.processes.len(),
+            read,
+            "refreshed process tree"
+        );
+        Ok(())
+    }
+
+    /// Updates the tree with events of [`connector::ProcConnector`] without rescanning /proc,
+    /// unless some events are lost
+    #[cfg(feature = "proc-connector")]
+    pub fn apply(&mut self, events: &[ProcessEvent]) -> anyhow::Result<()> {
+        if events.contains(&ProcessEvent::Lost) {
+            tracing::debug!("proc events are lost");
+            return self.refresh();
+        }
+
+        let mut forked = false;
+        for event in events {
+            match *event {
+                ProcessEvent::Fork { parent, child } => {
+                    forked = true;
+                    let Some(info) = self.read(child) else {
+                        continue;
+                    };
+                    self.processes.insert(child, Process::new(info));
+                    self.adopt(parent, child);
+                }
+                ProcessEvent::Exec(pid) | ProcessEvent::Comm(pid) => {
+                    let Some(info) = self.read(pid) else {
+                        continue;
+                    };
+                    if let Some(process) = self.processes.get_mut(&pid) {
+                        process.info = info;
+                    }
+                }
+                ProcessEvent::Exit(pid) => {
+                    let parents = self.parents.remove(&pid).unwrap_or_default();
+                    let Some(process) = self.processes.remove(&pid) else {
+                        continue;
+                    };
+                    for parent in parents {
+                        if let Some(parent) = self.processes.get_mut(&parent) {
+                            parent.children.retain(|child| *child != pid);
+                        }
+                    }
+                    // orphans are reparented to init or the nearest subreaper
+                    for child in process.children {
+                        if let Some(parents) = self.parents.get_mut(&child) {
+                            parents.retain(|parent| *parent != pid);
+                        }
+                        let proc = procfs::process::Process::new(child);
+                        if let Ok(stat) = proc.and_then(|proc| proc.stat()) {
+                            self.adopt(stat.ppid, child);
+                        }
+                    }
+                }
+                ProcessEvent::Lost => {}
+            }
+        }
+
+        if forked {
+            self.graft_flatpak_spawns();
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "proc-connector")]
+    fn read(&mut self, pid: Pid) -> Option<ProcessInfo> {
+        let proc = procfs::process::Process::new(pid).context("open process");
+        if proc.as_ref().is_ok_and(|proc| self.options.skips(proc)) {
+            return None;
+        }
+        let read = proc.and_then(|proc| read_process(&proc, &self.options, &mut self.scratch));
+        // short-lived processes are gone by the time events are handled
+        read.inspect_err(|err| tracing::trace!(pid, "skip process: {err:#}"))
+            .ok()
+            .flatten()
+            .map(|(info, _)| info)
+    }
 
-        processes
-            .entry(stat.ppid)
-            .and_modify(|pproc| pproc.children.push(proc.pid()))
-            .or_insert(Process::new_with_children(
-                Default::default(),
-                vec![proc.pid],
-            ));
+    fn adopt(&mut self, parent: Pid, child: Pid) {
+        // see `link_processes`
+        if parent == 1 {
+            return;
+        }
+        if let Some(process) = self.processes.get_mut(&parent)
+            && !process.children.contains(&child)
+        {
+            process.children.push(child);
+            self.parents.entry(child).or_default().push(parent);
+        }
     }
 
+    fn graft_flatpak_spawns(&mut self) {
+        for (spawn, command) in graft_flatpak_spawns(&mut self.processes) {
+            self.parents.entry(command).or_default().push(spawn);
+        }
+    }
+}
+
+/// Processes below `root` if [`ScanOptions::subtree`] is set, every process otherwise. Falls back
+/// to full scan if kernel lacks `children` files, i.e. it's built without `CONFIG_PROC_CHILDREN`
+#[cfg(feature = "procfs")]
+pub fn build_process_tree_from(root: Pid, options: &ScanOptions) -> anyhow::Result<ProcessTree> {
+    let _span = tracing::debug_span!("process scan").entered();
+    if options.subtree {
+        match build_subtree(root, options) {
+            Ok(processes) => return Ok(processes),
+            Err(err) => tracing::debug!("scan every process: {err:#}"),
+        }
+    }
+    scan_all(options)
+}
+
+/// Processes below `root` including it found through `/proc/<pid>/task/<tid>/children`, so only
+/// a handful of processes are read. Commands of `flatpak-spawn` aren't linked to it
+#[cfg(feature = "procfs")]
+pub fn build_subtree(root: Pid, options: &ScanOptions) -> anyhow::Result<ProcessTree> {
+    let mut processes = ProcessTree::default();
+    let mut pending = vec![root];
+    let mut scratch = Scratch::default();
+    while let Some(pid) = pending.pop() {
+        let read = procfs::process::Process::new(pid)
+            .context("open process")
+            .and_then(|proc| {
+                anyhow::ensure!(!options.skips(&proc), "process of another user");
+                let (info, _) =
+                    read_process(&proc, options, &mut scratch)?.context("kernel thread")?;
+                Ok((info, children(&proc)?))
+            });
+        let (info, children) = match read {
+            Ok(read) => read,
+            Err(err) if pid == root => return Err(err),
+            // Process could die by the time we come to it, it's normal
+            Err(err) => {
+                tracing::trace!(pid, "skip process: {err:#}");
+                continue;
+            }
+        };
+        pending.extend(&children);
+        processes.insert(pid, Process::new_with_children(info, children));
+    }
+
+    tracing::debug!(root, processes = processes.len(), "scanned subtree");
     Ok(processes)
 }
+
+/// Children of every thread of process
+#[cfg(feature = "procfs")]
+fn children(proc: &procfs::process::Process) -> anyhow::Result<Children> {
+    let mut children = Children::new();
+    for task in proc.tasks().context("read tasks")? {
+        let Ok(task) = task else {
+            continue;
+        };
+        let pids = task.children().context("read children file")?;
+        children.extend(pids.into_iter().map(|pid| pid as Pid));
+    }
+    Ok(children)
+}
+
+/// Name of process run in Flatpak sandbox to run a command on host, e.g. shell of a terminal
+#[cfg(feature = "procfs")]
+const FLATPAK_SPAWN: &str = "flatpak-spawn";
+/// Names of processes running commands of `flatpak-spawn`: host helper of `--host` ones, truncated
+/// by kernel, and portal running sandboxed ones through `bwrap`
+#[cfg(feature = "procfs")]
+const FLATPAK_HELPERS: [&str; 2] = ["flatpak-session", "flatpak-portal"];
+
+/// Command run by `flatpak-spawn` process with `args`, i.e. arguments after its options. Options
+/// with values are expected in `--option=value` form as terminals pass them
+#[cfg(feature = "procfs")]
+fn spawned_command(args: &[String]) -> &[String] {
+    let args = args.get(1..).unwrap_or_default();
+    match args
+        .iter()
+        .position(|arg| arg == "--" || !arg.starts_with('-'))
+    {
+        Some(i) if args[i] == "--" => &args[i + 1..],
+        Some(i) => &args[i..],
+        None => &[],
+    }
+}
+
+/// Commands run by `flatpak-spawn` are children of `flatpak-session-helper` on host or of `bwrap`
+/// of `flatpak-portal` for sandboxed ones, so they are unreachable from the sandboxed app, e.g.
+/// shells of a terminal installed as Flatpak. They are added as children of `flatpak-spawn`
+/// processes which started them.
+///
+/// Command line of a command ends with the command of its `flatpak-spawn`, which is the only one
+/// between it and the next spawn of the same command. Spawns whose commands are started out of
+/// order aren't grafted since it's unknown which one is which. Start times and command lines of
+/// these processes are read if they are unknown. Returns spawns and commands grafted to them
+#[cfg(feature = "procfs")]
+fn graft_flatpak_spawns(processes: &mut ProcessTree) -> Vec<(Pid, Pid)> {
+    let start_time = |info: &ProcessInfo| info.start_time.or_else(|| start_time(info.pid).ok());
+    let args = |info: &ProcessInfo| {
+        info.args.clone().or_else(|| {
+            let proc = procfs::process::Process::new(info.pid).ok()?;
+            proc.cmdline().ok()
+        })
+    };
+    let mut spawns: Vec<_> = processes
+        .values()
+        .filter(|process| *process.info.name == *FLATPAK_SPAWN)
+        .filter_map(|process| Some((start_time(&process.info)?, process.info.pid)))
+        .collect();
+    if spawns.is_empty() {
+        return Vec::new();
+    }
+    spawns.sort_unstable();
+    let commands: Vec<_> = processes
+        .values()
+        .filter(|process| FLATPAK_HELPERS.contains(&&*process.info.name))
+        .flat_map(|helper| &helper.children)
+        .filter_map(|pid| {
+            let info = &processes.get(pid)?.info;
+            Some((start_time(info)?, *pid, args(info)?))
+        })
+        .collect();
+
+    let spawns: Vec<_> = spawns
+        .into_iter()
+        .filter_map(|(started, spawn)| {
+            let args = args(&processes[&spawn].info)?;
+            let command = spawned_command(&args).to_vec();
+            (!command.is_empty()).then_some((started, spawn, command))
+        })
+        .collect();
+    let mut grafts = Vec::new();
+    for (i, (spawned_at, spawn, command)) in spawns.iter().enumerate() {
+        // the next spawn of the same command, commands started after it may be its ones
+        let next = spawns[i + 1..]
+            .iter()
+            .find(|(_, _, next)| next == command)
+            .map(|&(started, _, _)| started);
+        let mut started = commands.iter().filter(|(started, _, args)| {
+            started >= spawned_at
+                && next.is_none_or(|next| *started < next)
+                && args.ends_with(command)
+        });
+        match (started.next(), started.next()) {
+            (Some(&(_, pid, _)), None) => grafts.push((*spawn, pid)),
+            (first, _) => tracing::trace!(
+                spawn,
+                found = first.is_some(),
+                "skip ambiguous or missing command of flatpak-spawn"
+            ),
+        }
+    }
+
+    grafts.retain(|&(spawn, command)| {
+        let Some(process) = processes.get_mut(&spawn) else {
+            return false;
+        };
+        if process.children.contains(&command) {
+            return false;
+        }
+        tracing::trace!(spawn, command, "graft command of flatpak-spawn");
+        process.children.push(command);
+        true
+    });
+    grafts
+}
+
+#[cfg(all(test, feature = "procfs"))]
+mod tests {
+    use super::*;
+
+    fn flatpak_process(
+        pid: Pid,
+        name: &str,
+        start_time: u64,
+        args: &str,
+        children: &[Pid],
+    ) -> (Pid, Process) {
+        let info = ProcessInfo {
+            start_time: Some(start_time),
+            args: Some(args.split(' ').map(str::to_owned).collect()),
+            ..ProcessInfo::new(pid, name.to_owned())
+        };
+        (
+            pid,
+            Process::new_with_children(info, children.iter().copied()),
+        )
+    }
+
+    #[test]
+    fn flatpak_spawns() {
+        let spawn = "flatpak-spawn --host --watch-bus --env=TERM=xterm-256color /bin/zsh -l";
+        // sandboxed terminal (2) runs shells through flatpak-spawn (3, 4), host helper (10) runs
+        // them (11, 12) along with unrelated commands (13, 14). Sandboxed `fd` (5) is run by
+        // `bwrap` (21) of the portal (20)
+        let mut processes: ProcessTree = [
+            flatpak_process(2, "ptyxis", 100, "ptyxis", &[3, 4, 5]),
+            flatpak_process(3, FLATPAK_SPAWN, 110, spawn, &[]),
+            flatpak_process(4, FLATPAK_SPAWN, 120, spawn, &[]),
+            flatpak_process(5, FLATPAK_SPAWN, 130, "flatpak-spawn -- fd x", &[]),
+            flatpak_process(
+                10,
+                "flatpak-session",
+                50,
+                "flatpak-session-helper",
+                &[13, 14, 12, 11],
+            ),
+            flatpak_process(11, "zsh", 111, "/bin/zsh -l", &[]),
+            flatpak_process(12, "zsh", 121, "/bin/zsh -l", &[]),
+            flatpak_process(13, "podman", 90, "podman ps", &[]),
+            flatpak_process(14, "htop", 115, "htop", &[]),
+            flatpak_process(20, "flatpak-portal", 50, "flatpak-portal", &[21]),
+            flatpak_process(21, "bwrap", 131, "bwrap --args 42 -- fd x", &[]),
+        ]
+        .into_iter()
+        .collect();
+        graft_flatpak_spawns(&mut processes);
+
+        assert_eq!(processes[&3].children.as_slice(), [11]);
+        assert_eq!(processes[&4].children.as_slice(), [12]);
+        assert_eq!(processes[&5].children.as_slice(), [21]);
+    }
+
+    #[test]
+    fn interleaved_flatpak_spawns() {
+        let spawn = "flatpak-spawn --host zsh";
+        // both shells are started after both spawns, so it's unknown which one is which
+        let mut processes: ProcessTree = [
+            flatpak_process(3, FLATPAK_SPAWN, 110, spawn, &[]),
+            flatpak_process(4, FLATPAK_SPAWN, 111, spawn, &[]),
+            flatpak_process(5, FLATPAK_SPAWN, 112, "flatpak-spawn --host fish", &[]),
+            flatpak_process(
+                10,
+                "flatpak-session",
+                50,
+                "flatpak-session-helper",
+                &[11, 12, 13],
+            ),
+            flatpak_process(11, "zsh", 113, "zsh", &[]),
+            flatpak_process(12, "zsh", 114, "zsh", &[]),
+            flatpak_process(13, "fish", 115, "fish", &[]),
+        ]
+        .into_iter()
+        .collect();
+        graft_flatpak_spawns(&mut processes);
+
+        assert!(processes[&3].children.is_empty());
+        assert!(processes[&4].children.is_empty());
+        assert_eq!(processes[&5].children.as_slice(), [13]);
+    }
+
+    #[test]
+    fn subtree() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        // spawn may return before exec renames the child, e.g. after a signal is handled
+        let comm = format!("/proc/{}/comm", child.id());
+        for _ in 0..100 {
+            if std::fs::read_to_string(&comm).is_ok_and(|name| name == "sleep\n") {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        let root = std::process::id() as Pid;
+        let processes = build_subtree(root, &ScanOptions::default());
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let processes = processes.unwrap();
+        let child = &processes[&(child.id() as Pid)];
+        assert_eq!(&*child.info.name, "sleep");
+        assert!(processes[&root].children.contains(&child.info.pid));
+        assert!(processes.values().all(|process| process.info.pid != 1));
+    }
+
+    #[test]
+    fn cache() {
+        let root = std::process::id() as Pid;
+        let mut cache = ProcessCache::new(ScanOptions::default());
+        cache.refresh().unwrap();
+        assert!(cache.processes().contains_key(&root));
+
+        // start time is read by the cache anyway
+        cache.set_options(ScanOptions {
+            read_start_time: true,
+            ..ScanOptions::default()
+        });
+        assert!(cache.processes().contains_key(&root));
+        cache.set_options(ScanOptions {
+            read_cmdline: true,
+            ..ScanOptions::default()
+        });
+        assert!(cache.processes().is_empty());
+        cache.refresh().unwrap();
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as Pid;
+        cache.refresh().unwrap();
+        assert_eq!(&*cache.processes()[&pid].info.name, "sleep");
+        assert!(cache.processes()[&root].children.contains(&pid));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        cache.refresh().unwrap();
+        assert!(!cache.processes().contains_key(&pid));
+        assert!(!cache.processes()[&root].children.contains(&pid));
+    }
+
+    #[cfg(feature = "proc-connector")]
+    #[test]
+    fn cache_events() {
+        let root = std::process::id() as Pid;
+        let mut cache = ProcessCache::new(ScanOptions::default());
+        cache.refresh().unwrap();
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        let pid = child.id() as Pid;
+        let fork = ProcessEvent::Fork {
+            parent: root,
+            child: pid,
+        };
+        cache.apply(&[fork]).unwrap();
+        assert!(cache.processes()[&root].children.contains(&pid));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+        cache.apply(&[ProcessEvent::Exit(pid)]).unwrap();
+        assert!(!cache.processes().contains_key(&pid));
+        assert!(!cache.processes()[&root].children.contains(&pid));
+        assert!(!cache.parents.contains_key(&pid));
+    }
+
+    #[test]
+    fn shared_names() {
+        let mut children: Vec<_> = (0..4)
+            .map(|_| {
+                std::process::Command::new("sleep")
+                    .arg("10")
+                    .spawn()
+                    .unwrap()
+            })
+            .collect();
+        let processes = build_process_tree_with(&ScanOptions::default()).unwrap();
+        for child in &mut children {
+            child.kill().unwrap();
+            child.wait().unwrap();
+        }
+
+        let mut names: HashMap<&str, &Arc<str>> = HashMap::new();
+        for process in processes.values() {
+            let name = &process.info.name;
+            let interned = names.entry(name).or_insert(name);
+            assert!(Arc::ptr_eq(interned, name), "{name} isn't shared");
+        }
+    }
+
+    #[test]
+    fn other_users() {
+        let pid = std::process::id() as Pid;
+        let uid = nix::unistd::geteuid().as_raw();
+        let scan = |uid| {
+            let options = ScanOptions {
+                uid: Some(uid),
+                ..Default::default()
+            };
+            build_process_tree_with(&options).unwrap()
+        };
+
+        assert_eq!(scan(uid)[&pid].info.pid, pid);
+        assert!(!scan(uid.wrapping_add(1)).contains_key(&pid));
+    }
+
+    #[cfg(feature = "sysinfo")]
+    #[test]
+    fn sysinfo() {
+        let options = ScanOptions {
+            read_cmdline: true,
+            ..ScanOptions::default()
+        };
+        let (sender, receiver) = std::sync::mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || receiver.recv());
+        let processes = build_process_tree_sysinfo(&options).unwrap();
+        let scanned = build_process_tree_with(&options).unwrap();
+        let threads: Vec<Pid> = fs::read_dir("/proc/self/task")
+            .unwrap()
+            .filter_map(|task| task.ok()?.file_name().to_str()?.parse().ok())
+            .collect();
+        drop(sender);
+        _ = thread.join();
+
+        let pid = std::process::id() as Pid;
+        let process = &processes[&pid].info;
+        assert_eq!(process.name, scanned[&pid].info.name);
+        assert_eq!(process.args, scanned[&pid].info.args);
+        assert_eq!(process.uid, scanned[&pid].info.uid);
+        assert!(!processes.contains_key(&2));
+        assert!(threads.len() > 1);
+        let mut tids = threads.iter().filter(|&&tid| tid != pid);
+        assert!(tids.all(|tid| !processes.contains_key(tid)));
+    }
+
+    #[test]
+    fn kernel_threads() {
+        let processes = build_process_tree().unwrap();
+        assert!(processes.values().all(|process| process.info.pid != 2));
+        assert!(!processes.contains_key(&2));
+    }
+
+    #[test]
+    fn status() {
+        let mut names = NamePool::default();
+        let status = "Name:\tzsh\nUmask:\t0022\nState:\tS (sleeping)\nPPid:\t4200\n\
+            Uid:\t0\t1000\t1000\t1000\nNSpid:\t4242\t17\nNSsid:\t1\nKthread:\t0\n";
+        let zsh = Status::parse(status.as_bytes(), &mut names).unwrap();
+        assert_eq!(&*zsh.name, "zsh");
+        assert_eq!(zsh.ppid, 4200);
+        assert_eq!(zsh.uid, Some(1000));
+        assert_eq!(zsh.ns_pid, Some(17));
+        assert!(!zsh.is_kernel_thread(4242));
+
+        let status = "Name:\tzsh\nPPid:\t2\nNSpid:\t4242\n";
+        let kthread = Status::parse(status.as_bytes(), &mut names).unwrap();
+        assert_eq!(kthread.ns_pid, None);
+        assert!(kthread.is_kernel_thread(4242));
+        // the same name is shared
+        assert!(Arc::ptr_eq(&kthread.name, &zsh.name));
+        let status = Status::parse("Name:\tzsh\nPPid:\t1\n".as_bytes(), &mut names);
+        assert_eq!(status.unwrap().ns_pid, None);
+        assert!(Status::parse("State:\tS\n".as_bytes(), &mut names).is_none());
+        assert!(Status::parse("Name:\tzsh\n".as_bytes(), &mut names).is_none());
+
+        drop((zsh, kthread));
+        names.shrink();
+        assert!(names.names.is_empty());
+    }
+}
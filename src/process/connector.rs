@@ -0,0 +1,248 @@
+//! Kernel proc connector, a netlink socket reporting fork, exec and exit of every process
+
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+
+use anyhow::Context;
+use nix::errno::Errno;
+use nix::poll::{PollFd, PollFlags, PollTimeout};
+use nix::sys::socket::{self, MsgFlags, NetlinkAddr};
+
+use crate::process::Pid;
+
+/// Missing from `libc` for Linux, see `linux/netlink.h`
+const NETLINK_CONNECTOR: libc::c_int = 11;
+/// Type of single part netlink message
+const NLMSG_DONE: u16 = 3;
+const NLMSG_HEADER_LEN: usize = 16;
+const CN_MSG_HEADER_LEN: usize = 20;
+/// `what`, `cpu` and `timestamp_ns` of `proc_event` precede its data
+const PROC_EVENT_HEADER_LEN: usize = 16;
+const EVENT_OFFSET: usize = NLMSG_HEADER_LEN + CN_MSG_HEADER_LEN;
+const DATA_OFFSET: usize = EVENT_OFFSET + PROC_EVENT_HEADER_LEN;
+
+/// Change of a process reported by [`ProcConnector`], events of threads are skipped
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ProcessEvent {
+    /// `child` is started by `parent`
+    Fork {
+        parent: Pid,
+        child: Pid,
+    },
+    /// Process runs another program
+    Exec(Pid),
+    /// Process has changed its name, e.g. with `prctl(PR_SET_NAME)`
+    Comm(Pid),
+    Exit(Pid),
+    /// Socket buffer has overflowed, some events are lost and the tree should be rebuilt
+    Lost,
+}
+
+/// Subscription to the kernel proc connector, it requires `CAP_NET_ADMIN` and kernel built with
+/// `CONFIG_PROC_EVENTS`
+#[derive(Debug)]
+pub struct ProcConnector {
+    socket: OwnedFd,
+}
+
+impl ProcConnector {
+    pub fn new() -> anyhow::Result<Self> {
+        // SAFETY: plain syscall, the descriptor is owned right after
+        let fd = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                NETLINK_CONNECTOR,
+            )
+        };
+        if fd < 0 {
+            return Err(Errno::last()).context("create netlink socket");
+        }
+        // SAFETY: `fd` is a new descriptor nothing else owns
+        let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+        // port id is assigned by kernel
+        let addr = NetlinkAddr::new(0, libc::CN_IDX_PROC);
+        socket::bind(socket.as_raw_fd(), &addr).context("subscribe to proc connector")?;
+
+        let connector = Self { socket };
+        connector
+            .send_op(libc::PROC_CN_MCAST_LISTEN)
+            .context("listen to proc connector")?;
+        Ok(connector)
+    }
+
+    fn send_op(&self, op: libc::proc_cn_mcast_op) -> nix::Result<()> {
+        let len = EVENT_OFFSET + 4;
+        let mut message = Vec::with_capacity(len);
+        // nlmsghdr: len, type, flags, seq, port id
+        message.extend((len as u32).to_ne_bytes());
+        message.extend(NLMSG_DONE.to_ne_bytes());
+        message.extend(0u16.to_ne_bytes());
+        message.extend(0u32.to_ne_bytes());
+        message.extend(0u32.to_ne_bytes());
+        // cn_msg: idx, val, seq, ack, len, flags
+        message.extend(libc::CN_IDX_PROC.to_ne_bytes());
+        message.extend(libc::CN_VAL_PROC.to_ne_bytes());
+        message.extend(0u32.to_ne_bytes());
+        message.extend(0u32.to_ne_bytes());
+        message.extend(4u16.to_ne_bytes());
+        message.extend(0u16.to_ne_bytes());
+        message.extend(op.to_ne_bytes());
+
+        let kernel = NetlinkAddr::new(0, 0);
+        socket::sendto(
+            self.socket.as_raw_fd(),
+            &message,
+            &kernel,
+            MsgFlags::empty(),
+        )?;
+        Ok(())
+    }
+
+    /// Blocks until some processes change
+    pub fn next_events(&self) -> anyhow::Result<Vec<ProcessEvent>> {
+        loop {
+            let events = self.receive()?;
+            if !events.is_empty() {
+                return Ok(events);
+            }
+        }
+    }
+
+    /// Same as [`Self::next_events`] but returns `None` once `stop` is readable or closed, e.g.
+    /// when write end of a pipe is dropped, so the thread waiting for events can be joined
+    pub fn next_events_until(
+        &self,
+        stop: BorrowedFd<'_>,
+    ) -> anyhow::Result<Option<Vec<ProcessEvent>>> {
+        loop {
+            let mut fds = [
+                PollFd::new(self.socket.as_fd(), PollFlags::POLLIN),
+                PollFd::new(stop, PollFlags::POLLIN),
+            ];
+            match nix::poll::poll(&mut fds, PollTimeout::NONE) {
+                Ok(_) => {}
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(err).context("wait for proc events"),
+            }
+            if fds[1].revents().is_some_and(|events| !events.is_empty()) {
+                return Ok(None);
+            }
+            if fds[0].revents().is_some_and(|events| !events.is_empty()) {
+                let events = self.receive()?;
+                if !events.is_empty() {
+                    return Ok(Some(events));
+                }
+            }
+        }
+    }
+
+    /// Events of a single datagram, empty if it has only events of threads
+    fn receive(&self) -> anyhow::Result<Vec<ProcessEvent>> {
+        let mut buf = [0u8; 4096];
+        let len = loop {
+            match socket::recv(self.socket.as_raw_fd(), &mut buf, MsgFlags::empty()) {
+                Ok(len) => break len,
+                Err(Errno::ENOBUFS) => return Ok(vec![ProcessEvent::Lost]),
+                Err(Errno::EINTR) => continue,
+                Err(err) => return Err(err).context("receive proc events"),
+            }
+        };
+
+        let mut events = Vec::new();
+        let mut messages = &buf[..len];
+        while let Some(message_len) = read_u32(messages, 0) {
+            let message_len = message_len as usize;
+            if message_len < NLMSG_HEADER_LEN || message_len > messages.len() {
+                break;
+            }
+            events.extend(parse_event(&messages[..message_len]));
+            // messages are aligned to 4 bytes
+            let aligned = message_len.next_multiple_of(4).min(messages.len());
+            messages = &messages[aligned..];
+        }
+        Ok(events)
+    }
+}
+
+impl Drop for ProcConnector {
+    fn drop(&mut self) {
+        // kernel counts listeners to skip sending events when there are none
+        _ = self.send_op(libc::PROC_CN_MCAST_IGNORE);
+    }
+}
+
+/// Lets async runtimes wait for readiness instead of blocking on [`ProcConnector::next_events`]
+impl AsFd for ProcConnector {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.socket.as_fd()
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+fn read_pid(buf: &[u8], offset: usize) -> Option<Pid> {
+    read_u32(buf, DATA_OFFSET + offset).map(|pid| pid as Pid)
+}
+
+/// Parses a netlink message holding `proc_event`, see `linux/cn_proc.h`
+fn parse_event(message: &[u8]) -> Option<ProcessEvent> {
+    let what = read_u32(message, EVENT_OFFSET)?;
+    // data starts with pid and tgid of the process, they differ for threads
+    let process = || {
+        let pid = read_pid(message, 0)?;
+        (read_pid(message, 4)? == pid).then_some(pid)
+    };
+    match what {
+        libc::PROC_EVENT_FORK => {
+            // parent pid, parent tgid, child pid, child tgid
+            let child = read_pid(message, 8)?;
+            (read_pid(message, 12)? == child).then_some(ProcessEvent::Fork {
+                parent: read_pid(message, 4)?,
+                child,
+            })
+        }
+        libc::PROC_EVENT_EXEC => process().map(ProcessEvent::Exec),
+        libc::PROC_EVENT_COMM => process().map(ProcessEvent::Comm),
+        libc::PROC_EVENT_EXIT => process().map(ProcessEvent::Exit),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(what: u32, data: &[i32]) -> Vec<u8> {
+        let mut message = vec![0; DATA_OFFSET];
+        message[EVENT_OFFSET..EVENT_OFFSET + 4].copy_from_slice(&what.to_ne_bytes());
+        message.extend(data.iter().flat_map(|value| value.to_ne_bytes()));
+        message
+    }
+
+    #[test]
+    fn events() {
+        let fork = message(libc::PROC_EVENT_FORK, &[10, 10, 11, 11]);
+        assert_eq!(
+            parse_event(&fork),
+            Some(ProcessEvent::Fork {
+                parent: 10,
+                child: 11
+            })
+        );
+        // a thread is started
+        let clone = message(libc::PROC_EVENT_FORK, &[10, 10, 12, 11]);
+        assert_eq!(parse_event(&clone), None);
+
+        let exec = message(libc::PROC_EVENT_EXEC, &[11, 11]);
+        assert_eq!(parse_event(&exec), Some(ProcessEvent::Exec(11)));
+        let exit = message(libc::PROC_EVENT_EXIT, &[11, 11, 0, 17, 10, 10]);
+        assert_eq!(parse_event(&exit), Some(ProcessEvent::Exit(11)));
+        let thread_exit = message(libc::PROC_EVENT_EXIT, &[12, 11, 0, 17, 10, 10]);
+        assert_eq!(parse_event(&thread_exit), None);
+
+        assert_eq!(parse_event(&exec[..DATA_OFFSET]), None);
+    }
+}
@@ -0,0 +1,216 @@
+//! Process trees built without /proc, e.g. to test search deterministically
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::process::{Pid, Process, ProcessInfo, ProcessTree};
+
+/// Builds [`ProcessTree`] top down, pids are assigned in order of processes starting from 1:
+///
+/// ```
+/// # use current_location::process::fixture::TreeBuilder;
+/// // kitty (1) -> zsh (2) -> [htop (3), nvim (4)]
+/// let processes = TreeBuilder::new("kitty")
+///     .child("zsh")
+///     .child("htop")
+///     .up()
+///     .child("nvim")
+///     .build();
+/// assert_eq!(processes.len(), 4);
+/// ```
+#[derive(Clone, Debug)]
+pub struct TreeBuilder {
+    processes: ProcessTree,
+    /// Pids from root to the current process
+    path: Vec<Pid>,
+    next_pid: Pid,
+}
+
+impl TreeBuilder {
+    /// Tree of a single process, it's the current one
+    pub fn new(name: &str) -> Self {
+        let mut builder = Self {
+            processes: ProcessTree::default(),
+            path: vec![],
+            next_pid: 1,
+        };
+        builder.push(name);
+        builder
+    }
+
+    /// Adds a child of the current process, the child becomes the current one
+    pub fn child(mut self, name: &str) -> Self {
+        self.push(name);
+        self
+    }
+
+    /// Makes parent of the current process the current one, root stays current
+    pub fn up(mut self) -> Self {
+        if self.path.len() > 1 {
+            self.path.pop();
+        }
+        self
+    }
+
+    /// Changes the current process, e.g. sets its arguments or owner
+    pub fn with(mut self, f: impl FnOnce(&mut ProcessInfo)) -> Self {
+        let pid = self.pid();
+        f(&mut self.processes.get_mut(&pid).expect("current process").info);
+        self
+    }
+
+    pub fn args(self, args: &[&str]) -> Self {
+        let args = args.iter().map(|arg| (*arg).to_owned()).collect();
+        self.with(|info| info.args = Some(args))
+    }
+
+    pub fn uid(self, uid: u32) -> Self {
+        self.with(|info| info.uid = Some(uid))
+    }
+
+    /// Pid of the current process
+    pub fn pid(&self) -> Pid {
+        *self.path.last().expect("root process")
+    }
+
+    pub fn build(self) -> ProcessTree {
+        self.processes
+    }
+
+    fn push(&mut self, name: &str) {
+        let pid = self.next_pid;
+        self.next_pid += 1;
+        self.insert(ProcessInfo::new(pid, name.to_owned()));
+        self.path.push(pid);
+    }
+
+    /// Adds `info` as a child of the current process if there is one
+    fn insert(&mut self, info: ProcessInfo) {
+        let pid = info.pid;
+        if let Some(parent) = self.path.last() {
+            let parent = self.processes.get_mut(parent).expect("current process");
+            parent.children.push(pid);
+        }
+        self.processes.insert(pid, Process::new(info));
+    }
+}
+
+/// Process of a JSON fixture, only `name` is required. Processes without `pid` are numbered after
+/// the largest one of the fixture:
+///
+/// ``` json
+/// {
+///     "name": "kitty",
+///     "children": [{ "name": "zsh", "pid": 42, "uid": 1000, "children": [{ "name": "nvim" }] }]
+/// }
+/// ```
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+struct FixtureProcess {
+    name: String,
+    pid: Option<Pid>,
+    args: Option<Vec<String>>,
+    start_time: Option<u64>,
+    ns_pid: Option<Pid>,
+    uid: Option<u32>,
+    exe: Option<PathBuf>,
+    #[serde(default)]
+    children: Vec<FixtureProcess>,
+}
+
+impl FixtureProcess {
+    fn max_pid(&self) -> Pid {
+        let children = self.children.iter().map(Self::max_pid);
+        children.chain(self.pid).max().unwrap_or_default()
+    }
+
+    fn add_to(self, builder: &mut TreeBuilder) -> anyhow::Result<()> {
+        let pid = self.pid.unwrap_or_else(|| {
+            builder.next_pid += 1;
+            builder.next_pid - 1
+        });
+        anyhow::ensure!(
+            !builder.processes.contains_key(&pid),
+            "pid {pid} is duplicated"
+        );
+        builder.insert(ProcessInfo {
+            pid,
+            name: self.name.into(),
+            args: self.args,
+            start_time: self.start_time,
+            ns_pid: self.ns_pid,
+            uid: self.uid,
+            exe: self.exe,
+        });
+
+        builder.path.push(pid);
+        for child in self.children {
+            child.add_to(builder)?;
+        }
+        builder.path.pop();
+        Ok(())
+    }
+}
+
+/// Parses process tree from JSON, see [`load`] for its format
+pub fn from_json(json: &str) -> anyhow::Result<ProcessTree> {
+    let root: FixtureProcess = serde_json::from_str(json).context("parse process tree")?;
+    let mut builder = TreeBuilder {
+        processes: ProcessTree::default(),
+        path: vec![],
+        next_pid: root.max_pid() + 1,
+    };
+    root.add_to(&mut builder)?;
+    Ok(builder.build())
+}
+
+/// Reads process tree from JSON file. It's the root process with `name` and optional `pid`,
+/// `args`, `start_time`, `ns_pid`, `uid`, `exe` and `children`
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<ProcessTree> {
+    let path = path.as_ref();
+    let json = fs::read_to_string(path).with_context(|| format!("read {}", path.display()))?;
+    from_json(&json).with_context(|| format!("load {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk::Node;
+
+    #[test]
+    fn json() {
+        let processes = from_json(
+            r#"{
+                "name": "kitty",
+                "children": [
+                    { "name": "zsh", "pid": 42, "uid": 1000, "children": [{ "name": "nvim" }] },
+                    { "name": "htop", "args": ["htop", "-t"] }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let names = |pid| {
+            let process = &processes[&pid];
+            let children = process.children(&processes);
+            let mut names: Vec<_> = children.map(|child| &*child.data().name).collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(43), ["htop", "zsh"]);
+        assert_eq!(names(42), ["nvim"]);
+        assert_eq!(processes[&42].data().uid, Some(1000));
+        assert!(processes[&45].data().has_arg("-t"));
+
+        assert!(
+            from_json(r#"{ "name": "zsh", "children": [{ "name": "nvim", "pid": 1 }] }"#).is_ok()
+        );
+        assert!(
+            from_json(r#"{ "name": "zsh", "pid": 1, "children": [{ "name": "nvim", "pid": 1 }] }"#)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,290 @@
+//! Python module `current_location`, functions read config from `config` path or its default one
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+use crate::config::Config;
+use crate::error::ErrorCode;
+use crate::process::Pid;
+use crate::registry::{LocationRegistry, RegistryEntry};
+use crate::{LocationData, SearchResult};
+
+create_exception!(
+    current_location,
+    LocationError,
+    PyException,
+    "Failure of current-location"
+);
+
+fn error(err: impl Into<anyhow::Error>) -> PyErr {
+    LocationError::new_err(format!("{:#}", err.into()))
+}
+
+fn load_config(path: Option<&Path>) -> PyResult<Config> {
+    Config::load_from(path).map_err(|err| error(err.context(ErrorCode::Config)))
+}
+
+/// Location written by a process or found otherwise
+#[pyclass(name = "Location", module = "current_location", frozen)]
+#[derive(Clone)]
+struct PyLocation(LocationData);
+
+#[pymethods]
+impl PyLocation {
+    #[getter]
+    fn location(&self) -> &Path {
+        self.0.location()
+    }
+
+    #[getter]
+    fn nvim_pipe(&self) -> Option<&str> {
+        self.0.nvim_pipe()
+    }
+
+    #[getter]
+    fn line(&self) -> Option<u32> {
+        self.0.line()
+    }
+
+    #[getter]
+    fn column(&self) -> Option<u32> {
+        self.0.column()
+    }
+
+    #[getter]
+    fn project_root(&self) -> Option<&Path> {
+        self.0.project_root()
+    }
+
+    #[getter]
+    fn window(&self) -> Option<&str> {
+        self.0.window()
+    }
+
+    #[getter]
+    fn terminal(&self) -> Option<&str> {
+        self.0.terminal()
+    }
+
+    #[getter]
+    fn extra(&self) -> HashMap<String, String> {
+        self.0.extra().clone()
+    }
+
+    #[getter]
+    fn written_at(&self) -> Option<SystemTime> {
+        self.0.written_at()
+    }
+
+    /// No known process is found and fallback location of config is used
+    #[getter]
+    fn is_fallback(&self) -> bool {
+        self.0.is_fallback()
+    }
+
+    /// Working directory of known process which hasn't written its location
+    #[getter]
+    fn is_cwd_fallback(&self) -> bool {
+        self.0.is_cwd_fallback()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Location({:?})", self.0.location())
+    }
+}
+
+/// Known process selected by `search`
+#[pyclass(name = "SearchResult", module = "current_location", frozen, get_all)]
+struct PySearchResult {
+    pid: Pid,
+    name: String,
+    /// Depth below process of active window
+    depth: u64,
+    /// Same as `get` would return, fallback one if the process hasn't written its location
+    location: PyLocation,
+}
+
+#[pymethods]
+impl PySearchResult {
+    fn __repr__(&self) -> String {
+        format!("SearchResult(pid={}, name={:?})", self.pid, self.name)
+    }
+}
+
+/// Registry entry of a process, see `entries`
+#[pyclass(name = "Entry", module = "current_location", frozen, get_all)]
+struct PyEntry {
+    pid: Pid,
+    name: String,
+    location: PyLocation,
+}
+
+#[pymethods]
+impl PyEntry {
+    fn __repr__(&self) -> String {
+        format!("Entry(pid={}, name={:?})", self.pid, self.name)
+    }
+}
+
+fn search_result(
+    result: SearchResult,
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> crate::error::Result<PySearchResult> {
+    let data = crate::read_location(Some(result.resolved), registry, config)?;
+    Ok(PySearchResult {
+        pid: result.process.pid,
+        name: result.process.name.to_string(),
+        depth: result.depth,
+        location: PyLocation(data),
+    })
+}
+
+fn entry_list(
+    registry: &impl LocationRegistry,
+    config: &Config,
+) -> crate::error::Result<Vec<PyEntry>> {
+    let entries = crate::entries(registry, config)?
+        .into_iter()
+        .map(|(RegistryEntry { pid, name }, data)| PyEntry {
+            pid,
+            name,
+            location: PyLocation(data),
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// Known process of active window or of `active_pid` and its location, `None` if there is no
+/// known process
+#[pyfunction]
+#[pyo3(signature = (active_pid = None, config = None))]
+fn search(
+    py: Python<'_>,
+    active_pid: Option<Pid>,
+    config: Option<PathBuf>,
+) -> PyResult<Option<PySearchResult>> {
+    let config = load_config(config.as_deref())?;
+    py.detach(|| {
+        let Some(result) = crate::search_detected(active_pid, &config).map_err(error)? else {
+            return Ok(None);
+        };
+        let registry = config
+            .registry()
+            .context(ErrorCode::Registry)
+            .map_err(error)?;
+        search_result(result, &registry, &config)
+            .map(Some)
+            .map_err(error)
+    })
+}
+
+/// Location of active window or of `active_pid`, same as `current-location get`
+#[pyfunction]
+#[pyo3(signature = (active_pid = None, config = None))]
+fn get(py: Python<'_>, active_pid: Option<Pid>, config: Option<PathBuf>) -> PyResult<PyLocation> {
+    let config = load_config(config.as_deref())?;
+    py.detach(|| {
+        let registry = config
+            .registry()
+            .context(ErrorCode::Registry)
+            .map_err(error)?;
+        crate::get_detected(active_pid, &registry, &config)
+            .map(PyLocation)
+            .map_err(error)
+    })
+}
+
+/// Entries of running processes in Location Registry, broken and expired ones are skipped
+#[pyfunction]
+#[pyo3(signature = (config = None))]
+fn entries(py: Python<'_>, config: Option<PathBuf>) -> PyResult<Vec<PyEntry>> {
+    let config = load_config(config.as_deref())?;
+    py.detach(|| {
+        let registry = config
+            .registry()
+            .context(ErrorCode::Registry)
+            .map_err(error)?;
+        entry_list(&registry, &config).map_err(error)
+    })
+}
+
+#[pymodule]
+fn current_location(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("LocationError", m.py().get_type::<LocationError>())?;
+    m.add_class::<PyLocation>()?;
+    m.add_class::<PySearchResult>()?;
+    m.add_class::<PyEntry>()?;
+    m.add_function(wrap_pyfunction!(search, m)?)?;
+    m.add_function(wrap_pyfunction!(get, m)?)?;
+    m.add_function(wrap_pyfunction!(entries, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::config::Config;
+    use crate::process::fixture::TreeBuilder;
+    use crate::registry::LocationRegistry;
+    use crate::registry::memory::MemoryRegistry;
+    use crate::{LocationData, search_in, unix_time};
+
+    use super::{entry_list, search_result};
+
+    #[test]
+    fn search_result_location() {
+        let processes = TreeBuilder::new("kitty").child("zsh").build();
+        let config = Config {
+            known_procs: vec!["zsh".to_owned()],
+            cwd_fallback: false,
+            entry_ttl: Some(60),
+            ..Config::default()
+        };
+        let registry = MemoryRegistry::new();
+        let result = || search_in(&processes, 1, &config).unwrap().unwrap();
+        let written = |written_at| LocationData {
+            written_at: Some(written_at),
+            ..LocationData::new("/srv".into())
+        };
+
+        registry.write("zsh", &[2], &written(unix_time())).unwrap();
+        let found = search_result(result(), &registry, &config).unwrap();
+        assert_eq!((found.pid, found.name.as_str(), found.depth), (2, "zsh", 1));
+        assert_eq!(found.location.0.location(), Path::new("/srv"));
+
+        // stale entry isn't returned as is
+        registry.write("zsh", &[2], &written(0)).unwrap();
+        let found = search_result(result(), &registry, &config).unwrap();
+        assert!(found.location.0.is_fallback());
+    }
+
+    #[test]
+    fn entries() {
+        let config = Config {
+            entry_ttl: Some(60),
+            ..Config::default()
+        };
+        let registry = MemoryRegistry::new();
+        let written = |written_at| LocationData {
+            written_at: Some(written_at),
+            ..LocationData::new("/srv".into())
+        };
+        registry.write("zsh", &[2], &written(unix_time())).unwrap();
+        registry.write("fish", &[3], &written(0)).unwrap();
+
+        let entries = entry_list(&registry, &config).unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.pid, entry.name.as_str()))
+            .collect();
+        assert_eq!(entries, [(2, "zsh")]);
+    }
+}
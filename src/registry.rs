@@ -0,0 +1,224 @@
+#[cfg(feature = "registry")]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+#[cfg(feature = "registry")]
+use std::path::Path;
+
+#[cfg(feature = "registry")]
+use anyhow::{Context, ensure};
+use serde::{Deserialize, Serialize};
+
+use crate::LocationData;
+use crate::error::RegistryError;
+use crate::process::Pid;
+
+#[cfg(feature = "registry")]
+mod format;
+#[cfg(feature = "registry")]
+pub mod fs;
+pub mod memory;
+#[cfg(feature = "registry")]
+pub mod socket;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "registry")]
+pub mod watch;
+
+#[cfg(feature = "registry")]
+pub use format::{FORMAT_VERSION, RegistryFormat};
+#[cfg(feature = "registry")]
+pub(crate) use format::{decode, encode};
+
+/// Location of a specific process in Location Registry
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RegistryEntry {
+    pub pid: Pid,
+    /// Name of the process, set by `write`
+    pub name: String,
+}
+
+/// Storage of locations written by known processes, implement it to keep them elsewhere. `anyhow`
+/// errors are converted with `?`, see [`RegistryError`]
+pub trait LocationRegistry {
+    /// Returns `None` if the process hasn't written its location
+    fn read(&self, entry: &RegistryEntry) -> Result<Option<LocationData>, RegistryError>;
+
+    /// Writes the same location for all `pids`, replacing previous ones
+    fn write(&self, name: &str, pids: &[Pid], data: &LocationData) -> Result<(), RegistryError>;
+
+    /// Removes single entry, does nothing if there is no such entry
+    fn remove(&self, entry: &RegistryEntry) -> Result<(), RegistryError>;
+
+    /// Removes all entries
+    fn clear(&self) -> Result<(), RegistryError>;
+
+    /// Locations written by the process, the most recent first. Registries keeping no history
+    /// return only the current one
+    fn history(&self, entry: &RegistryEntry) -> Result<Vec<LocationData>, RegistryError> {
+        Ok(self.read(entry)?.into_iter().collect())
+    }
+
+    /// Iterates over all entries, entries which fail to read are yielded as errors
+    fn iter(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(RegistryEntry, LocationData), RegistryError>>,
+        RegistryError,
+    >;
+
+    /// Rewrites entries stored with older [`FORMAT_VERSION`] or in another [`RegistryFormat`] in
+    /// place, returns number of rewritten entries. Does nothing for registries that aren't persisted
+    fn migrate(&self) -> Result<usize, RegistryError> {
+        Ok(0)
+    }
+}
+
+/// Kind of Location Registry
+#[cfg(feature = "registry")]
+#[derive(Deserialize, Default, Eq, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryBackend {
+    /// File per process, see [`fs::FsRegistry`]
+    #[default]
+    Fs,
+    /// In-memory registry of `registry-daemon`, see [`socket::SocketRegistry`]
+    Socket,
+    /// SQLite database keeping history, see [`sqlite::SqliteRegistry`]
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+/// Location Registry of any of supported kinds
+#[cfg(feature = "registry")]
+#[derive(Debug)]
+pub enum Registry {
+    Fs(fs::FsRegistry),
+    Socket(socket::SocketRegistry),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlite::SqliteRegistry),
+}
+
+#[cfg(feature = "registry")]
+impl Registry {
+    /// Opens registry of given kind in `path` directory, entries are written in `format`
+    pub fn open(
+        backend: RegistryBackend,
+        format: RegistryFormat,
+        path: &Path,
+    ) -> anyhow::Result<Self> {
+        let fs = fs::FsRegistry::new(path.to_owned()).with_format(format);
+        let registry = match backend {
+            RegistryBackend::Fs => Self::Fs(fs),
+            RegistryBackend::Socket => {
+                Self::Socket(socket::SocketRegistry::new(socket::socket_path(path), fs))
+            }
+            #[cfg(feature = "sqlite")]
+            RegistryBackend::Sqlite => {
+                Self::Sqlite(sqlite::SqliteRegistry::open(path)?.with_format(format))
+            }
+        };
+        Ok(registry)
+    }
+
+    /// Number of previous locations kept per process, `fs` backend only since `sqlite` keeps all
+    /// of them and `socket` keeps none
+    pub fn with_history(self, size: usize) -> Self {
+        match self {
+            Self::Fs(registry) => Self::Fs(registry.with_history(size)),
+            registry => registry,
+        }
+    }
+}
+
+#[cfg(feature = "registry")]
+impl LocationRegistry for Registry {
+    fn read(&self, entry: &RegistryEntry) -> Result<Option<LocationData>, RegistryError> {
+        match self {
+            Self::Fs(registry) => registry.read(entry),
+            Self::Socket(registry) => registry.read(entry),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(registry) => registry.read(entry),
+        }
+    }
+
+    fn write(&self, name: &str, pids: &[Pid], data: &LocationData) -> Result<(), RegistryError> {
+        match self {
+            Self::Fs(registry) => registry.write(name, pids, data),
+            Self::Socket(registry) => registry.write(name, pids, data),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(registry) => registry.write(name, pids, data),
+        }
+    }
+
+    fn remove(&self, entry: &RegistryEntry) -> Result<(), RegistryError> {
+        match self {
+            Self::Fs(registry) => registry.remove(entry),
+            Self::Socket(registry) => registry.remove(entry),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(registry) => registry.remove(entry),
+        }
+    }
+
+    fn clear(&self) -> Result<(), RegistryError> {
+        match self {
+            Self::Fs(registry) => registry.clear(),
+            Self::Socket(registry) => registry.clear(),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(registry) => registry.clear(),
+        }
+    }
+
+    fn history(&self, entry: &RegistryEntry) -> Result<Vec<LocationData>, RegistryError> {
+        match self {
+            Self::Fs(registry) => registry.history(entry),
+            Self::Socket(registry) => registry.history(entry),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(registry) => registry.history(entry),
+        }
+    }
+
+    fn iter(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(RegistryEntry, LocationData), RegistryError>>,
+        RegistryError,
+    > {
+        let entries: Box<dyn Iterator<Item = _>> = match self {
+            Self::Fs(registry) => Box::new(registry.iter()?),
+            Self::Socket(registry) => Box::new(registry.iter()?),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(registry) => Box::new(registry.iter()?),
+        };
+        Ok(entries)
+    }
+
+    fn migrate(&self) -> Result<usize, RegistryError> {
+        match self {
+            Self::Fs(registry) => registry.migrate(),
+            Self::Socket(registry) => registry.migrate(),
+            #[cfg(feature = "sqlite")]
+            Self::Sqlite(registry) => registry.migrate(),
+        }
+    }
+}
+
+/// Creates registry directory accessible only by current user, fails if it's owned by another
+/// user
+#[cfg(feature = "registry")]
+pub(crate) fn create_registry_dir(path: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(path).context("create location dir")?;
+
+    let metadata = std::fs::symlink_metadata(path).context("access location dir's metadata")?;
+    ensure!(
+        metadata.is_dir(),
+        "location registry {} is not a directory",
+        path.display()
+    );
+    ensure!(
+        metadata.uid() == nix::unistd::geteuid().as_raw(),
+        "location registry {} is owned by another user",
+        path.display()
+    );
+
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o700))
+        .context("set permissions for location registry")
+}
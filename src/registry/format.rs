@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, bail};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorCode;
+use crate::{LocationData, LocationKind};
+
+/// Version of stored entries, bump it only when stored [`LocationData`] changes in a way old
+/// binaries can't read, e.g. a field is removed or changes its type. New fields are appended
+/// without a bump: JSON ones are optional and binary ones are read as empty if they are absent
+pub const FORMAT_VERSION: u32 = 5;
+
+/// Prefix of binary entries, JSON can't start with `0xff`
+const BINARY_MAGIC: &[u8] = b"\xffCL";
+
+/// Encoding of stored entries, entries of both formats are read regardless of it
+#[derive(clap::ValueEnum, Deserialize, Default, Eq, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryFormat {
+    /// Human readable
+    #[default]
+    Json,
+    /// Compact and faster to write, see [`postcard`]
+    Binary,
+}
+
+/// How a stored entry is encoded
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub(crate) struct Encoding {
+    pub format: RegistryFormat,
+    pub version: u32,
+}
+
+impl Encoding {
+    /// Entry has to be rewritten to be stored in `format` of the current version
+    pub fn is_outdated(self, format: RegistryFormat) -> bool {
+        self.version < FORMAT_VERSION || self.format != format
+    }
+}
+
+/// JSON form of [`LocationData`], entries written before versioning have no `version` and are
+/// version 0
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u32,
+    #[serde(flatten)]
+    data: &'a LocationData,
+}
+
+#[derive(Deserialize)]
+struct VersionedOwned {
+    #[serde(default)]
+    version: u32,
+    #[serde(flatten)]
+    data: LocationData,
+}
+
+/// Binary form of [`LocationData`]. Postcard isn't self-describing, so fields are only appended
+/// and ones missing at the end of entries written by older versions are read as empty, see
+/// [`Binary::from_bytes`]. Fields unknown to this version at the end are ignored
+#[derive(Serialize)]
+struct Binary {
+    #[serde(with = "crate::os_path")]
+    location: PathBuf,
+    nvim_pipe: Option<String>,
+    fallback: Option<bool>,
+    written_at: Option<u64>,
+    start_time: Option<u64>,
+    kind: Option<LocationKind>,
+    line: Option<u32>,
+    column: Option<u32>,
+    #[serde(with = "crate::os_path::option")]
+    project_root: Option<PathBuf>,
+    window: Option<String>,
+    terminal: Option<String>,
+    extra: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct OsPath(#[serde(with = "crate::os_path")] PathBuf);
+
+#[derive(Deserialize, Default)]
+struct OsPathOption(#[serde(with = "crate::os_path::option")] Option<PathBuf>);
+
+/// Postcard encoded fields of a struct, which is encoded as a tuple of them
+struct Fields<'a>(&'a [u8]);
+
+impl Fields<'_> {
+    fn next<T: DeserializeOwned>(&mut self) -> postcard::Result<T> {
+        let (field, rest) = postcard::take_from_bytes(self.0)?;
+        self.0 = rest;
+        Ok(field)
+    }
+
+    /// Field appended after the first version, it's absent in older entries
+    fn appended<T: DeserializeOwned + Default>(&mut self) -> postcard::Result<T> {
+        if self.0.is_empty() {
+            return Ok(T::default());
+        }
+        self.next()
+    }
+}
+
+impl Binary {
+    fn from_bytes(bytes: &[u8]) -> postcard::Result<Self> {
+        let mut fields = Fields(bytes);
+        Ok(Self {
+            location: fields.next::<OsPath>()?.0,
+            nvim_pipe: fields.next()?,
+            fallback: fields.next()?,
+            written_at: fields.next()?,
+            start_time: fields.next()?,
+            kind: fields.appended()?,
+            line: fields.appended()?,
+            column: fields.appended()?,
+            project_root: fields.appended::<OsPathOption>()?.0,
+            window: fields.appended()?,
+            terminal: fields.appended()?,
+            extra: fields.appended()?,
+        })
+    }
+}
+
+impl From<&LocationData> for Binary {
+    fn from(data: &LocationData) -> Self {
+        Self {
+            location: data.location.clone(),
+            nvim_pipe: data.nvim_pipe.clone(),
+            fallback: data.fallback,
+            written_at: data.written_at,
+            start_time: data.start_time,
+            kind: data.kind,
+            line: data.line,
+            column: data.column,
+            project_root: data.project_root.clone(),
+            window: data.window.clone(),
+            terminal: data.terminal.clone(),
+            extra: data.extra.clone(),
+        }
+    }
+}
+
+impl From<Binary> for LocationData {
+    fn from(binary: Binary) -> Self {
+        Self {
+            location: binary.location,
+            nvim_pipe: binary.nvim_pipe,
+            fallback: binary.fallback,
+            cwd_fallback: false,
+            written_at: binary.written_at,
+            start_time: binary.start_time,
+            kind: binary.kind,
+            line: binary.line,
+            column: binary.column,
+            project_root: binary.project_root,
+            window: binary.window,
+            terminal: binary.terminal,
+            extra: binary.extra,
+        }
+    }
+}
+
+/// Serializes `data` for storage tagged with [`FORMAT_VERSION`]
+pub(crate) fn encode(data: &LocationData, format: RegistryFormat) -> anyhow::Result<Vec<u8>> {
+    match format {
+        RegistryFormat::Json => {
+            let versioned = Versioned {
+                version: FORMAT_VERSION,
+                data,
+            };
+            serde_json::to_vec(&versioned).context("serialize location data")
+        }
+        RegistryFormat::Binary => {
+            let binary = Binary::from(data);
+            let mut bytes = BINARY_MAGIC.to_vec();
+            postcard::to_io(&(FORMAT_VERSION, binary), &mut bytes)
+                .context("serialize location data")?;
+            Ok(bytes)
+        }
+    }
+}
+
+/// Deserializes stored entry of either format and any older version
+pub(crate) fn decode(bytes: &[u8]) -> anyhow::Result<(Encoding, LocationData)> {
+    decode_inner(bytes).context(ErrorCode::CorruptEntry)
+}
+
+fn decode_inner(bytes: &[u8]) -> anyhow::Result<(Encoding, LocationData)> {
+    let Some(bytes) = bytes.strip_prefix(BINARY_MAGIC) else {
+        let VersionedOwned { version, data } =
+            serde_json::from_slice(bytes).context("deserialize location data")?;
+        // older versions lack fields that are optional anyway, nothing to upgrade
+        ensure_supported(version)?;
+        let encoding = Encoding {
+            format: RegistryFormat::Json,
+            version,
+        };
+        return Ok((encoding, data));
+    };
+
+    let (version, bytes): (u32, _) =
+        postcard::take_from_bytes(bytes).context("deserialize format version")?;
+    ensure_supported(version)?;
+    let binary = Binary::from_bytes(bytes);
+    let data = binary.context("deserialize location data")?.into();
+    let encoding = Encoding {
+        format: RegistryFormat::Binary,
+        version,
+    };
+    Ok((encoding, data))
+}
+
+fn ensure_supported(version: u32) -> anyhow::Result<()> {
+    if version > FORMAT_VERSION {
+        bail!(
+            "location data of version {version} is written by a newer version of current-location"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    use crate::LocationData;
+    use crate::registry::format::{BINARY_MAGIC, FORMAT_VERSION, RegistryFormat, decode, encode};
+
+    #[test]
+    fn versioning() {
+        let data = LocationData::new("/srv".into());
+        let (encoding, decoded) = decode(&encode(&data, RegistryFormat::Json).unwrap()).unwrap();
+        assert_eq!(encoding.version, FORMAT_VERSION);
+        assert_eq!(decoded.location, data.location);
+
+        let (encoding, decoded) = decode(br#"{"location":"/srv","nvim_pipe":null}"#).unwrap();
+        assert_eq!(encoding.version, 0);
+        assert!(encoding.is_outdated(RegistryFormat::Json));
+        assert_eq!(decoded.location, data.location);
+
+        assert!(decode(br#"{"version":999,"location":"/srv","nvim_pipe":null}"#).is_err());
+    }
+
+    #[test]
+    fn binary() {
+        let mut data = LocationData::new("/srv".into());
+        data.nvim_pipe = Some("/run/nvim.sock".to_owned());
+        data.start_time = Some(42);
+        data.line = Some(7);
+        data.extra.insert("branch".to_owned(), "main".to_owned());
+
+        let (encoding, decoded) = decode(&encode(&data, RegistryFormat::Binary).unwrap()).unwrap();
+        assert_eq!(encoding.format, RegistryFormat::Binary);
+        assert!(!encoding.is_outdated(RegistryFormat::Binary));
+        assert!(encoding.is_outdated(RegistryFormat::Json));
+        assert_eq!(decoded.location, data.location);
+        assert_eq!(decoded.nvim_pipe, data.nvim_pipe);
+        assert_eq!(decoded.start_time, data.start_time);
+        assert_eq!(decoded.line, data.line);
+        assert_eq!(decoded.extra, data.extra);
+        assert_eq!(decoded.written_at, None);
+    }
+
+    #[test]
+    fn non_utf8_location() {
+        let location = PathBuf::from(OsStr::from_bytes(b"/srv/\xff\xfe.txt"));
+        let mut data = LocationData::new(location.clone());
+        data.project_root = Some(PathBuf::from(OsStr::from_bytes(b"/srv/\xff")));
+        data.line = Some(7);
+
+        for format in [RegistryFormat::Json, RegistryFormat::Binary] {
+            let bytes = encode(&data, format).unwrap();
+            let (encoding, decoded) = decode(&bytes).unwrap();
+            assert_eq!(encoding.format, format);
+            assert_eq!(encoding.version, FORMAT_VERSION);
+            assert_eq!(decoded.location, location);
+            assert_eq!(decoded.project_root, data.project_root);
+            assert_eq!(decoded.line, data.line);
+        }
+
+        // flattened into `Versioned`, bytes are still an array rather than a lossy string
+        let json: serde_json::Value =
+            serde_json::from_slice(&encode(&data, RegistryFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["version"], FORMAT_VERSION);
+        assert!(json["location"].is_array());
+    }
+
+    #[test]
+    fn binary_v1() {
+        // postcard encodes structs as tuples of fields
+        let fields = (
+            "/srv",
+            Some("/run/nvim.sock"),
+            None::<bool>,
+            Some(7u64),
+            Some(42u64),
+        );
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend(postcard::to_stdvec(&(1u32, fields)).unwrap());
+
+        let (encoding, decoded) = decode(&bytes).unwrap();
+        assert_eq!(encoding.version, 1);
+        assert_eq!(decoded.location, PathBuf::from("/srv"));
+        assert_eq!(decoded.nvim_pipe.as_deref(), Some("/run/nvim.sock"));
+        assert_eq!(decoded.start_time, Some(42));
+        assert_eq!(decoded.line, None);
+    }
+
+    #[test]
+    fn appended_fields() {
+        let mut data = LocationData::new("/srv".into());
+        data.line = Some(7);
+        let bytes = encode(&data, RegistryFormat::Binary).unwrap();
+
+        // written by a newer version with a field appended
+        let mut newer = bytes.clone();
+        newer.extend(postcard::to_stdvec(&Some("unknown")).unwrap());
+        let (encoding, decoded) = decode(&newer).unwrap();
+        assert_eq!(encoding.version, FORMAT_VERSION);
+        assert_eq!(decoded.line, data.line);
+
+        // written by version 4, before `extra`. Empty map at the end is a zero byte and the version
+        // is a single byte at the start
+        let mut older = bytes[BINARY_MAGIC.len()..].to_vec();
+        assert_eq!(older.pop(), Some(0));
+        let mut bytes = BINARY_MAGIC.to_vec();
+        bytes.extend(postcard::to_stdvec(&4u32).unwrap());
+        bytes.extend(&older[1..]);
+        let (encoding, decoded) = decode(&bytes).unwrap();
+        assert_eq!(encoding.version, 4);
+        assert!(encoding.is_outdated(RegistryFormat::Binary));
+        assert_eq!(decoded.line, data.line);
+        assert!(decoded.extra.is_empty());
+    }
+}
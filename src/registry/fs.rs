@@ -0,0 +1,483 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, anyhow, ensure};
+use nix::fcntl::{Flock, FlockArg};
+
+use crate::LocationData;
+use crate::error::RegistryError;
+use crate::process::Pid;
+use crate::registry::format::Encoding;
+use crate::registry::{
+    LocationRegistry, RegistryEntry, RegistryFormat, create_registry_dir, decode, encode,
+};
+
+const LOCK_FILENAME: &str = ".lock";
+/// File names are limited to 255 bytes, leaves room for pid and temporary file suffix
+const MAX_ESCAPED_NAME_LEN: usize = 200;
+
+/// Directory with a file per process, `<name>-<pid>.txt` with unsafe characters of name escaped.
+/// Previous locations are kept in `<name>-<pid>.txt.<n>`, the most recent one is `.1`
+#[derive(Clone, Debug)]
+pub struct FsRegistry {
+    path: PathBuf,
+    format: RegistryFormat,
+    history: usize,
+}
+
+impl FsRegistry {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            format: RegistryFormat::default(),
+            history: 0,
+        }
+    }
+
+    /// Format of written files, files of any format are read
+    pub fn with_format(self, format: RegistryFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Number of previous locations kept per process, none by default
+    pub fn with_history(self, history: usize) -> Self {
+        Self { history, ..self }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Start time of the process isn't a part of the path: readers know only pid and name, and
+    /// entry of a dead process with the same pid is rejected by start time written in it
+    fn entry_path(&self, entry: &RegistryEntry) -> anyhow::Result<PathBuf> {
+        let name = escape_name(&entry.name);
+        ensure!(
+            name.len() <= MAX_ESCAPED_NAME_LEN,
+            "process name {:?} is too long",
+            entry.name
+        );
+        let filename = format!("{name}-{}.txt", entry.pid);
+        Ok(self.path.join(filename))
+    }
+
+    /// Path of `n`-th previous location, the current one is 0th
+    fn history_path(&self, entry: &RegistryEntry, n: usize) -> anyhow::Result<PathBuf> {
+        let mut path = self.entry_path(entry)?.into_os_string();
+        if n > 0 {
+            path.push(format!(".{n}"));
+        }
+        Ok(path.into())
+    }
+
+    /// Shifts previous locations by one, dropping the oldest one, and keeps the current one as
+    /// `.1`. It's linked rather than renamed so the entry never disappears for readers and watchers
+    fn rotate(&self, entry: &RegistryEntry) -> anyhow::Result<()> {
+        if self.history == 0 {
+            return Ok(());
+        }
+        for n in (1..self.history).rev() {
+            match fs::rename(
+                self.history_path(entry, n)?,
+                self.history_path(entry, n + 1)?,
+            ) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(anyhow!(err).context("rotate location history")),
+            }
+        }
+
+        let previous = self.history_path(entry, 1)?;
+        remove_if_exists(&previous).context("rotate location history")?;
+        match fs::hard_link(self.entry_path(entry)?, previous) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(anyhow!(err).context("keep previous location")),
+        }
+    }
+
+    /// Takes advisory lock of the whole registry, shared one for readers and exclusive one for
+    /// writers. Shared lock is `None` if registry doesn't exist yet
+    fn lock(&self, arg: FlockArg) -> anyhow::Result<Option<Flock<File>>> {
+        let path = self.path.join(LOCK_FILENAME);
+        let mut options = File::options();
+        options
+            .read(true)
+            .custom_flags(nix::libc::O_NOFOLLOW | nix::libc::O_CLOEXEC);
+        if arg == FlockArg::LockExclusive {
+            options.write(true).create(true).mode(0o600);
+        }
+
+        let file = match options.open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(anyhow!(err).context("open lock file")),
+        };
+        let lock =
+            Flock::lock(file, arg).map_err(|(_, errno)| anyhow!(errno).context("lock registry"))?;
+        Ok(lock.into())
+    }
+
+    /// Opens file for reading, `None` if it doesn't exist
+    ///
+    /// Rejects symlinks, files of other users and files writable by others since registry
+    /// directory may be pre-created by someone else
+    fn open(&self, path: &Path) -> anyhow::Result<Option<File>> {
+        let file = match File::options()
+            .read(true)
+            .custom_flags(nix::libc::O_NOFOLLOW)
+            .open(path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(anyhow!(err).context("open location file")),
+        };
+
+        let metadata = file.metadata().context("access file's metadata")?;
+        ensure!(
+            metadata.uid() == nix::unistd::geteuid().as_raw(),
+            "location file {} is owned by another user",
+            path.display()
+        );
+        ensure!(
+            metadata.mode() & 0o022 == 0,
+            "location file {} is writable by others",
+            path.display()
+        );
+
+        Ok(file.into())
+    }
+
+    /// Reads entry along with how it's stored
+    fn read_encoded(
+        &self,
+        entry: &RegistryEntry,
+    ) -> anyhow::Result<Option<(Encoding, LocationData)>> {
+        self.read_path(&self.entry_path(entry)?)
+    }
+
+    fn read_path(&self, path: &Path) -> anyhow::Result<Option<(Encoding, LocationData)>> {
+        let Some(mut file) = self.open(path)? else {
+            return Ok(None);
+        };
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).context("read location file")?;
+        decode(&bytes).map(Some)
+    }
+
+    fn entries(&self) -> anyhow::Result<impl Iterator<Item = anyhow::Result<RegistryEntry>>> {
+        let dir = match fs::read_dir(&self.path) {
+            Ok(dir) => Some(dir),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(anyhow!(err).context("read location dir")),
+        };
+
+        let entries = dir.into_iter().flatten().filter_map(|dir_entry| {
+            let dir_entry = match dir_entry.context("read location dir") {
+                Ok(dir_entry) => dir_entry,
+                Err(err) => return Some(Err(err)),
+            };
+            // foreign files are ignored
+            parse_filename(dir_entry.file_name().to_str()?).map(Ok)
+        });
+        Ok(entries)
+    }
+}
+
+/// Writes to a temporary file which is then renamed over `path`, so readers never see a partially
+/// written file
+fn write_atomically(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(".{}.tmp", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+    // left behind by a crashed process with the same pid
+    remove_if_exists(&tmp_path).context("remove stale temporary location file")?;
+
+    let file = File::options()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(nix::libc::O_NOFOLLOW)
+        .open(&tmp_path)
+        .context("create temporary location file")?;
+
+    let written = (&file)
+        .write_all(bytes)
+        .context("write to file")
+        .and_then(|()| fs::rename(&tmp_path, path).context("rename temporary location file"));
+    if written.is_err() {
+        _ = fs::remove_file(&tmp_path);
+    }
+    written
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Percent-encodes everything but a few safe characters, names are set by `write` or by a process
+/// itself and may contain `/`, newlines and so on. Leading `.` is escaped as well so files aren't
+/// hidden
+fn escape_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for (i, byte) in name.bytes().enumerate() {
+        let safe =
+            byte.is_ascii_alphanumeric() || b"-_+@".contains(&byte) || (byte == b'.' && i > 0);
+        if safe {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    escaped
+}
+
+/// Reverses [`escape_name`], `None` if `escaped` isn't produced by it
+fn unescape_name(escaped: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// Parses `<escaped name>-<pid>.txt`
+pub(crate) fn parse_filename(filename: &str) -> Option<RegistryEntry> {
+    let (name, pid) = filename.strip_suffix(".txt")?.rsplit_once('-')?;
+    let entry = RegistryEntry {
+        pid: pid.parse().ok()?,
+        name: unescape_name(name)?,
+    };
+    Some(entry)
+}
+
+impl LocationRegistry for FsRegistry {
+    fn read(&self, entry: &RegistryEntry) -> Result<Option<LocationData>, RegistryError> {
+        let _lock = self.lock(FlockArg::LockShared)?;
+        let data = self.read_encoded(entry)?;
+        Ok(data.map(|(_, data)| data))
+    }
+
+    fn write(&self, name: &str, pids: &[Pid], data: &LocationData) -> Result<(), RegistryError> {
+        let bytes = encode(data, self.format)?;
+        create_registry_dir(&self.path)?;
+        let _lock = self.lock(FlockArg::LockExclusive)?;
+
+        for &pid in pids {
+            let entry = RegistryEntry {
+                pid,
+                name: name.to_owned(),
+            };
+            self.rotate(&entry)?;
+            write_atomically(&self.entry_path(&entry)?, &bytes)?;
+        }
+
+        Ok(())
+    }
+
+    fn remove(&self, entry: &RegistryEntry) -> Result<(), RegistryError> {
+        let Some(_lock) = self.lock(FlockArg::LockExclusive)? else {
+            return Ok(());
+        };
+        remove_if_exists(&self.entry_path(entry)?).context("remove location file")?;
+
+        // history may be longer than the configured one if it's changed since
+        for n in 1.. {
+            let path = self.history_path(entry, n)?;
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => break,
+                Err(err) => return Err(anyhow!(err).context("remove location history").into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn history(&self, entry: &RegistryEntry) -> Result<Vec<LocationData>, RegistryError> {
+        let _lock = self.lock(FlockArg::LockShared)?;
+        let mut history = Vec::new();
+        for n in 0.. {
+            let Some((_, data)) = self.read_path(&self.history_path(entry, n)?)? else {
+                break;
+            };
+            history.push(data);
+        }
+        Ok(history)
+    }
+
+    fn clear(&self) -> Result<(), RegistryError> {
+        let _lock = self.lock(FlockArg::LockExclusive)?;
+        match fs::remove_dir_all(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(anyhow!(e).context("remove location dir").into()),
+        }
+    }
+
+    fn iter(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(RegistryEntry, LocationData), RegistryError>>,
+        RegistryError,
+    > {
+        let entries = self.entries()?.filter_map(|entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => return Some(Err(err.into())),
+            };
+            let data = self.read(&entry).map_err(|err| {
+                err.context(format!("read location of {}-{}", entry.name, entry.pid))
+            });
+            match data {
+                Ok(Some(data)) => Some(Ok((entry, data))),
+                // removed in between
+                Ok(None) => None,
+                Err(err) => Some(Err(err)),
+            }
+        });
+        Ok(entries)
+    }
+
+    /// Previous locations are migrated as well, each of them is counted
+    fn migrate(&self) -> Result<usize, RegistryError> {
+        let Some(_lock) = self.lock(FlockArg::LockExclusive)? else {
+            return Ok(0);
+        };
+
+        let mut migrated = 0;
+        for entry in self.entries()? {
+            let entry = entry?;
+            let context = || format!("migrate location of {}-{}", entry.name, entry.pid);
+            // history may be longer than the configured one if it's changed since
+            for n in 0.. {
+                let path = self.history_path(&entry, n).with_context(context)?;
+                let Some((encoding, data)) = self.read_path(&path).with_context(context)? else {
+                    break;
+                };
+                if encoding.is_outdated(self.format) {
+                    let bytes = encode(&data, self.format).with_context(context)?;
+                    write_atomically(&path, &bytes).with_context(context)?;
+                    migrated += 1;
+                }
+            }
+        }
+        Ok(migrated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use crate::LocationData;
+    use crate::registry::fs::{
+        FsRegistry, escape_name, parse_filename, unescape_name, write_atomically,
+    };
+    use crate::registry::{LocationRegistry, RegistryEntry, RegistryFormat};
+
+    #[test]
+    fn filename() {
+        let entry = parse_filename("my-shell-42.txt").unwrap();
+        assert_eq!(entry.name, "my-shell");
+        assert_eq!(entry.pid, 42);
+
+        assert!(parse_filename("nvim.txt").is_none());
+        assert!(parse_filename("nvim-x.txt").is_none());
+        assert!(parse_filename("nvim%2-1.txt").is_none());
+    }
+
+    #[test]
+    fn escaping() {
+        let name = "../evil\nname 100%";
+        let escaped = escape_name(name);
+        assert_eq!(escaped, "%2E.%2Fevil%0Aname%20100%25");
+        assert_eq!(unescape_name(&escaped).unwrap(), name);
+
+        let entry = parse_filename(&format!("{escaped}-42.txt")).unwrap();
+        assert_eq!(entry.name, name);
+    }
+
+    #[test]
+    fn write_read() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("registry");
+        let registry = FsRegistry::new(dir.clone());
+        let data = LocationData::new("/srv".into());
+
+        registry.write("nvim", &[1, 2], &data).unwrap();
+
+        let entry = RegistryEntry {
+            pid: 2,
+            name: "nvim".to_owned(),
+        };
+        let read = registry.read(&entry).unwrap().unwrap();
+        assert_eq!(read.location, data.location);
+        assert_eq!(registry.iter().unwrap().count(), 2);
+
+        registry.clear().unwrap();
+        assert!(registry.read(&entry).unwrap().is_none());
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn stale_tmp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zsh-1.txt");
+        let tmp_path = dir
+            .path()
+            .join(format!("zsh-1.txt.{}.tmp", std::process::id()));
+        std::fs::write(&tmp_path, "stale").unwrap();
+
+        write_atomically(&path, b"fresh").unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"fresh");
+        assert!(!tmp_path.exists());
+    }
+
+    #[test]
+    fn history() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = FsRegistry::new(dir.path().to_owned()).with_history(2);
+        let entry = RegistryEntry {
+            pid: 1,
+            name: "zsh".to_owned(),
+        };
+
+        for location in ["/a", "/b", "/c", "/d"] {
+            registry
+                .write("zsh", &[1], &LocationData::new(location.into()))
+                .unwrap();
+        }
+        let locations: Vec<_> = registry
+            .history(&entry)
+            .unwrap()
+            .into_iter()
+            .map(|data| data.location)
+            .collect();
+        assert_eq!(locations, ["/d", "/c", "/b"].map(PathBuf::from));
+        // history isn't listed as entries
+        assert_eq!(registry.iter().unwrap().count(), 1);
+
+        let binary = registry.clone().with_format(RegistryFormat::Binary);
+        assert_eq!(binary.migrate().unwrap(), 3);
+        assert_eq!(binary.migrate().unwrap(), 0);
+        assert_eq!(binary.history(&entry).unwrap().len(), 3);
+
+        registry.remove(&entry).unwrap();
+        assert!(registry.history(&entry).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use crate::LocationData;
+use crate::error::RegistryError;
+use crate::process::Pid;
+use crate::registry::{LocationRegistry, RegistryEntry};
+
+/// Registry living only as long as the value, backs `registry-daemon` and is handy in tests
+#[derive(Default, Debug)]
+pub struct MemoryRegistry {
+    entries: RefCell<HashMap<RegistryEntry, LocationData>>,
+}
+
+impl MemoryRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl LocationRegistry for MemoryRegistry {
+    fn read(&self, entry: &RegistryEntry) -> Result<Option<LocationData>, RegistryError> {
+        Ok(self.entries.borrow().get(entry).cloned())
+    }
+
+    fn write(&self, name: &str, pids: &[Pid], data: &LocationData) -> Result<(), RegistryError> {
+        let mut entries = self.entries.borrow_mut();
+        for &pid in pids {
+            let entry = RegistryEntry {
+                pid,
+                name: name.to_owned(),
+            };
+            entries.insert(entry, data.clone());
+        }
+        Ok(())
+    }
+
+    fn remove(&self, entry: &RegistryEntry) -> Result<(), RegistryError> {
+        self.entries.borrow_mut().remove(entry);
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<(), RegistryError> {
+        self.entries.borrow_mut().clear();
+        Ok(())
+    }
+
+    fn iter(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(RegistryEntry, LocationData), RegistryError>>,
+        RegistryError,
+    > {
+        let entries: Vec<_> = self
+            .entries
+            .borrow()
+            .iter()
+            .map(|(entry, data)| Ok((entry.clone(), data.clone())))
+            .collect();
+        Ok(entries.into_iter())
+    }
+}
@@ -0,0 +1,289 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::fd::AsFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, anyhow, bail, ensure};
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::LocationData;
+use crate::error::RegistryError;
+use crate::process::Pid;
+use crate::registry::fs::FsRegistry;
+use crate::registry::memory::MemoryRegistry;
+use crate::registry::{LocationRegistry, RegistryEntry};
+
+/// Client must not block the daemon for long since connections are served one by one
+pub(crate) const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum Request {
+    Read(RegistryEntry),
+    Write {
+        name: String,
+        pids: Vec<Pid>,
+        data: Box<LocationData>,
+    },
+    Remove(RegistryEntry),
+    Clear,
+    List,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum Reply {
+    Location(Option<LocationData>),
+    Done,
+    Entries(Vec<(RegistryEntry, LocationData)>),
+}
+
+/// Registry held by `registry-daemon` in memory, requests go over Unix socket as JSON lines.
+/// Falls back to [`FsRegistry`] when the daemon isn't running
+#[derive(Clone, Debug)]
+pub struct SocketRegistry {
+    socket: PathBuf,
+    fallback: FsRegistry,
+}
+
+impl SocketRegistry {
+    pub fn new(socket: PathBuf, fallback: FsRegistry) -> Self {
+        Self { socket, fallback }
+    }
+
+    /// Returns `None` if the daemon isn't running
+    fn request(&self, request: &Request) -> anyhow::Result<Option<Reply>> {
+        let reply = send_request(&self.socket, "registry daemon", request)?;
+        if reply.is_none() {
+            tracing::debug!("fall back to files");
+        }
+        Ok(reply)
+    }
+}
+
+/// Sends `request` as a JSON line to `daemon` of the same user listening on `socket` and reads its
+/// reply, `None` if the daemon isn't running
+pub(crate) fn send_request<T: DeserializeOwned>(
+    socket: &Path,
+    daemon: &str,
+    request: &impl Serialize,
+) -> anyhow::Result<Option<T>> {
+    let stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            tracing::debug!("{daemon} isn't running");
+            return Ok(None);
+        }
+        Err(err) => return Err(anyhow!(err).context(format!("connect to {daemon}"))),
+    };
+    ensure_same_user(&stream).with_context(|| format!("check {daemon}"))?;
+    stream
+        .set_read_timeout(CLIENT_TIMEOUT.into())
+        .context("set socket timeout")?;
+
+    let mut line = serde_json::to_string(request).context("serialize request")?;
+    line.push('\n');
+    (&stream)
+        .write_all(line.as_bytes())
+        .context("send request")?;
+
+    line.clear();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .context("receive reply")?;
+    let reply: Result<T, String> = serde_json::from_str(&line).context("deserialize reply")?;
+    reply
+        .map(Some)
+        .map_err(|err| anyhow!(err).context(format!("{daemon} failed")))
+}
+
+fn unexpected(reply: Reply) -> RegistryError {
+    RegistryError::Io(anyhow!("unexpected reply of registry daemon: {reply:?}"))
+}
+
+impl LocationRegistry for SocketRegistry {
+    fn read(&self, entry: &RegistryEntry) -> Result<Option<LocationData>, RegistryError> {
+        match self.request(&Request::Read(entry.clone()))? {
+            Some(Reply::Location(data)) => Ok(data),
+            Some(reply) => Err(unexpected(reply)),
+            None => self.fallback.read(entry),
+        }
+    }
+
+    fn write(&self, name: &str, pids: &[Pid], data: &LocationData) -> Result<(), RegistryError> {
+        let request = Request::Write {
+            name: name.to_owned(),
+            pids: pids.to_vec(),
+            data: data.clone().into(),
+        };
+        match self.request(&request)? {
+            Some(Reply::Done) => Ok(()),
+            Some(reply) => Err(unexpected(reply)),
+            None => self.fallback.write(name, pids, data),
+        }
+    }
+
+    fn remove(&self, entry: &RegistryEntry) -> Result<(), RegistryError> {
+        match self.request(&Request::Remove(entry.clone()))? {
+            // file may be written while the daemon was down
+            Some(Reply::Done) => self.fallback.remove(entry),
+            Some(reply) => Err(unexpected(reply)),
+            None => self.fallback.remove(entry),
+        }
+    }
+
+    fn clear(&self) -> Result<(), RegistryError> {
+        match self.request(&Request::Clear)? {
+            // files written while the daemon was down are cleared as well
+            Some(Reply::Done) => self.fallback.clear(),
+            Some(reply) => Err(unexpected(reply)),
+            None => self.fallback.clear(),
+        }
+    }
+
+    fn iter(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(RegistryEntry, LocationData), RegistryError>>,
+        RegistryError,
+    > {
+        let entries: Vec<_> = match self.request(&Request::List)? {
+            Some(Reply::Entries(entries)) => entries.into_iter().map(Ok).collect(),
+            Some(reply) => return Err(unexpected(reply)),
+            None => self.fallback.iter()?.collect(),
+        };
+        Ok(entries.into_iter())
+    }
+
+    /// Daemon keeps entries in memory, only files written while it was down are migrated
+    fn migrate(&self) -> Result<usize, RegistryError> {
+        self.fallback.migrate()
+    }
+}
+
+/// Socket may be pre-created by another user if it's in a shared directory
+pub(crate) fn ensure_same_user(stream: &impl AsFd) -> anyhow::Result<()> {
+    let credentials = getsockopt(stream, PeerCredentials).context("get peer credentials")?;
+    ensure!(
+        credentials.uid() == nix::unistd::geteuid().as_raw(),
+        "peer is owned by another user"
+    );
+    Ok(())
+}
+
+/// Socket next to registry directory, `<registry_path>.sock`
+pub fn socket_path(registry_path: &Path) -> PathBuf {
+    // `with_extension` would replace a dot suffix of the directory, e.g. of `locations.d`
+    let mut path = registry_path.as_os_str().to_owned();
+    path.push(".sock");
+    path.into()
+}
+
+/// Holds registry in memory and serves it on [`socket_path`], entries written to `registry_path`
+/// while the daemon was down are loaded on start
+pub fn run_daemon(registry_path: &Path) -> anyhow::Result<()> {
+    let registry = MemoryRegistry::new();
+    for entry in FsRegistry::new(registry_path.to_owned()).iter()? {
+        let (entry, data) = entry?;
+        registry.write(&entry.name, &[entry.pid], &data)?;
+    }
+
+    let listener = bind(&socket_path(registry_path), "registry daemon")?;
+    serve(&listener, &registry)
+}
+
+/// Binds to `socket`, fails if another daemon is already listening on it
+pub fn bind(socket: &Path, daemon: &str) -> anyhow::Result<UnixListener> {
+    match UnixStream::connect(socket) {
+        Ok(_) => bail!("{daemon} is already running on {}", socket.display()),
+        // left by a daemon that didn't exit cleanly
+        Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+            fs::remove_file(socket).context("remove stale socket")?;
+        }
+        Err(_) => {}
+    }
+
+    UnixListener::bind(socket).with_context(|| format!("bind {daemon} socket"))
+}
+
+/// Serves requests of [`SocketRegistry`] from `registry` until `listener` fails
+pub fn serve(listener: &UnixListener, registry: &impl LocationRegistry) -> anyhow::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream.context("accept connection")?;
+        // one misbehaving client must not stop the daemon
+        if let Err(err) = handle_connection(&stream, registry) {
+            tracing::warn!("failed to handle connection: {err:#}");
+        }
+    }
+
+    Ok(())
+}
+
+fn handle_connection(stream: &UnixStream, registry: &impl LocationRegistry) -> anyhow::Result<()> {
+    ensure_same_user(stream).context("check client")?;
+    stream
+        .set_read_timeout(CLIENT_TIMEOUT.into())
+        .context("set socket timeout")?;
+
+    let mut line = String::new();
+    let read = BufReader::new(stream)
+        .read_line(&mut line)
+        .context("receive request")?;
+    // connection is only checked, e.g. by `bind` of another daemon
+    if read == 0 {
+        return Ok(());
+    }
+    let request: Request = serde_json::from_str(&line).context("deserialize request")?;
+
+    let reply = handle_request(request, registry).map_err(|err| format!("{err:#}"));
+    let mut line = serde_json::to_string(&reply).context("serialize reply")?;
+    line.push('\n');
+    let mut stream = stream;
+    stream.write_all(line.as_bytes()).context("send reply")
+}
+
+fn handle_request(request: Request, registry: &impl LocationRegistry) -> anyhow::Result<Reply> {
+    let reply = match request {
+        Request::Read(entry) => Reply::Location(registry.read(&entry)?),
+        Request::Write { name, pids, data } => {
+            registry.write(&name, &pids, &data)?;
+            Reply::Done
+        }
+        Request::Remove(entry) => {
+            registry.remove(&entry)?;
+            Reply::Done
+        }
+        Request::Clear => {
+            registry.clear()?;
+            Reply::Done
+        }
+        Request::List => Reply::Entries(registry.iter()?.collect::<Result<_, _>>()?),
+    };
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::registry::socket::socket_path;
+
+    #[test]
+    fn path() {
+        assert_eq!(
+            socket_path(Path::new("/run/user/1000/current-location")),
+            Path::new("/run/user/1000/current-location.sock")
+        );
+        assert_eq!(
+            socket_path(Path::new("/tmp/locations.d")),
+            Path::new("/tmp/locations.d.sock")
+        );
+    }
+}
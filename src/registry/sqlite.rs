@@ -0,0 +1,250 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+
+use crate::LocationData;
+use crate::error::RegistryError;
+use crate::process::{self, Pid};
+use crate::registry::{
+    LocationRegistry, RegistryEntry, RegistryFormat, create_registry_dir, decode, encode,
+};
+
+const DATABASE_FILENAME: &str = "registry.sqlite3";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS locations (
+    pid INTEGER NOT NULL,
+    starttime INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    data TEXT NOT NULL,
+    written_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS locations_entry ON locations (pid, starttime, name);
+";
+
+/// JSON is kept as text so the database stays readable with `sqlite3`, binary entries are blobs
+fn to_value(data: &LocationData, format: RegistryFormat) -> anyhow::Result<Value> {
+    let bytes = encode(data, format)?;
+    let value = match format {
+        RegistryFormat::Json => Value::Text(String::from_utf8(bytes).context("encode location")?),
+        RegistryFormat::Binary => Value::Blob(bytes),
+    };
+    Ok(value)
+}
+
+/// Bytes of either text or blob column
+fn get_bytes(row: &Row, index: usize) -> rusqlite::Result<Vec<u8>> {
+    Ok(row.get_ref(index)?.as_bytes()?.to_vec())
+}
+
+/// SQLite has no unsigned integers, start time is far from overflowing anyway
+fn start_time(pid: Pid) -> anyhow::Result<i64> {
+    process::start_time(pid).map(|starttime| starttime as i64)
+}
+
+/// SQLite database keeping every written location, entries are keyed by pid, start time of the
+/// process and its name so locations of dead processes are not picked up by a process with reused
+/// pid
+#[derive(Debug)]
+pub struct SqliteRegistry {
+    connection: Connection,
+    format: RegistryFormat,
+}
+
+impl SqliteRegistry {
+    /// Opens or creates database in `dir` directory
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        create_registry_dir(dir)?;
+
+        let path = dir.join(DATABASE_FILENAME);
+        let connection = Connection::open(&path).context("open registry database")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))
+            .context("set permissions for registry database")?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("create registry schema")?;
+
+        Ok(Self {
+            connection,
+            format: RegistryFormat::default(),
+        })
+    }
+
+    /// Format of written locations, locations of any format are read
+    pub fn with_format(self, format: RegistryFormat) -> Self {
+        Self { format, ..self }
+    }
+}
+
+impl LocationRegistry for SqliteRegistry {
+    fn read(&self, entry: &RegistryEntry) -> Result<Option<LocationData>, RegistryError> {
+        // location of dead process is useless
+        let Ok(starttime) = start_time(entry.pid) else {
+            return Ok(None);
+        };
+
+        let data = self
+            .connection
+            .query_row(
+                "SELECT data FROM locations
+                WHERE pid = ?1 AND starttime = ?2 AND name = ?3
+                ORDER BY rowid DESC LIMIT 1",
+                params![entry.pid, starttime, entry.name],
+                |row| get_bytes(row, 0),
+            )
+            .optional()
+            .context("query location")?;
+
+        let data = data.map(|data| decode(&data)).transpose()?;
+        Ok(data.map(|(_, data)| data))
+    }
+
+    fn write(&self, name: &str, pids: &[Pid], data: &LocationData) -> Result<(), RegistryError> {
+        let data = to_value(data, self.format)?;
+        let written_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("system time is before UNIX epoch")?
+            .as_millis() as i64;
+
+        let transaction = self
+            .connection
+            .unchecked_transaction()
+            .context("begin transaction")?;
+        for &pid in pids {
+            let starttime = start_time(pid).with_context(|| format!("get start time of {pid}"))?;
+            transaction
+                .execute(
+                    "INSERT INTO locations (pid, starttime, name, data, written_at)
+                    VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![pid, starttime, name, data, written_at],
+                )
+                .context("insert location")?;
+        }
+        transaction.commit().context("commit transaction")?;
+        Ok(())
+    }
+
+    /// Removes the whole history of the entry
+    fn remove(&self, entry: &RegistryEntry) -> Result<(), RegistryError> {
+        self.connection
+            .execute(
+                "DELETE FROM locations WHERE pid = ?1 AND name = ?2",
+                params![entry.pid, entry.name],
+            )
+            .context("delete location")?;
+        Ok(())
+    }
+
+    /// Every location written by the process, `written_at` is taken from the row if it's not
+    /// written
+    fn history(&self, entry: &RegistryEntry) -> Result<Vec<LocationData>, RegistryError> {
+        let Ok(starttime) = start_time(entry.pid) else {
+            return Ok(vec![]);
+        };
+
+        let mut statement = self
+            .connection
+            .prepare_cached(
+                "SELECT written_at, data FROM locations
+                WHERE pid = ?1 AND starttime = ?2 AND name = ?3
+                ORDER BY rowid DESC",
+            )
+            .context("prepare history query")?;
+        let rows = statement
+            .query_map(params![entry.pid, starttime, entry.name], |row| {
+                Ok((row.get::<_, i64>(0)?, get_bytes(row, 1)?))
+            })
+            .context("query history")?;
+
+        rows.map(|row| {
+            let (written_at, data) = row.context("read history row")?;
+            let (_, mut data) = decode(&data)?;
+            // column is in milliseconds
+            data.written_at.get_or_insert(written_at as u64 / 1000);
+            Ok(data)
+        })
+        .collect()
+    }
+
+    fn clear(&self) -> Result<(), RegistryError> {
+        self.connection
+            .execute("DELETE FROM locations", [])
+            .context("delete locations")?;
+        Ok(())
+    }
+
+    fn iter(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(RegistryEntry, LocationData), RegistryError>>,
+        RegistryError,
+    > {
+        // bare columns are taken from the row with max rowid
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT pid, name, data, max(rowid) FROM locations
+                GROUP BY pid, starttime, name",
+            )
+            .context("prepare locations query")?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, Pid>(0)?,
+                    row.get::<_, String>(1)?,
+                    get_bytes(row, 2)?,
+                ))
+            })
+            .context("query locations")?;
+
+        let entries: Vec<_> = rows
+            .map(|row| {
+                let (pid, name, data) = row.context("read location row")?;
+                let (_, data) = decode(&data)?;
+                Ok((RegistryEntry { pid, name }, data))
+            })
+            .collect();
+        Ok(entries.into_iter())
+    }
+
+    /// Rewrites the whole history, not only the latest locations
+    fn migrate(&self) -> Result<usize, RegistryError> {
+        let transaction = self
+            .connection
+            .unchecked_transaction()
+            .context("begin transaction")?;
+
+        let rows: Vec<(i64, Vec<u8>)> = {
+            let mut statement = transaction
+                .prepare("SELECT rowid, data FROM locations")
+                .context("prepare locations query")?;
+            statement
+                .query_map([], |row| Ok((row.get(0)?, get_bytes(row, 1)?)))
+                .context("query locations")?
+                .collect::<Result<_, _>>()
+                .context("read location row")?
+        };
+
+        let mut migrated = 0;
+        for (rowid, data) in rows {
+            let (encoding, data) = decode(&data)?;
+            if encoding.is_outdated(self.format) {
+                let data = to_value(&data, self.format)?;
+                transaction
+                    .execute(
+                        "UPDATE locations SET data = ?1 WHERE rowid = ?2",
+                        params![data, rowid],
+                    )
+                    .context("update location")?;
+                migrated += 1;
+            }
+        }
+        transaction.commit().context("commit transaction")?;
+        Ok(migrated)
+    }
+}
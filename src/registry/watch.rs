@@ -0,0 +1,138 @@
+use std::os::fd::{AsFd, BorrowedFd};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+use crate::registry::fs::parse_filename;
+use crate::registry::{RegistryEntry, create_registry_dir};
+
+/// Entries are written to temporary files which are then renamed, so moves are enough to catch
+/// writes
+const WATCH_FLAGS: AddWatchFlags = AddWatchFlags::IN_MOVED_TO
+    .union(AddWatchFlags::IN_MOVED_FROM)
+    .union(AddWatchFlags::IN_DELETE)
+    .union(AddWatchFlags::IN_DELETE_SELF)
+    .union(AddWatchFlags::IN_ONLYDIR);
+
+/// Change of Location Registry
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RegistryEvent {
+    /// Entry is written or replaced
+    Written(RegistryEntry),
+    /// Entry is removed
+    Removed(RegistryEntry),
+    /// The whole registry is removed, e.g. by `clear`
+    Cleared,
+}
+
+/// Watches directory of [`super::fs::FsRegistry`] with inotify, so changes are noticed without
+/// polling. Other backends keep nothing there, except `socket` while the daemon is down
+#[derive(Debug)]
+pub struct RegistryWatcher {
+    inotify: Inotify,
+    path: PathBuf,
+}
+
+impl RegistryWatcher {
+    /// Creates registry directory if it doesn't exist yet, otherwise there is nothing to watch
+    pub fn new(path: PathBuf) -> anyhow::Result<Self> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC).context("initialize inotify")?;
+        let watcher = Self { inotify, path };
+        watcher.watch()?;
+        Ok(watcher)
+    }
+
+    fn watch(&self) -> anyhow::Result<()> {
+        create_registry_dir(&self.path)?;
+        self.inotify
+            .add_watch(&self.path, WATCH_FLAGS)
+            .context("watch location registry")?;
+        Ok(())
+    }
+
+    /// Blocks until some entries change. Changes of foreign files, lock file and temporary files
+    /// are skipped
+    pub fn next_events(&self) -> anyhow::Result<Vec<RegistryEvent>> {
+        loop {
+            let events = self.inotify.read_events().context("read inotify events")?;
+
+            let mut registry_events = Vec::new();
+            for event in events {
+                // watch is removed along with the directory, it's created again since writers
+                // recreate it as well
+                if event.mask.contains(AddWatchFlags::IN_DELETE_SELF) {
+                    self.watch().context("watch recreated location registry")?;
+                    registry_events.push(RegistryEvent::Cleared);
+                }
+
+                let Some(entry) = event
+                    .name
+                    .as_deref()
+                    .and_then(|name| name.to_str())
+                    .and_then(parse_filename)
+                else {
+                    continue;
+                };
+                if event.mask.contains(AddWatchFlags::IN_MOVED_TO) {
+                    registry_events.push(RegistryEvent::Written(entry));
+                } else if event
+                    .mask
+                    .intersects(AddWatchFlags::IN_DELETE | AddWatchFlags::IN_MOVED_FROM)
+                {
+                    registry_events.push(RegistryEvent::Removed(entry));
+                }
+            }
+
+            if !registry_events.is_empty() {
+                return Ok(registry_events);
+            }
+        }
+    }
+}
+
+/// Lets async runtimes wait for readiness instead of blocking on [`RegistryWatcher::next_events`]
+impl AsFd for RegistryWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inotify.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LocationData;
+    use crate::registry::fs::FsRegistry;
+    use crate::registry::watch::{RegistryEvent, RegistryWatcher};
+    use crate::registry::{LocationRegistry, RegistryEntry};
+
+    #[test]
+    fn events() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("registry");
+        let watcher = RegistryWatcher::new(dir.clone()).unwrap();
+        let registry = FsRegistry::new(dir.clone());
+        let entry = RegistryEntry {
+            pid: 1,
+            name: "nvim".to_owned(),
+        };
+
+        registry
+            .write("nvim", &[1], &LocationData::new("/srv".into()))
+            .unwrap();
+        assert_eq!(
+            watcher.next_events().unwrap(),
+            [RegistryEvent::Written(entry.clone())]
+        );
+
+        registry.remove(&entry).unwrap();
+        assert_eq!(
+            watcher.next_events().unwrap(),
+            [RegistryEvent::Removed(entry)]
+        );
+
+        registry.clear().unwrap();
+        assert_eq!(watcher.next_events().unwrap(), [RegistryEvent::Cleared]);
+        // registry is watched again
+        assert!(dir.exists());
+    }
+}
@@ -0,0 +1,69 @@
+use std::process::Command;
+
+use anyhow::{Context, ensure};
+use serde::Deserialize;
+
+use crate::LocationData;
+use crate::process::{self, ProcessInfo};
+use crate::registry::RegistryEntry;
+
+/// How location of a known process is resolved
+#[derive(Deserialize, Default, Clone, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolver {
+    /// Read from Location Registry, the process has to `write` its location
+    #[default]
+    Registry,
+    /// Working directory of the process
+    Cwd,
+    /// Stdout of a shell command, pid of the process is passed as `$1`
+    Command(String),
+}
+
+/// Result of location search
+#[derive(Clone, Debug)]
+pub enum Resolved {
+    /// Entry of Location Registry, may not exist if the process hasn't written it yet
+    Registry(RegistryEntry),
+    Location(LocationData),
+}
+
+impl Resolver {
+    pub fn resolve(&self, proc: &ProcessInfo) -> anyhow::Result<Option<Resolved>> {
+        match self {
+            Self::Registry => {
+                let entry = RegistryEntry {
+                    pid: proc.pid,
+                    name: proc.name.to_string(),
+                };
+                Ok(Resolved::Registry(entry).into())
+            }
+            Self::Cwd => {
+                let location = process::cwd(proc.pid)?;
+                Ok(Resolved::Location(LocationData::new(location)).into())
+            }
+            Self::Command(command) => {
+                let output = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .arg("sh")
+                    .arg(proc.pid.to_string())
+                    .output()
+                    .context("run resolver command")?;
+                ensure!(
+                    output.status.success(),
+                    "resolver command failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+
+                let stdout = String::from_utf8(output.stdout)
+                    .context("resolver command output is not UTF-8")?;
+                let location = stdout.trim_end_matches('\n');
+                if location.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Resolved::Location(LocationData::new(location.into())).into())
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::process::Pid;
+use crate::resolver::Resolver;
+use crate::wm::{ActiveWindowProvider, WindowManager};
+use crate::{LocationData, Selection};
+
+/// Location search of active window configured in code, see [`Search::builder`]
+///
+/// ```no_run
+/// # async fn run() -> current_location::error::Result<()> {
+/// use current_location::Search;
+///
+/// let data = Search::builder()
+///     .known_procs(["fish", "hx"])
+///     .registry_path("/tmp/locations")
+///     .run()
+///     .await?;
+/// println!("{}", data.location().display());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Search<P = WindowManager> {
+    active_pid: Option<Pid>,
+    provider: P,
+    config: Config,
+}
+
+impl Search {
+    /// Starts from default config with detected window manager
+    pub fn builder() -> SearchBuilder {
+        SearchBuilder::from(Config::default())
+    }
+}
+
+impl<P: ActiveWindowProvider> Search<P> {
+    /// Location of active window, fallback location is returned if nothing is found
+    pub async fn run(&self) -> Result<LocationData> {
+        let result = crate::search(self.active_pid, &self.provider, &self.config).await?;
+        let registry = self.config.registry().map_err(Error::RegistryIo)?;
+        // Blocking executor but it's fine here
+        crate::read_location(
+            result.map(|result| result.resolved),
+            &registry,
+            &self.config,
+        )
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+/// Settings of [`Search`], the ones which are not set are taken from [`Config`] it's started from
+#[must_use]
+#[derive(Clone, Debug)]
+pub struct SearchBuilder<P = WindowManager> {
+    search: Search<P>,
+}
+
+impl From<Config> for SearchBuilder {
+    fn from(config: Config) -> Self {
+        Self {
+            search: Search {
+                active_pid: None,
+                provider: WindowManager::default(),
+                config,
+            },
+        }
+    }
+}
+
+impl<P> SearchBuilder<P> {
+    /// Process of active window, window manager is not queried then
+    pub fn active_pid(mut self, pid: Pid) -> Self {
+        self.search.active_pid = Some(pid);
+        self
+    }
+
+    /// Source of active window, e.g. a specific [`WindowManager`]
+    pub fn provider<Q: ActiveWindowProvider>(self, provider: Q) -> SearchBuilder<Q> {
+        let Search {
+            active_pid, config, ..
+        } = self.search;
+        SearchBuilder {
+            search: Search {
+                active_pid,
+                provider,
+                config,
+            },
+        }
+    }
+
+    /// Replaces known processes, same patterns as in config
+    pub fn known_procs(mut self, patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.search.config.known_procs = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Priority of known process `pattern`
+    pub fn priority(mut self, pattern: impl Into<String>, priority: i32) -> Self {
+        self.search
+            .config
+            .priorities
+            .insert(pattern.into(), priority);
+        self
+    }
+
+    /// How location of known process `pattern` is resolved
+    pub fn resolver(mut self, pattern: impl Into<String>, resolver: Resolver) -> Self {
+        self.search
+            .config
+            .resolvers
+            .insert(pattern.into(), resolver);
+        self
+    }
+
+    /// Replaces processes which subtrees are skipped
+    pub fn excluded_procs(mut self, patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.search.config.excluded_procs = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// How one of several found processes is picked, see [`Selection`]
+    pub fn selection(mut self, selection: Selection) -> Self {
+        self.search.config.selection = selection;
+        self
+    }
+
+    /// Directory of Location Registry, both writers and this search have to use the same one
+    pub fn registry_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.search.config.registry_path = path.into();
+        self
+    }
+
+    /// How deep below process of active window known processes are searched
+    pub fn max_depth(mut self, depth: u64) -> Self {
+        self.search.config.max_depth = Some(depth);
+        self
+    }
+
+    /// Reads arguments of every process, see [`crate::process::ProcessInfo::args`]
+    pub fn read_cmdline(mut self, read_cmdline: bool) -> Self {
+        self.search.config.read_cmdline = read_cmdline;
+        self
+    }
+
+    /// Reads only processes below process of active window, see [`Config::scan_subtree`]
+    pub fn scan_subtree(mut self, scan_subtree: bool) -> Self {
+        self.search.config.scan_subtree = scan_subtree;
+        self
+    }
+
+    /// Initial capacity of process tree, tune it if you have lots of processes
+    pub fn process_tree_capacity(mut self, capacity: usize) -> Self {
+        self.search.config.process_tree_capacity = capacity;
+        self
+    }
+
+    /// Initial capacity of the queue of processes to visit while searching
+    pub fn bfs_heap_capacity(mut self, capacity: usize) -> Self {
+        self.search.config.bfs_heap_capacity = capacity;
+        self
+    }
+
+    /// How long window manager may take to report active window, the search fails after that
+    pub fn wm_timeout(mut self, timeout: Duration) -> Self {
+        self.search.config.wm_timeout = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+        self
+    }
+
+    pub fn build(self) -> Search<P> {
+        self.search
+    }
+}
+
+impl<P: ActiveWindowProvider> SearchBuilder<P> {
+    /// Same as [`Search::run`] of [`Self::build`]
+    pub async fn run(self) -> Result<LocationData> {
+        self.build().run().await
+    }
+}
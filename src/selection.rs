@@ -0,0 +1,253 @@
+use std::cmp::Reverse;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+
+use crate::Match;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::registry::{LocationRegistry, RegistryEntry};
+
+/// Picks the process to read location of among matched ones
+pub trait SelectionStrategy {
+    /// Index of the selected one, `matches` are in BFS order, see [`crate::matches_in`]
+    fn select(&self, matches: &[Match]) -> Option<usize>;
+
+    /// Matches in order of preference: selected one, then selected among the rest and so on.
+    /// Ones left after nothing is selected are dropped
+    ///
+    /// Selects repeatedly by default, strategies comparing matches by a key should sort by it
+    /// instead
+    fn rank<'a>(&self, mut matches: Vec<Match<'a>>) -> Vec<Match<'a>> {
+        let mut ranked = Vec::with_capacity(matches.len());
+        while let Some(i) = self.select(&matches).filter(|&i| i < matches.len()) {
+            ranked.push(matches.remove(i));
+        }
+        ranked
+    }
+}
+
+impl<F: Fn(&[Match]) -> Option<usize>> SelectionStrategy for F {
+    fn select(&self, matches: &[Match]) -> Option<usize> {
+        self(matches)
+    }
+}
+
+/// The last matched one regardless of priority, the deepest one in the last subtree
+#[derive(Default, Copy, Clone, Debug)]
+pub struct LastMatch;
+
+impl SelectionStrategy for LastMatch {
+    fn select(&self, matches: &[Match]) -> Option<usize> {
+        matches.len().checked_sub(1)
+    }
+
+    fn rank<'a>(&self, mut matches: Vec<Match<'a>>) -> Vec<Match<'a>> {
+        matches.reverse();
+        matches
+    }
+}
+
+/// The deepest one of the highest priority, e.g. editor opened from shell
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Deepest;
+
+impl SelectionStrategy for Deepest {
+    fn select(&self, matches: &[Match]) -> Option<usize> {
+        // `max_by_key` takes the last one on ties
+        (0..matches.len()).max_by_key(|&i| matches[i].priority)
+    }
+
+    fn rank<'a>(&self, matches: Vec<Match<'a>>) -> Vec<Match<'a>> {
+        rank_by_key(matches, |i, matched| (matched.priority, i))
+    }
+}
+
+/// The nearest one of the highest priority to process of active window, i.e. to root of the tree
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Shallowest;
+
+impl SelectionStrategy for Shallowest {
+    fn select(&self, matches: &[Match]) -> Option<usize> {
+        // `min_by_key` takes the first one on ties
+        (0..matches.len()).min_by_key(|&i| Reverse(matches[i].priority))
+    }
+
+    fn rank<'a>(&self, matches: Vec<Match<'a>>) -> Vec<Match<'a>> {
+        rank_by_key(matches, |i, matched| (matched.priority, Reverse(i)))
+    }
+}
+
+/// The most recently started one of the highest priority, e.g. the shell of the newest pane of a
+/// terminal window. The deepest one if start times are unknown
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Newest;
+
+impl SelectionStrategy for Newest {
+    fn select(&self, matches: &[Match]) -> Option<usize> {
+        (0..matches.len()).max_by_key(|&i| (matches[i].priority, matches[i].process.start_time))
+    }
+
+    fn rank<'a>(&self, matches: Vec<Match<'a>>) -> Vec<Match<'a>> {
+        rank_by_key(matches, |i, matched| {
+            (matched.priority, matched.process.start_time, i)
+        })
+    }
+}
+
+/// The one of the highest priority that has written its location most recently, e.g. the shell
+/// of the last used pane of a terminal window. The deepest one if none of them has written it
+#[derive(Clone, Debug)]
+pub struct MostRecentEntry<R> {
+    registry: R,
+}
+
+impl<R: LocationRegistry> MostRecentEntry<R> {
+    pub fn new(registry: R) -> Self {
+        Self { registry }
+    }
+
+    fn written_at(&self, matched: &Match) -> Option<SystemTime> {
+        let entry = RegistryEntry {
+            pid: matched.process.pid,
+            name: matched.process.name.to_string(),
+        };
+        match self.registry.read(&entry) {
+            // entries of dead processes with the same pid don't count
+            Ok(data) => data
+                .filter(|data| data.is_written_by_process(matched.process))?
+                .written_at(),
+            Err(err) => {
+                tracing::debug!(pid = entry.pid, "failed to read registry entry: {err:#}");
+                None
+            }
+        }
+    }
+}
+
+impl<R: LocationRegistry> SelectionStrategy for MostRecentEntry<R> {
+    fn select(&self, matches: &[Match]) -> Option<usize> {
+        (0..matches.len()).max_by_key(|&i| (matches[i].priority, self.written_at(&matches[i])))
+    }
+
+    /// Every entry is read once
+    fn rank<'a>(&self, matches: Vec<Match<'a>>) -> Vec<Match<'a>> {
+        rank_by_key(matches, |i, matched| {
+            (matched.priority, self.written_at(matched), i)
+        })
+    }
+}
+
+/// Sorts `matches` by `key` of their index and themselves, the greatest one goes first. Key of
+/// every match is computed once
+fn rank_by_key<'a, K: Ord>(
+    matches: Vec<Match<'a>>,
+    mut key: impl FnMut(usize, &Match) -> K,
+) -> Vec<Match<'a>> {
+    let mut matches: Vec<_> = matches.into_iter().enumerate().collect();
+    matches.sort_by_cached_key(|(i, matched)| Reverse(key(*i, matched)));
+    matches.into_iter().map(|(_, matched)| matched).collect()
+}
+
+/// Built-in [`SelectionStrategy`] set in config
+#[derive(Deserialize, Default, Eq, PartialEq, Copy, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Selection {
+    /// See [`Deepest`]
+    #[default]
+    Deepest,
+    /// See [`Shallowest`]
+    #[serde(alias = "nearest-to-root")]
+    Shallowest,
+    /// See [`LastMatch`]
+    LastMatch,
+    /// See [`Newest`]
+    Newest,
+    /// See [`MostRecentEntry`], reads registry of config. Requires `registry` feature
+    MostRecent,
+}
+
+impl Selection {
+    pub fn strategy(self, config: &Config) -> Result<Box<dyn SelectionStrategy>> {
+        Ok(match self {
+            Self::Deepest => Box::new(Deepest),
+            Self::Shallowest => Box::new(Shallowest),
+            Self::LastMatch => Box::new(LastMatch),
+            Self::Newest => Box::new(Newest),
+            #[cfg(feature = "registry")]
+            Self::MostRecent => Box::new(MostRecentEntry::new(
+                config.registry().map_err(Error::RegistryIo)?,
+            )),
+            #[cfg(not(feature = "registry"))]
+            Self::MostRecent => {
+                let _ = config;
+                return Err(Error::Config(anyhow::anyhow!(
+                    "`most-recent` selection requires `registry` feature"
+                )));
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::LocationData;
+    use crate::process::ProcessInfo;
+    use crate::registry::memory::MemoryRegistry;
+    use crate::resolver::Resolver;
+
+    #[test]
+    fn strategies() {
+        let started = |pid, name: &str, start_time| ProcessInfo {
+            start_time: Some(start_time),
+            ..ProcessInfo::new(pid, name.to_owned())
+        };
+        let processes = [
+            started(2, "zsh", 20),
+            started(4, "zsh", 10),
+            started(3, "nvim", 30),
+        ];
+        let matched = |i: usize, depth, priority| Match {
+            process: &processes[i],
+            depth,
+            sibling_no: 0,
+            priority,
+            resolver: Resolver::Registry,
+        };
+        let matches = [matched(0, 1, 1), matched(1, 2, 1), matched(2, 2, 0)];
+
+        assert_eq!(LastMatch.select(&matches), Some(2));
+        assert_eq!(Deepest.select(&matches), Some(1));
+        assert_eq!(Shallowest.select(&matches), Some(0));
+        let parsed = |value| toml::Value::from(value).try_into::<Selection>().ok();
+        assert_eq!(parsed("nearest-to-root"), Some(Selection::Shallowest));
+        assert_eq!(Newest.select(&matches), Some(0));
+        let nvim = |matches: &[Match]| matches.iter().position(|m| &*m.process.name == "nvim");
+        assert_eq!(nvim.select(&matches), Some(2));
+
+        let registry = MemoryRegistry::new();
+        let data = |written_at| LocationData {
+            written_at: Some(written_at),
+            ..LocationData::new(PathBuf::from("/tmp"))
+        };
+        registry.write("zsh", &[2], &data(200)).unwrap();
+        registry.write("zsh", &[4], &data(100)).unwrap();
+        let most_recent = MostRecentEntry::new(registry);
+        assert_eq!(most_recent.select(&matches), Some(0));
+
+        let ranked = |strategy: &dyn SelectionStrategy| -> Vec<_> {
+            let ranked = strategy.rank(matches.to_vec());
+            ranked.iter().map(|m| m.process.pid).collect()
+        };
+        assert_eq!(ranked(&most_recent), [2, 4, 3]);
+        assert_eq!(ranked(&Deepest), [4, 2, 3]);
+        assert_eq!(ranked(&Shallowest), [2, 4, 3]);
+        assert_eq!(ranked(&LastMatch), [3, 4, 2]);
+        assert_eq!(ranked(&Newest), [2, 4, 3]);
+        // ranked by selecting repeatedly
+        assert_eq!(ranked(&nvim), [3]);
+    }
+}
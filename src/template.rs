@@ -0,0 +1,212 @@
+use std::ffi::OsString;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+
+use crate::{LocationData, LocationKind};
+
+/// Output format of location data, e.g. `{location}[ --server {nvim_pipe}]`
+///
+/// `{field}` is replaced with a field of [`LocationData`], unset fields are empty. Section in
+/// square brackets is printed only if all fields inside of it are set. Brackets and braces are
+/// escaped by doubling them: `{{`, `]]`
+#[derive(Clone, Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Field(Field),
+    /// Sections can't be nested
+    Section(Vec<Part>),
+}
+
+#[derive(Clone, Debug)]
+enum Field {
+    Location,
+    NvimPipe,
+    Fallback,
+    WrittenAt,
+    StartTime,
+    Kind,
+    Line,
+    Column,
+    ProjectRoot,
+    Window,
+    Terminal,
+    /// Entry of `extra` map, `{extra.<key>}`
+    Extra(String),
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        let field = match name {
+            "location" => Self::Location,
+            "nvim_pipe" => Self::NvimPipe,
+            "fallback" => Self::Fallback,
+            "written_at" => Self::WrittenAt,
+            "start_time" => Self::StartTime,
+            "kind" => Self::Kind,
+            "line" => Self::Line,
+            "column" => Self::Column,
+            "project_root" => Self::ProjectRoot,
+            "window" => Self::Window,
+            "terminal" => Self::Terminal,
+            _ => {
+                return name
+                    .strip_prefix("extra.")
+                    .map(|key| Self::Extra(key.to_owned()));
+            }
+        };
+        Some(field)
+    }
+
+    /// Paths are kept as is, they may be not valid UTF-8
+    fn value(&self, data: &LocationData) -> Option<OsString> {
+        let value = match self {
+            Self::Location => return Some(data.location.clone().into_os_string()),
+            Self::ProjectRoot => return data.project_root.clone().map(Into::into),
+            Self::NvimPipe => data.nvim_pipe.clone(),
+            Self::Fallback => data.fallback.map(|fallback| fallback.to_string()),
+            Self::WrittenAt => data.written_at.map(|written_at| written_at.to_string()),
+            Self::StartTime => data.start_time.map(|start_time| start_time.to_string()),
+            Self::Kind => data.kind.map(|kind| match kind {
+                LocationKind::File => "file".to_owned(),
+                LocationKind::Directory => "directory".to_owned(),
+            }),
+            Self::Line => data.line.map(|line| line.to_string()),
+            Self::Column => data.column.map(|column| column.to_string()),
+            Self::Window => data.window.clone(),
+            Self::Terminal => data.terminal.clone(),
+            Self::Extra(key) => data.extra.get(key).cloned(),
+        };
+        value.map(Into::into)
+    }
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> anyhow::Result<Self> {
+        let mut parts = vec![];
+        // parts of currently open section
+        let mut section: Option<Vec<Part>> = None;
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+
+        while let Some(char) = chars.next() {
+            let escaped =
+                matches!(char, '{' | '}' | '[' | ']') && chars.next_if_eq(&char).is_some();
+            if escaped || !matches!(char, '{' | '}' | '[' | ']') {
+                literal.push(char);
+                continue;
+            }
+
+            let current = section.as_mut().unwrap_or(&mut parts);
+            if !literal.is_empty() {
+                current.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+            match char {
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(char) => name.push(char),
+                            None => bail!("unclosed `{{`"),
+                        }
+                    }
+                    let field =
+                        Field::from_name(&name).ok_or_else(|| anyhow!("unknown field `{name}`"))?;
+                    current.push(Part::Field(field));
+                }
+                '[' if section.is_some() => bail!("sections can't be nested"),
+                '[' => section = Some(vec![]),
+                ']' => {
+                    let section = section.take().ok_or_else(|| anyhow!("unmatched `]`"))?;
+                    parts.push(Part::Section(section));
+                }
+                _ => bail!("unmatched `}}`"),
+            }
+        }
+
+        if section.is_some() {
+            bail!("unclosed `[`");
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    pub fn render(&self, data: &LocationData) -> OsString {
+        let mut rendered = OsString::new();
+        render_parts(&self.parts, data, &mut rendered);
+        rendered
+    }
+}
+
+/// Returns `false` if some field is unset
+fn render_parts(parts: &[Part], data: &LocationData, rendered: &mut OsString) -> bool {
+    let mut complete = true;
+    for part in parts {
+        match part {
+            Part::Literal(literal) => rendered.push(literal),
+            Part::Field(field) => match field.value(data) {
+                Some(value) => rendered.push(value),
+                None => complete = false,
+            },
+            Part::Section(parts) => {
+                let mut section = OsString::new();
+                if render_parts(parts, data, &mut section) {
+                    rendered.push(section);
+                }
+            }
+        }
+    }
+    complete
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LocationData;
+    use crate::template::Template;
+
+    #[test]
+    fn render() {
+        let template: Template =
+            "nvim[ --server {nvim_pipe}] {location} {{[[x]]}}[ ({extra.branch})]"
+                .parse()
+                .unwrap();
+
+        let mut data = LocationData::new("/srv".into());
+        assert_eq!(template.render(&data), "nvim /srv {[x]}");
+
+        data.nvim_pipe = Some("/run/nvim.sock".to_owned());
+        data.extra.insert("branch".to_owned(), "main".to_owned());
+        assert_eq!(
+            template.render(&data),
+            "nvim --server /run/nvim.sock /srv {[x]} (main)"
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for template in [
+            "{pid}",
+            "{location",
+            "location}",
+            "[[x]",
+            "[x",
+            "x]",
+            "[[x]]]",
+        ] {
+            assert!(template.parse::<Template>().is_err(), "{template}");
+        }
+        assert!("[a [b]]".parse::<Template>().is_err());
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(dead_code)]
 
-use std::{collections::VecDeque, iter, marker::PhantomData, ops::ControlFlow};
+use std::{collections::VecDeque, fmt, iter, marker::PhantomData, ops::ControlFlow};
 
 pub trait Node<T: ?Sized> {
     type Context;
@@ -25,103 +25,128 @@ impl<'a, T, N> Clone for WalkerNode<'a, T, N> {
     }
 }
 
-impl<'a, T, N> WalkerNode<'a, T, N> {
-    fn root(inner: &'a N) -> Self {
+impl<'a, T, N> Copy for WalkerNode<'a, T, N> {}
+
+#[derive(Eq, PartialEq, Default, Copy, Clone, Debug)]
+pub enum ContinueFlow {
+    #[default]
+    Forward,
+    Skip,
+}
+
+/// Tree walked by [`TreeWalker`]. Its nodes are handles, e.g. owned nodes or indices of an arena,
+/// so the tree can be built lazily while it's walked
+pub trait Tree {
+    type Node;
+
+    /// Called once for every walked node unless its subtree is skipped, children may be built here
+    fn children(&mut self, node: &Self::Node) -> impl Iterator<Item = Self::Node>;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Visit<H> {
+    pub node: H,
+    pub depth: u64,
+    pub sibling_no: u64,
+}
+
+impl<H> Visit<H> {
+    fn root(node: H) -> Self {
         Self {
-            inner,
+            node,
             depth: 0,
             sibling_no: 0,
-            _data: PhantomData,
         }
     }
 }
 
-impl<'a, T, N> WalkerNode<'a, T, N>
-where
-    N: Node<T>,
-{
-    fn children(&self, ctx: &'a N::Context) -> impl Iterator<Item = WalkerNode<'a, T, N>> {
-        self.inner
-            .children(ctx)
-            .enumerate()
-            .map(|(i, n)| WalkerNode {
-                inner: n,
-                depth: self.depth + 1,
-                sibling_no: i as u64,
-                _data: PhantomData,
-            })
-    }
-}
-
-impl<'a, T, N> Copy for WalkerNode<'a, T, N> {}
-
+/// Walks [`Tree`] it owns, the tree is passed to callbacks along with visited node
 #[derive(Clone, Debug)]
-pub struct Walker<'a, T, N: Node<T>> {
-    ctx: &'a N::Context,
-    // A "workhorse" collection: https://nnethercote.github.io/perf-book/heap-allocations.html#reusing-collections
-    heap: VecDeque<WalkerNode<'a, T, N>>,
-    _data: PhantomData<T>,
+pub struct TreeWalker<Tr: Tree> {
+    tree: Tr,
+    heap: VecDeque<Visit<Tr::Node>>,
+    /// Nodes whose children are being walked by post-order DFS along with length of `heap` once
+    /// they are all walked
+    expanded: Vec<(Visit<Tr::Node>, usize)>,
 }
 
-#[derive(Eq, PartialEq, Default, Copy, Clone, Debug)]
-pub enum ContinueFlow {
-    #[default]
-    Forward,
-    Skip,
-}
-
-impl<'a, T, N: Node<T>> Walker<'a, T, N> {
-    pub fn new(root: &'a N, ctx: &'a N::Context) -> Self {
+impl<Tr: Tree> TreeWalker<Tr> {
+    pub fn new(tree: Tr, root: Tr::Node) -> Self {
         Self {
-            ctx,
-            heap: iter::once(WalkerNode::root(root)).collect(),
-            _data: PhantomData,
+            tree,
+            heap: iter::once(Visit::root(root)).collect(),
+            expanded: vec![],
         }
     }
 
-    pub fn with_capacity(root: &'a N, ctx: &'a N::Context, capacity: usize) -> Self {
+    pub fn with_capacity(tree: Tr, root: Tr::Node, capacity: usize) -> Self {
         let mut heap = VecDeque::with_capacity(capacity);
-        heap.push_front(WalkerNode::root(root));
+        heap.push_front(Visit::root(root));
 
         Self {
-            ctx,
+            tree,
             heap,
-            _data: PhantomData,
+            expanded: vec![],
         }
     }
 
-    pub fn set(&mut self, root: &'a N) {
+    pub fn set(&mut self, root: Tr::Node) {
         self.heap.clear();
-        self.heap.push_front(WalkerNode::root(root));
+        self.expanded.clear();
+        self.heap.push_front(Visit::root(root));
     }
-}
 
-impl<'a, T, N: Node<T>> Walker<'a, T, N> {
-    pub fn bfs_step<R>(
-        &mut self,
-        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
-    ) -> ControlFlow<R, ContinueFlow> {
-        self.bfs_step_by_ref(&mut f)
+    pub fn tree(&self) -> &Tr {
+        &self.tree
     }
 
-    pub fn bfs_step_by_ref<R>(
+    pub fn tree_mut(&mut self) -> &mut Tr {
+        &mut self.tree
+    }
+
+    pub fn into_tree(self) -> Tr {
+        self.tree
+    }
+
+    fn step<R>(
         &mut self,
-        f: &mut impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+        current: Option<Visit<Tr::Node>>,
+        f: &mut impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R, ContinueFlow>,
     ) -> ControlFlow<R, ContinueFlow> {
-        let Some(current_node) = self.heap.pop_front() else {
+        let Some(current) = current else {
             return ControlFlow::Continue(ContinueFlow::Forward);
         };
 
-        let control_flow = f(current_node);
+        let control_flow = f(&self.tree, &current);
         if !matches!(control_flow, ControlFlow::Continue(ContinueFlow::Skip)) {
-            self.heap.extend(current_node.children(self.ctx));
+            let children = self.tree.children(&current.node).enumerate();
+            self.heap.extend(children.map(|(i, node)| Visit {
+                node,
+                depth: current.depth + 1,
+                sibling_no: i as u64,
+            }));
         }
         control_flow
     }
 
+    pub fn bfs_step<R>(
+        &mut self,
+        mut f: impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R, ContinueFlow>,
+    ) -> ControlFlow<R, ContinueFlow> {
+        self.bfs_step_by_ref(&mut f)
+    }
+
+    pub fn bfs_step_by_ref<R>(
+        &mut self,
+        f: &mut impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R, ContinueFlow>,
+    ) -> ControlFlow<R, ContinueFlow> {
+        let current = self.heap.pop_front();
+        self.step(current, f)
+    }
+
     pub fn bfs<R>(
         &mut self,
-        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+        mut f: impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R, ContinueFlow>,
     ) -> Option<R> {
         while !self.heap.is_empty() {
             if let ControlFlow::Break(value) = self.bfs_step_by_ref(&mut f) {
@@ -134,29 +159,22 @@ impl<'a, T, N: Node<T>> Walker<'a, T, N> {
 
     pub fn dfs_step<R>(
         &mut self,
-        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+        mut f: impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R, ContinueFlow>,
     ) -> ControlFlow<R, ContinueFlow> {
         self.dfs_step_by_ref(&mut f)
     }
 
     pub fn dfs_step_by_ref<R>(
         &mut self,
-        f: &mut impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+        f: &mut impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R, ContinueFlow>,
     ) -> ControlFlow<R, ContinueFlow> {
-        let Some(current_node) = self.heap.pop_back() else {
-            return ControlFlow::Continue(ContinueFlow::Forward);
-        };
-
-        let control_flow = f(current_node);
-        if !matches!(control_flow, ControlFlow::Continue(ContinueFlow::Skip)) {
-            self.heap.extend(current_node.children(self.ctx));
-        }
-        control_flow
+        let current = self.heap.pop_back();
+        self.step(current, f)
     }
 
     pub fn dfs<R>(
         &mut self,
-        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+        mut f: impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R, ContinueFlow>,
     ) -> Option<R> {
         while !self.heap.is_empty() {
             if let ControlFlow::Break(value) = self.dfs_step_by_ref(&mut f) {
@@ -166,4 +184,302 @@ impl<'a, T, N: Node<T>> Walker<'a, T, N> {
 
         None
     }
+
+    /// Visits the next node whose children are all visited, `f` isn't called once every node is
+    pub fn dfs_post_step<R>(
+        &mut self,
+        mut f: impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R>,
+    ) -> ControlFlow<R> {
+        self.dfs_post_step_by_ref(&mut f)
+    }
+
+    pub fn dfs_post_step_by_ref<R>(
+        &mut self,
+        f: &mut impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R>,
+    ) -> ControlFlow<R> {
+        loop {
+            if let Some(&(_, walked)) = self.expanded.last()
+                && walked == self.heap.len()
+            {
+                let (current, _) = self.expanded.pop().expect("node is expanded");
+                return f(&self.tree, &current);
+            }
+            let Some(current) = self.heap.pop_back() else {
+                return ControlFlow::Continue(());
+            };
+
+            let walked = self.heap.len();
+            let children = self.tree.children(&current.node).enumerate();
+            self.heap.extend(children.map(|(i, node)| Visit {
+                node,
+                depth: current.depth + 1,
+                sibling_no: i as u64,
+            }));
+            self.expanded.push((current, walked));
+        }
+    }
+
+    /// Post-order DFS, i.e. children are visited before their parent, e.g. to aggregate data of
+    /// subtrees bottom-up. Subtrees can't be skipped since they are walked by then
+    pub fn dfs_post<R>(
+        &mut self,
+        mut f: impl FnMut(&Tr, &Visit<Tr::Node>) -> ControlFlow<R>,
+    ) -> Option<R> {
+        while !self.heap.is_empty() || !self.expanded.is_empty() {
+            if let ControlFlow::Break(value) = self.dfs_post_step_by_ref(&mut f) {
+                return value.into();
+            }
+        }
+
+        None
+    }
+}
+
+/// [`Tree`] of [`Node`]s borrowed from their context, the one [`Walker`] walks
+pub struct Borrowed<'a, T, N: Node<T>> {
+    ctx: &'a N::Context,
+    _data: PhantomData<T>,
+}
+
+impl<'a, T, N: Node<T>> Borrowed<'a, T, N> {
+    pub fn new(ctx: &'a N::Context) -> Self {
+        Self {
+            ctx,
+            _data: PhantomData,
+        }
+    }
+}
+
+impl<'a, T, N: Node<T>> Clone for Borrowed<'a, T, N> {
+    fn clone(&self) -> Self {
+        Self::new(self.ctx)
+    }
+}
+
+impl<'a, T, N: Node<T>> fmt::Debug for Borrowed<'a, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Borrowed").finish_non_exhaustive()
+    }
+}
+
+impl<'a, T, N: Node<T> + 'a> Tree for Borrowed<'a, T, N> {
+    type Node = &'a N;
+
+    fn children(&mut self, node: &&'a N) -> impl Iterator<Item = &'a N> {
+        let node: &'a N = node;
+        node.children(self.ctx)
+    }
+}
+
+impl<'a, T, N> From<Visit<&'a N>> for WalkerNode<'a, T, N> {
+    fn from(visit: Visit<&'a N>) -> Self {
+        Self {
+            inner: visit.node,
+            depth: visit.depth,
+            sibling_no: visit.sibling_no,
+            _data: PhantomData,
+        }
+    }
+}
+
+/// Walks [`Node`]s of an already built tree, see [`TreeWalker`] for trees built while walking
+#[derive(Clone, Debug)]
+pub struct Walker<'a, T, N: Node<T> + 'a> {
+    // A "workhorse" collection: https://nnethercote.github.io/perf-book/heap-allocations.html#reusing-collections
+    walker: TreeWalker<Borrowed<'a, T, N>>,
+}
+
+impl<'a, T, N: Node<T> + 'a> Walker<'a, T, N> {
+    pub fn new(root: &'a N, ctx: &'a N::Context) -> Self {
+        Self {
+            walker: TreeWalker::new(Borrowed::new(ctx), root),
+        }
+    }
+
+    pub fn with_capacity(root: &'a N, ctx: &'a N::Context, capacity: usize) -> Self {
+        Self {
+            walker: TreeWalker::with_capacity(Borrowed::new(ctx), root, capacity),
+        }
+    }
+
+    pub fn set(&mut self, root: &'a N) {
+        self.walker.set(root);
+    }
+}
+
+impl<'a, T, N: Node<T> + 'a> Walker<'a, T, N> {
+    pub fn bfs_step<R>(
+        &mut self,
+        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+    ) -> ControlFlow<R, ContinueFlow> {
+        self.bfs_step_by_ref(&mut f)
+    }
+
+    pub fn bfs_step_by_ref<R>(
+        &mut self,
+        f: &mut impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+    ) -> ControlFlow<R, ContinueFlow> {
+        self.walker
+            .bfs_step_by_ref(&mut |_, visit| f(WalkerNode::from(*visit)))
+    }
+
+    pub fn bfs<R>(
+        &mut self,
+        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+    ) -> Option<R> {
+        self.walker.bfs(|_, visit| f(WalkerNode::from(*visit)))
+    }
+
+    pub fn dfs_step<R>(
+        &mut self,
+        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+    ) -> ControlFlow<R, ContinueFlow> {
+        self.dfs_step_by_ref(&mut f)
+    }
+
+    pub fn dfs_step_by_ref<R>(
+        &mut self,
+        f: &mut impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+    ) -> ControlFlow<R, ContinueFlow> {
+        self.walker
+            .dfs_step_by_ref(&mut |_, visit| f(WalkerNode::from(*visit)))
+    }
+
+    pub fn dfs<R>(
+        &mut self,
+        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R, ContinueFlow>,
+    ) -> Option<R> {
+        self.walker.dfs(|_, visit| f(WalkerNode::from(*visit)))
+    }
+
+    /// Same as [`Self::bfs`] but nodes are yielded by iterator, so no subtree can be skipped
+    pub fn iter_bfs(&mut self) -> impl Iterator<Item = WalkerNode<'a, T, N>> {
+        iter::from_fn(|| self.bfs_step(ControlFlow::Break).break_value())
+    }
+
+    /// Same as [`Self::dfs`] but nodes are yielded by iterator, so no subtree can be skipped
+    pub fn iter_dfs(&mut self) -> impl Iterator<Item = WalkerNode<'a, T, N>> {
+        iter::from_fn(|| self.dfs_step(ControlFlow::Break).break_value())
+    }
+
+    /// Visits the next node in post-order, i.e. children before their parent, see
+    /// [`TreeWalker::dfs_post_step`]
+    pub fn dfs_post_step<R>(
+        &mut self,
+        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R>,
+    ) -> ControlFlow<R> {
+        self.walker
+            .dfs_post_step_by_ref(&mut |_, visit| f(WalkerNode::from(*visit)))
+    }
+
+    /// See [`TreeWalker::dfs_post`]
+    pub fn dfs_post<R>(
+        &mut self,
+        mut f: impl FnMut(WalkerNode<'a, T, N>) -> ControlFlow<R>,
+    ) -> Option<R> {
+        self.walker.dfs_post(|_, visit| f(WalkerNode::from(*visit)))
+    }
+
+    /// Same as [`Self::dfs_post`] but nodes are yielded by iterator
+    pub fn iter_dfs_post(&mut self) -> impl Iterator<Item = WalkerNode<'a, T, N>> {
+        iter::from_fn(|| self.dfs_post_step(ControlFlow::Break).break_value())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::fixture::TreeBuilder;
+    use crate::process::{Process, ProcessInfo};
+
+    /// Infinite binary tree of heap indices, children are built when they are reached
+    #[derive(Default)]
+    struct Heap {
+        built: Vec<u64>,
+    }
+
+    impl Tree for Heap {
+        type Node = u64;
+
+        fn children(&mut self, node: &u64) -> impl Iterator<Item = u64> {
+            let children = [node * 2, node * 2 + 1];
+            self.built.extend(children);
+            children.into_iter()
+        }
+    }
+
+    #[test]
+    fn lazy_tree() {
+        let mut walker = TreeWalker::new(Heap::default(), 1);
+        let found = walker.bfs(|_, visit| match visit.node {
+            5 => ControlFlow::Break((visit.depth, visit.sibling_no)),
+            // subtree of 3 is never built
+            3 => ControlFlow::Continue(ContinueFlow::Skip),
+            _ => ControlFlow::Continue(ContinueFlow::Forward),
+        });
+        assert_eq!(found, Some((2, 1)));
+        assert_eq!(walker.into_tree().built, [2, 3, 4, 5, 8, 9, 10, 11]);
+    }
+
+    #[test]
+    fn iter() {
+        // kitty (1) -> zsh (2) -> [htop (3), zsh (4) -> nvim (5)]
+        let processes = TreeBuilder::new("kitty")
+            .child("zsh")
+            .child("htop")
+            .up()
+            .child("zsh")
+            .child("nvim")
+            .build();
+        let mut walker = Walker::new(&processes[&1], &processes);
+        let pids = |nodes: &mut dyn Iterator<Item = WalkerNode<'_, ProcessInfo, Process>>| {
+            nodes.map(|node| node.inner.data().pid).collect::<Vec<_>>()
+        };
+
+        assert_eq!(pids(&mut walker.iter_bfs()), [1, 2, 3, 4, 5]);
+        walker.set(&processes[&1]);
+        assert_eq!(pids(&mut walker.iter_dfs()), [1, 2, 4, 5, 3]);
+
+        walker.set(&processes[&1]);
+        let nvim = walker
+            .iter_bfs()
+            .find(|node| &*node.inner.data().name == "nvim");
+        assert_eq!(nvim.map(|node| node.depth), Some(3));
+
+        walker.set(&processes[&1]);
+        assert_eq!(pids(&mut walker.iter_dfs_post()), [5, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn post_order() {
+        // kitty (1) -> zsh (2) -> [zsh (3) -> nvim (4), zsh (5) -> htop (6)]
+        let processes = TreeBuilder::new("kitty")
+            .child("zsh")
+            .child("zsh")
+            .child("nvim")
+            .up()
+            .up()
+            .child("zsh")
+            .child("htop")
+            .build();
+        let mut walker = Walker::new(&processes[&1], &processes);
+
+        // whether some descendant is nvim, results of children are on top of the stack
+        let mut found: Vec<(u64, bool)> = vec![];
+        let mut shells = vec![];
+        walker.dfs_post::<()>(|node| {
+            let mut below = false;
+            while found.last().is_some_and(|&(depth, _)| depth > node.depth) {
+                below |= found.pop().expect("checked above").1;
+            }
+            let proc = node.inner.data();
+            if below && &*proc.name == "zsh" {
+                shells.push(proc.pid);
+            }
+            found.push((node.depth, below || &*proc.name == "nvim"));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(shells, [3, 2]);
+        assert_eq!(found, [(0, true)]);
+    }
 }
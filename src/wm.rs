@@ -0,0 +1,160 @@
+use std::{env, panic};
+
+use anyhow::{anyhow, bail};
+
+use crate::process::Pid;
+
+#[cfg(feature = "gnome")]
+pub mod gnome;
+#[cfg(feature = "hyprland")]
+pub mod hyprland;
+#[cfg(feature = "kwin")]
+pub mod kwin;
+pub mod niri;
+pub mod sway;
+#[cfg(feature = "x11")]
+pub mod x11;
+
+/// Source of active window, implement it to support window managers unknown to this crate
+pub trait ActiveWindowProvider {
+    /// Returns pid of active window or `None` if no window is focused
+    fn active_pid(&self) -> impl Future<Output = anyhow::Result<Option<Pid>>> + Send;
+}
+
+/// Window manager to request active window from
+#[derive(clap::ValueEnum, Default, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum WindowManager {
+    /// Detect window manager from environment, see [`WindowManager::detect`]
+    #[default]
+    Auto,
+    #[cfg(feature = "hyprland")]
+    Hyprland,
+    /// Sway, socket is taken from `SWAYSOCK`
+    Sway,
+    /// niri, socket is taken from `NIRI_SOCKET`
+    Niri,
+    /// Any X11 window manager supporting EWMH, display is taken from `DISPLAY`
+    #[cfg(feature = "x11")]
+    X11,
+    /// KWin, requires D-Bus session bus
+    #[cfg(feature = "kwin")]
+    Kwin,
+    /// GNOME Shell, requires `Window Calls` extension
+    #[cfg(feature = "gnome")]
+    Gnome,
+}
+
+impl WindowManager {
+    /// Picks window manager by environment variables it sets for its clients
+    pub fn detect() -> anyhow::Result<Self> {
+        let is_set = |var| env::var_os(var).is_some_and(|value| !value.is_empty());
+
+        #[cfg(feature = "hyprland")]
+        if is_set("HYPRLAND_INSTANCE_SIGNATURE") {
+            return Ok(Self::Hyprland);
+        }
+        // i3 sets only `I3SOCK` but its IPC has no pids, it's detected as X11 below
+        if is_set("SWAYSOCK") {
+            return Ok(Self::Sway);
+        }
+        if is_set("NIRI_SOCKET") {
+            return Ok(Self::Niri);
+        }
+
+        if is_set("WAYLAND_DISPLAY") {
+            // compositors without own socket are told apart by desktop name
+            #[cfg(feature = "kwin")]
+            if is_current_desktop("KDE") {
+                return Ok(Self::Kwin);
+            }
+            #[cfg(feature = "gnome")]
+            if is_current_desktop("GNOME") {
+                return Ok(Self::Gnome);
+            }
+
+            bail!("unsupported Wayland compositor, use `--active-pid` instead");
+        }
+
+        #[cfg(feature = "x11")]
+        if is_set("DISPLAY") {
+            return Ok(Self::X11);
+        }
+
+        bail!("no supported window manager detected, use `--wm` or `--active-pid`")
+    }
+
+    /// Same as [`ActiveWindowProvider::active_pid`] but with blocking IPC, so no async runtime is
+    /// required
+    pub fn active_pid_blocking(self) -> anyhow::Result<Option<Pid>> {
+        let wm = match self {
+            Self::Auto => Self::detect()?,
+            wm => wm,
+        };
+        tracing::debug!(?wm, "request active window");
+
+        match wm {
+            Self::Auto => unreachable!("window manager is detected above"),
+            #[cfg(feature = "hyprland")]
+            Self::Hyprland => hyprland::Hyprland::default().active_pid_blocking(),
+            Self::Sway => sway::active_pid(),
+            Self::Niri => niri::active_pid(),
+            #[cfg(feature = "x11")]
+            Self::X11 => x11::active_pid(),
+            #[cfg(feature = "kwin")]
+            Self::Kwin => kwin::active_pid(),
+            #[cfg(feature = "gnome")]
+            Self::Gnome => gnome::active_pid(),
+        }
+    }
+}
+
+impl ActiveWindowProvider for WindowManager {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        let wm = match self {
+            Self::Auto => Self::detect()?,
+            wm => *wm,
+        };
+        tracing::debug!(?wm, "request active window");
+
+        match wm {
+            Self::Auto => unreachable!("window manager is detected above"),
+            #[cfg(feature = "hyprland")]
+            Self::Hyprland => hyprland::Hyprland::default().active_pid().await,
+            Self::Sway => sway::Sway.active_pid().await,
+            Self::Niri => niri::Niri.active_pid().await,
+            #[cfg(feature = "x11")]
+            Self::X11 => x11::X11.active_pid().await,
+            #[cfg(feature = "kwin")]
+            Self::Kwin => kwin::Kwin.active_pid().await,
+            #[cfg(feature = "gnome")]
+            Self::Gnome => gnome::Gnome.active_pid().await,
+        }
+    }
+}
+
+/// `XDG_CURRENT_DESKTOP` is a colon separated list of desktop names
+#[cfg(any(feature = "kwin", feature = "gnome"))]
+fn is_current_desktop(name: &str) -> bool {
+    env::var("XDG_CURRENT_DESKTOP").is_ok_and(|desktops| desktops.split(':').any(|d| d == name))
+}
+
+/// Asks window manager with blocking IPC on a thread of [`blocking`] pool, so searching with it
+/// doesn't depend on async runtime of window manager clients
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct BlockingIpc(pub WindowManager);
+
+impl ActiveWindowProvider for BlockingIpc {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        let wm = self.0;
+        blocking::unblock(move || panic::catch_unwind(move || wm.active_pid_blocking()))
+            .await
+            .map_err(|_| anyhow!("window manager thread panicked"))?
+    }
+}
+
+/// Runs blocking IPC client outside of async runtime
+async fn spawn_blocking(
+    active_pid: fn() -> anyhow::Result<Option<Pid>>,
+) -> anyhow::Result<Option<Pid>> {
+    blocking::unblock(active_pid).await
+}
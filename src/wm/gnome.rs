@@ -0,0 +1,51 @@
+//! GNOME Shell
+//!
+//! GNOME Shell doesn't expose windows over D-Bus by itself, so
+//! [Window Calls](https://github.com/ickyicky/window-calls) extension is required
+
+use anyhow::Context;
+use serde::Deserialize;
+use zbus::blocking::Connection;
+
+use crate::process::Pid;
+use crate::wm::{self, ActiveWindowProvider};
+
+#[derive(Deserialize, Clone, Debug)]
+struct Window {
+    pid: Pid,
+    focus: bool,
+}
+
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Gnome;
+
+impl ActiveWindowProvider for Gnome {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        wm::spawn_blocking(active_pid).await
+    }
+}
+
+pub fn active_pid() -> anyhow::Result<Option<Pid>> {
+    let conn = Connection::session().context("connect to session bus")?;
+    let windows: String = conn
+        .call_method(
+            Some("org.gnome.Shell"),
+            "/org/gnome/Shell/Extensions/Windows",
+            Some("org.gnome.Shell.Extensions.Windows"),
+            "List",
+            &(),
+        )
+        .context("list windows, is `Window Calls` extension installed?")?
+        .body()
+        .deserialize()
+        .context("deserialize windows")?;
+    let windows: Vec<Window> = serde_json::from_str(&windows).context("parse windows")?;
+
+    // windows of clients without pid report zero
+    let pid = windows
+        .into_iter()
+        .find(|window| window.focus)
+        .map(|window| window.pid)
+        .filter(|&pid| pid > 0);
+    Ok(pid)
+}
@@ -0,0 +1,342 @@
+use std::process::{Command, Output};
+#[cfg(feature = "tokio")]
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, ensure};
+#[cfg(feature = "tokio")]
+use futures_lite::StreamExt;
+use futures_lite::future;
+use hyprland::data::{Client, Clients, Monitors, Workspaces};
+#[cfg(feature = "tokio")]
+use hyprland::event_listener::{Event, EventStream};
+use hyprland::instance::Instance;
+use hyprland::shared::{Address, HyprData, HyprDataActiveOptional};
+use serde::Deserialize;
+#[cfg(feature = "tokio")]
+use tokio::sync::Notify;
+#[cfg(feature = "tokio")]
+use tokio::task::AbortHandle;
+
+use crate::process::Pid;
+use crate::wm::ActiveWindowProvider;
+
+/// Hyprland instance, the one from `HYPRLAND_INSTANCE_SIGNATURE` by default
+#[derive(Default, Clone, Debug)]
+pub struct Hyprland {
+    signature: Option<String>,
+    instance: Option<Instance>,
+}
+
+/// Part of `hyprctl -j activewindow` output, it's `{}` if no window is focused
+#[derive(Deserialize, Clone, Debug)]
+struct HyprctlWindow {
+    pid: Option<Pid>,
+}
+
+impl Hyprland {
+    /// Selects instance by its signature, useful when multiple instances are running
+    pub fn with_instance(signature: String) -> anyhow::Result<Self> {
+        let instance =
+            Instance::from_instance(signature.clone()).context("find Hyprland instance")?;
+        Ok(Self {
+            signature: signature.into(),
+            instance: instance.into(),
+        })
+    }
+
+    fn instance(&self) -> anyhow::Result<&Instance> {
+        match &self.instance {
+            Some(instance) => Ok(instance),
+            None => hyprland::default_instance().context("find Hyprland instance"),
+        }
+    }
+
+    /// Returns pid of window with given address or `None` if there is no such window
+    pub async fn window_pid(&self, address: &Address) -> anyhow::Result<Option<Pid>> {
+        let clients = Clients::instance_get_async(self.instance()?)
+            .await
+            .context("get clients")?;
+        let pid = clients
+            .into_iter()
+            .find(|client| &client.address == address)
+            .map(|client| client.pid);
+        Ok(pid)
+    }
+
+    /// Pids and addresses of open windows with blocking IPC
+    pub fn windows_blocking(&self) -> anyhow::Result<Vec<(Pid, String)>> {
+        let clients = Clients::instance_get(self.instance()?).context("get clients")?;
+        let windows = clients
+            .into_iter()
+            .map(|client| (client.pid, client.address.to_string()))
+            .collect();
+        Ok(windows)
+    }
+
+    /// Same as [`ActiveWindowProvider::active_pid`] but with blocking IPC, so no async runtime is
+    /// required
+    pub fn active_pid_blocking(&self) -> anyhow::Result<Option<Pid>> {
+        let ipc = || {
+            let active_client = Client::instance_get_active(self.instance()?)?;
+            anyhow::Ok(active_client.map(|client| client.pid))
+        };
+
+        match ipc() {
+            Ok(pid) => Ok(pid),
+            Err(err) => self
+                .hyprctl()
+                .output()
+                .context("run hyprctl")
+                .and_then(parse_hyprctl_output)
+                .with_context(|| format!("IPC failed: {err:#}"))
+                .context("failed to get active client"),
+        }
+    }
+
+    /// `hyprctl -j activewindow`, fallback for when hyprland-rs doesn't keep up with IPC changes of
+    /// new Hyprland
+    fn hyprctl(&self) -> Command {
+        let mut command = Command::new("hyprctl");
+        if let Some(signature) = &self.signature {
+            command.arg("--instance").arg(signature);
+        }
+        command.args(["-j", "activewindow"]);
+        command
+    }
+
+    async fn hyprctl_active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        let mut command = self.hyprctl();
+        let output = blocking::unblock(move || command.output())
+            .await
+            .context("run hyprctl")?;
+        parse_hyprctl_output(output)
+    }
+}
+
+fn parse_hyprctl_output(output: Output) -> anyhow::Result<Option<Pid>> {
+    ensure!(
+        output.status.success(),
+        "hyprctl failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let window: HyprctlWindow =
+        serde_json::from_slice(&output.stdout).context("deserialize hyprctl output")?;
+    Ok(window.pid)
+}
+
+impl ActiveWindowProvider for Hyprland {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        let ipc = async {
+            let active_client = Client::instance_get_active_async(self.instance()?).await?;
+            anyhow::Ok(active_client.map(|client| client.pid))
+        };
+
+        match ipc.await {
+            Ok(pid) => Ok(pid),
+            Err(err) => self
+                .hyprctl_active_pid()
+                .await
+                .with_context(|| format!("IPC failed: {err:#}"))
+                .context("failed to get active client"),
+        }
+    }
+}
+
+/// Specific window instead of the active one, fails if there is no window with such address
+#[derive(Clone, Debug)]
+pub struct Window {
+    pub hyprland: Hyprland,
+    pub address: Address,
+}
+
+impl Window {
+    /// `address` is in hex with or without `0x` prefix, Hyprland reports it with one
+    pub fn new(hyprland: Hyprland, address: &str) -> Self {
+        let address = address.trim_start_matches("0x");
+        Self {
+            hyprland,
+            address: Address::new(format!("0x{address}")),
+        }
+    }
+}
+
+impl ActiveWindowProvider for Window {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        let pid = self
+            .hyprland
+            .window_pid(&self.address)
+            .await?
+            .with_context(|| format!("window {} not found", self.address))?;
+        Ok(pid.into())
+    }
+}
+
+/// Window matching class and title instead of the active one, the most recently focused is
+/// taken if there are several. Fails if no window matches
+#[derive(Clone, Debug)]
+pub struct WindowMatch {
+    pub hyprland: Hyprland,
+    /// Matched exactly
+    pub class: Option<String>,
+    /// Matched as substring
+    pub title: Option<String>,
+}
+
+impl ActiveWindowProvider for WindowMatch {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        let clients = Clients::instance_get_async(self.hyprland.instance()?)
+            .await
+            .context("get clients")?;
+        let client = clients
+            .into_iter()
+            .filter(|client| {
+                self.class
+                    .as_ref()
+                    .is_none_or(|class| &client.class == class)
+            })
+            .filter(|client| {
+                self.title
+                    .as_ref()
+                    .is_none_or(|title| client.title.contains(title.as_str()))
+            })
+            .min_by_key(|client| client.focus_history_id)
+            .context("no window matches")?;
+        Ok(client.pid.into())
+    }
+}
+
+/// Active window of a specific monitor instead of the focused one, fails if there is no monitor
+/// with such name
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    pub hyprland: Hyprland,
+    pub name: String,
+}
+
+impl ActiveWindowProvider for Monitor {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        let instance = self.hyprland.instance()?;
+        let (monitors, workspaces) = future::zip(
+            Monitors::instance_get_async(instance),
+            Workspaces::instance_get_async(instance),
+        )
+        .await;
+        let monitors = monitors.context("get monitors")?;
+        let workspaces = workspaces.context("get workspaces")?;
+
+        let monitor = monitors
+            .into_iter()
+            .find(|monitor| monitor.name == self.name)
+            .with_context(|| format!("monitor {} not found", self.name))?;
+        // special workspace is shown on top of the regular one
+        let workspace_id = if monitor.special_workspace.id != 0 {
+            monitor.special_workspace.id
+        } else {
+            monitor.active_workspace.id
+        };
+
+        let Some(workspace) = workspaces
+            .into_iter()
+            .find(|workspace| workspace.id == workspace_id)
+        else {
+            return Ok(None);
+        };
+        if workspace.windows == 0 {
+            return Ok(None);
+        }
+
+        self.hyprland.window_pid(&workspace.last_window).await
+    }
+}
+
+/// Keeps track of active window by listening to Hyprland events, so asking for active window
+/// doesn't need an IPC round-trip
+///
+/// Makes sense only for long-living processes, events are handled by a background task which is
+/// stopped on drop
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+pub struct ActiveWindowTracker {
+    hyprland: Hyprland,
+    active_pid: Arc<Mutex<Option<Pid>>>,
+    focus_changed: Arc<Notify>,
+    listener: AbortHandle,
+}
+
+#[cfg(feature = "tokio")]
+impl ActiveWindowTracker {
+    pub async fn start(hyprland: Hyprland) -> anyhow::Result<Self> {
+        // window focused in between these two is caught on the next focus change
+        let active_pid = Arc::new(Mutex::new(hyprland.active_pid().await?));
+        let mut events = EventStream::instance_new(hyprland.instance()?.clone());
+        let focus_changed = Arc::new(Notify::new());
+
+        let listener = tokio::spawn({
+            let hyprland = hyprland.clone();
+            let active_pid = active_pid.clone();
+            let focus_changed = focus_changed.clone();
+            async move {
+                while let Some(event) = events.next().await {
+                    let Ok(Event::ActiveWindowChanged(window)) = event else {
+                        continue;
+                    };
+
+                    // new pid is requested only once per focus change
+                    let pid = match window {
+                        Some(window) => hyprland.window_pid(&window.address).await.ok().flatten(),
+                        None => None,
+                    };
+                    *active_pid.lock().expect("lock is not poisoned") = pid;
+                    focus_changed.notify_one();
+                }
+            }
+        });
+
+        Ok(Self {
+            hyprland,
+            active_pid,
+            focus_changed,
+            listener: listener.abort_handle(),
+        })
+    }
+
+    /// Completes on the next focus change or right away if focus has changed since the last call.
+    /// Never completes if event socket is closed
+    pub async fn focus_changed(&self) {
+        self.focus_changed.notified().await;
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ActiveWindowProvider for ActiveWindowTracker {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        // event socket is closed, e.g. Hyprland is reloaded
+        if self.listener.is_finished() {
+            return self.hyprland.active_pid().await;
+        }
+
+        let active_pid = *self.active_pid.lock().expect("lock is not poisoned");
+        Ok(active_pid)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Drop for ActiveWindowTracker {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wm::hyprland::{Hyprland, Window};
+
+    #[test]
+    fn window_address() {
+        for address in ["5f3a", "0x5f3a"] {
+            let window = Window::new(Hyprland::default(), address);
+            assert_eq!(window.address.to_string(), "0x5f3a");
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! KWin (KDE Plasma)
+//!
+//! KWin doesn't expose active window over D-Bus directly, so a tiny KWin script is loaded which
+//! reports pid of active window back to our connection
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use std::{env, process};
+
+use anyhow::Context;
+use zbus::blocking::{Connection, connection};
+use zbus::interface;
+
+use crate::process::Pid;
+use crate::wm::{self, ActiveWindowProvider};
+
+const KWIN_SERVICE: &str = "org.kde.KWin";
+const SCRIPTING_INTERFACE: &str = "org.kde.kwin.Scripting";
+const SCRIPT_INTERFACE: &str = "org.kde.kwin.Script";
+const RECEIVER_INTERFACE: &str = "org.current_location.Receiver";
+const REPORT_TIMEOUT: Duration = Duration::from_secs(1);
+
+struct Receiver {
+    tx: mpsc::Sender<String>,
+}
+
+#[interface(name = "org.current_location.Receiver")]
+impl Receiver {
+    /// Called by the script, `pid` is empty if there is no active window
+    fn active_pid(&self, pid: String) {
+        _ = self.tx.send(pid);
+    }
+}
+
+fn script(service: &str) -> String {
+    // `activeClient` is KWin 5 name of `activeWindow`
+    format!(
+        r#"const window = workspace.activeWindow || workspace.activeClient;
+callDBus("{service}", "/", "{RECEIVER_INTERFACE}", "ActivePid", window ? String(window.pid) : "");
+"#
+    )
+}
+
+/// Script file readable only by the user, removed on drop
+struct ScriptFile {
+    path: PathBuf,
+}
+
+impl ScriptFile {
+    /// Created in `$XDG_RUNTIME_DIR` which only the user can write to, temporary directory is
+    /// shared so the file is never followed if it's a symlink there
+    fn create(name: &str, script: &str) -> anyhow::Result<Self> {
+        let dir = env::var_os("XDG_RUNTIME_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map_or_else(env::temp_dir, PathBuf::from);
+        let path = dir.join(name);
+
+        // left by a process which wasn't able to remove it, e.g. killed one with the same pid
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(err).context("remove stale script");
+            }
+            _ => {}
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .custom_flags(nix::libc::O_NOFOLLOW | nix::libc::O_CLOEXEC)
+            .open(&path)
+            .context("create script")?;
+        // removed from now on, even if writing fails
+        let script_file = Self { path };
+        file.write_all(script.as_bytes()).context("write script")?;
+        Ok(script_file)
+    }
+}
+
+impl Drop for ScriptFile {
+    fn drop(&mut self) {
+        _ = fs::remove_file(&self.path);
+    }
+}
+
+fn run_script(conn: &Connection, path: &Path, plugin_name: &str) -> anyhow::Result<()> {
+    let path = path.to_str().context("script path is not valid UTF-8")?;
+    let script_id: i32 = conn
+        .call_method(
+            Some(KWIN_SERVICE),
+            "/Scripting",
+            Some(SCRIPTING_INTERFACE),
+            "loadScript",
+            &(path, plugin_name),
+        )
+        .context("load script")?
+        .body()
+        .deserialize()
+        .context("deserialize script id")?;
+
+    // KWin 6 and KWin 5 object paths respectively
+    let run = |object_path: String| {
+        conn.call_method(
+            Some(KWIN_SERVICE),
+            object_path.as_str(),
+            Some(SCRIPT_INTERFACE),
+            "run",
+            &(),
+        )
+    };
+    run(format!("/Scripting/Script{script_id}"))
+        .or_else(|_| run(format!("/{script_id}")))
+        .context("run script")?;
+
+    Ok(())
+}
+
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Kwin;
+
+impl ActiveWindowProvider for Kwin {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        wm::spawn_blocking(active_pid).await
+    }
+}
+
+pub fn active_pid() -> anyhow::Result<Option<Pid>> {
+    let (tx, rx) = mpsc::channel();
+    let conn = connection::Builder::session()
+        .context("connect to session bus")?
+        .serve_at("/", Receiver { tx })
+        .context("serve receiver")?
+        .build()
+        .context("connect to session bus")?;
+    let service = conn
+        .unique_name()
+        .context("connection has no unique name")?
+        .to_string();
+
+    let plugin_name = format!("current-location-{}", process::id());
+    let file = ScriptFile::create(&format!("{plugin_name}.js"), &script(&service))?;
+
+    // script reports asynchronously so it has to stay loaded until report is received
+    let pid = run_script(&conn, &file.path, &plugin_name).and_then(|()| {
+        rx.recv_timeout(REPORT_TIMEOUT)
+            .context("script didn't report active window")
+    });
+    _ = conn.call_method(
+        Some(KWIN_SERVICE),
+        "/Scripting",
+        Some(SCRIPTING_INTERFACE),
+        "unloadScript",
+        &plugin_name,
+    );
+    drop(file);
+
+    let pid = pid?;
+    if pid.is_empty() {
+        return Ok(None);
+    }
+
+    let pid = pid.parse().context("parse pid")?;
+    Ok(Some(pid))
+}
@@ -0,0 +1,55 @@
+//! Minimal client for niri IPC, socket is taken from `NIRI_SOCKET`
+
+use std::env;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+
+use anyhow::{Context, anyhow};
+use serde::Deserialize;
+
+use crate::process::Pid;
+use crate::wm::{self, ActiveWindowProvider};
+
+#[derive(Deserialize, Clone, Debug)]
+enum Reply {
+    Ok(Response),
+    Err(String),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+enum Response {
+    FocusedWindow(Option<Window>),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Window {
+    pid: Option<Pid>,
+}
+
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Niri;
+
+impl ActiveWindowProvider for Niri {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        wm::spawn_blocking(active_pid).await
+    }
+}
+
+pub fn active_pid() -> anyhow::Result<Option<Pid>> {
+    let path = env::var_os("NIRI_SOCKET").context("`NIRI_SOCKET` is not set")?;
+    let mut stream = UnixStream::connect(path).context("connect to IPC socket")?;
+    stream
+        .write_all(b"\"FocusedWindow\"\n")
+        .context("send request")?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .read_line(&mut reply)
+        .context("read reply")?;
+    let reply: Reply = serde_json::from_str(&reply).context("deserialize reply")?;
+
+    match reply {
+        Reply::Ok(Response::FocusedWindow(window)) => Ok(window.and_then(|window| window.pid)),
+        Reply::Err(err) => Err(anyhow!(err).context("get focused window")),
+    }
+}
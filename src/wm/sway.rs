@@ -0,0 +1,99 @@
+//! Minimal client for Sway IPC, see `man sway-ipc`
+
+use std::env;
+use std::io::{Read, Write};
+use std::ops::ControlFlow;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use anyhow::{Context, ensure};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::process::Pid;
+use crate::walk::{ContinueFlow, Node, Walker};
+use crate::wm::{self, ActiveWindowProvider};
+
+const MAGIC: &[u8; 6] = b"i3-ipc";
+const HEADER_LEN: usize = MAGIC.len() + 2 * size_of::<u32>();
+const GET_TREE: u32 = 4;
+
+#[derive(Deserialize, Clone, Debug)]
+struct TreeNode {
+    #[serde(default)]
+    focused: bool,
+    /// Not reported for containers without a window
+    pid: Option<Pid>,
+    #[serde(default)]
+    nodes: Vec<TreeNode>,
+    #[serde(default)]
+    floating_nodes: Vec<TreeNode>,
+}
+
+impl Node<TreeNode> for TreeNode {
+    type Context = ();
+
+    fn data(&self) -> &TreeNode {
+        self
+    }
+
+    fn data_mut(&mut self) -> &mut TreeNode {
+        self
+    }
+
+    fn children<'a>(&'a self, _: &'a Self::Context) -> impl Iterator<Item = &'a Self> {
+        self.nodes.iter().chain(&self.floating_nodes)
+    }
+}
+
+fn socket_path() -> anyhow::Result<PathBuf> {
+    env::var_os("SWAYSOCK")
+        .map(PathBuf::from)
+        .context("`SWAYSOCK` is not set")
+}
+
+fn request<T: DeserializeOwned>(stream: &mut UnixStream, message_type: u32) -> anyhow::Result<T> {
+    // requests sent here have no payload
+    let mut header = [0; HEADER_LEN];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[HEADER_LEN - size_of::<u32>()..].copy_from_slice(&message_type.to_ne_bytes());
+    stream.write_all(&header).context("send request")?;
+
+    stream.read_exact(&mut header).context("read reply header")?;
+    let (magic, rest) = header.split_at(MAGIC.len());
+    let (len, reply_type) = rest.split_at(size_of::<u32>());
+    ensure!(magic == MAGIC, "invalid reply magic");
+    let reply_type = u32::from_ne_bytes(reply_type.try_into().expect("slice is 4 bytes long"));
+    ensure!(reply_type == message_type, "unexpected reply type: {reply_type}");
+
+    let len = u32::from_ne_bytes(len.try_into().expect("slice is 4 bytes long"));
+    let mut payload = vec![0; len as usize];
+    stream.read_exact(&mut payload).context("read reply payload")?;
+    serde_json::from_slice(&payload).context("deserialize reply payload")
+}
+
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Sway;
+
+impl ActiveWindowProvider for Sway {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        wm::spawn_blocking(active_pid).await
+    }
+}
+
+/// Returns pid of focused window
+pub fn active_pid() -> anyhow::Result<Option<Pid>> {
+    let mut stream = UnixStream::connect(socket_path()?).context("connect to IPC socket")?;
+    let tree: TreeNode = request(&mut stream, GET_TREE).context("get tree")?;
+
+    let mut walker = Walker::new(&tree, &());
+    let focused = walker.dfs(|node| {
+        if node.inner.focused {
+            return ControlFlow::Break(node.inner.pid);
+        }
+
+        ControlFlow::Continue(ContinueFlow::Forward)
+    });
+
+    Ok(focused.flatten())
+}
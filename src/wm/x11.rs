@@ -0,0 +1,57 @@
+//! X11 window managers that support EWMH
+
+use anyhow::Context;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+use crate::process::Pid;
+use crate::wm::{self, ActiveWindowProvider};
+
+#[derive(Default, Copy, Clone, Debug)]
+pub struct X11;
+
+impl ActiveWindowProvider for X11 {
+    async fn active_pid(&self) -> anyhow::Result<Option<Pid>> {
+        wm::spawn_blocking(active_pid).await
+    }
+}
+
+/// Returns pid of `_NET_ACTIVE_WINDOW` taken from its `_NET_WM_PID` property
+pub fn active_pid() -> anyhow::Result<Option<Pid>> {
+    let (conn, screen_num) = x11rb::connect(None).context("connect to X server")?;
+    let root = conn.setup().roots[screen_num].root;
+
+    // send both requests before waiting for replies
+    let net_active_window = conn
+        .intern_atom(true, b"_NET_ACTIVE_WINDOW")
+        .context("intern `_NET_ACTIVE_WINDOW`")?;
+    let net_wm_pid = conn
+        .intern_atom(true, b"_NET_WM_PID")
+        .context("intern `_NET_WM_PID`")?;
+    let net_active_window = net_active_window.reply().context("intern `_NET_ACTIVE_WINDOW`")?;
+    let net_wm_pid = net_wm_pid.reply().context("intern `_NET_WM_PID`")?;
+
+    let active_window = conn
+        .get_property(false, root, net_active_window.atom, AtomEnum::WINDOW, 0, 1)
+        .context("get `_NET_ACTIVE_WINDOW`")?
+        .reply()
+        .context("get `_NET_ACTIVE_WINDOW`")?;
+    let Some(active_window) = active_window.value32().and_then(|mut v| v.next()) else {
+        return Ok(None);
+    };
+    if active_window == x11rb::NONE {
+        return Ok(None);
+    }
+
+    let pid = conn
+        .get_property(false, active_window, net_wm_pid.atom, AtomEnum::CARDINAL, 0, 1)
+        .context("get `_NET_WM_PID`")?
+        .reply()
+        .context("get `_NET_WM_PID`")?;
+    let Some(pid) = pid.value32().and_then(|mut v| v.next()) else {
+        return Ok(None);
+    };
+
+    let pid = Pid::try_from(pid).context("pid is out of range")?;
+    Ok(pid.into())
+}